@@ -76,7 +76,9 @@ async fn main() -> io::Result<()> {
             comments_page_index,
             comments_index,
             new_comments,
-        );
+            3,
+        )
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
         // Write the updated pages to the output file
         let mut output_file = OpenOptions::new()
@@ -93,4 +95,4 @@ async fn main() -> io::Result<()> {
     }
 
     Ok(())
-}
\ No newline at end of file
+}
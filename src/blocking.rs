@@ -0,0 +1,354 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::ogg_packet::OggPacket;
+use crate::ogg_page::OggPage;
+use crate::ogg_page_parser::{OggPageParser, OggSkipReport};
+use crate::ogg_seek::{OggGranuleSeeker, OggSeekResult};
+use crate::ogg_seek_index::{OggSeekIndex, OggSeekIndexBuilder};
+use crate::ogg_validator::{validate_ogg_data, OggValidationReport};
+use crate::read_ogg_vorbis_file::{OggVorbisPacketParser, OggVorbisPageResult};
+use crate::utils::trim_ogg_vorbis_page;
+
+/// The number of bytes requested from the reader at a time.
+const CHUNK_SIZE: usize = 4096;
+
+/// Reads Ogg pages one at a time from a blocking reader.
+///
+/// This is the blocking counterpart of `ogg_page_reader::OggPageReader`.
+pub struct OggPageReader<R> {
+    reader: R,
+    parser: OggPageParser,
+    chunk: Vec<u8>,
+    is_eof: bool,
+}
+
+impl<R: Read> OggPageReader<R> {
+    /// Creates a new `OggPageReader`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - A reader implementing `Read`.
+    /// * `tolerate` - A boolean indicating whether to tolerate errors and continue parsing.
+    pub fn new(reader: R, tolerate: bool) -> Self {
+        Self {
+            reader,
+            parser: OggPageParser::new(tolerate),
+            chunk: vec![0; CHUNK_SIZE],
+            is_eof: false,
+        }
+    }
+
+    /// Reads the next page.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the next `OggPage`, `None` at the end of the stream, or an
+    /// `io::Error` on failure. Once an error is returned, the reader yields no more pages.
+    pub fn next_page(&mut self) -> io::Result<Option<OggPage>> {
+        loop {
+            match self.parser.next_page(self.is_eof) {
+                Ok(Some(page)) => return Ok(Some(page)),
+                Ok(None) if self.is_eof => return Ok(None),
+                Ok(None) => {}
+                Err(error) => {
+                    self.is_eof = true;
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+                }
+            }
+
+            let n = match self.reader.read(&mut self.chunk) {
+                Ok(n) => n,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            if n == 0 {
+                self.is_eof = true;
+            } else {
+                self.parser.push_data(&self.chunk[..n]);
+            }
+        }
+    }
+
+    /// Returns the stream offset of the last page returned by `next_page`.
+    pub fn last_page_offset(&self) -> usize {
+        self.parser.last_page_offset()
+    }
+
+    /// Returns the byte ranges skipped so far while resynchronising.
+    pub fn skip_report(&self) -> &OggSkipReport {
+        self.parser.skip_report()
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for OggPageReader<R> {
+    type Item = io::Result<OggPage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_page().transpose()
+    }
+}
+
+/// Reads the pages of an Ogg Vorbis file one at a time from a blocking reader, parsing their
+/// packets as they arrive.
+///
+/// This is the blocking counterpart of `ogg_page_reader::OggVorbisReader`.
+pub struct OggVorbisReader<R> {
+    page_reader: OggPageReader<R>,
+    parser: OggVorbisPacketParser,
+    pending_packets: VecDeque<OggPacket>,
+}
+
+impl<R: Read> OggVorbisReader<R> {
+    /// Creates a new `OggVorbisReader`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - A reader implementing `Read`.
+    /// * `tolerate` - A boolean indicating whether to tolerate errors and continue parsing.
+    /// * `header_search_range` - The range within which to search for headers.
+    pub fn new(reader: R, tolerate: bool, header_search_range: usize) -> Self {
+        Self {
+            page_reader: OggPageReader::new(reader, tolerate),
+            parser: OggVorbisPacketParser::new(header_search_range),
+            pending_packets: VecDeque::new(),
+        }
+    }
+
+    /// Reads and parses the next page.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the next `OggVorbisPageResult`, `None` at the end of the stream, or
+    /// an `io::Error` on failure.
+    pub fn next_page(&mut self) -> io::Result<Option<OggVorbisPageResult>> {
+        match self.page_reader.next_page()? {
+            Some(page) => self
+                .parser
+                .parse_page(page)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the byte ranges skipped so far while resynchronising.
+    pub fn skip_report(&self) -> &OggSkipReport {
+        self.page_reader.skip_report()
+    }
+
+    /// Reads the next reassembled packet, reading as many pages as needed.
+    ///
+    /// Reading packets and pages from the same reader is not supported, since packets are taken
+    /// from the pages read by this function only.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the next `OggPacket`, `None` at the end of the stream, or an
+    /// `io::Error` on failure.
+    pub fn next_packet(&mut self) -> io::Result<Option<OggPacket>> {
+        while self.pending_packets.is_empty() {
+            match self.next_page()? {
+                Some(result) => self.pending_packets.extend(result.completed_packets),
+                None => return Ok(None),
+            }
+        }
+        Ok(self.pending_packets.pop_front())
+    }
+}
+
+impl<R: Read> Iterator for OggVorbisReader<R> {
+    type Item = io::Result<OggVorbisPageResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_page().transpose()
+    }
+}
+
+/// Reads all Ogg pages of a physical stream, without interpreting their packets.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to a reader implementing `Read`.
+/// * `tolerate` - A boolean indicating whether to tolerate errors and continue parsing.
+///
+/// # Returns
+///
+/// A `Result` containing a vector of `OggPage` on success, or an `io::Error` on failure.
+pub fn read_ogg_pages<R: Read>(reader: &mut R, tolerate: bool) -> io::Result<Vec<OggPage>> {
+    OggPageReader::new(reader, tolerate).collect()
+}
+
+/// Reads an Ogg Vorbis file and parses its pages and packets.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to a reader implementing `Read`.
+/// * `tolerate` - A boolean indicating whether to tolerate errors and continue parsing.
+/// * `header_search_range` - The range within which to search for headers.
+///
+/// # Returns
+///
+/// A `Result` containing a vector of `OggVorbisPageResult` on success, or an `io::Error` on failure.
+pub fn read_ogg_vorbis_file<R: Read>(
+    reader: &mut R,
+    tolerate: bool,
+    header_search_range: usize,
+) -> io::Result<Vec<OggVorbisPageResult>> {
+    OggVorbisReader::new(reader, tolerate, header_search_range).collect()
+}
+
+/// Reads an Ogg Vorbis file and parses its pages and packets, reporting the skipped byte ranges.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to a reader implementing `Read`.
+/// * `tolerate` - A boolean indicating whether to tolerate errors and continue parsing.
+/// * `header_search_range` - The range within which to search for headers.
+///
+/// # Returns
+///
+/// A `Result` containing a vector of `OggVorbisPageResult` and the `OggSkipReport` on success,
+/// or an `io::Error` on failure.
+pub fn read_ogg_vorbis_file_with_report<R: Read>(
+    reader: &mut R,
+    tolerate: bool,
+    header_search_range: usize,
+) -> io::Result<(Vec<OggVorbisPageResult>, OggSkipReport)> {
+    let mut reader = OggVorbisReader::new(reader, tolerate, header_search_range);
+    let mut results = Vec::new();
+
+    while let Some(result) = reader.next_page()? {
+        results.push(result);
+    }
+
+    Ok((results, reader.skip_report().clone()))
+}
+
+/// Trims an Ogg Vorbis file by removing segments before the first header.
+///
+/// # Arguments
+///
+/// * `reader` - A reader implementing `Read`.
+/// * `tolerate` - A boolean indicating whether to tolerate minor errors during reading.
+/// * `header_search_range` - The range in bytes to search for the header.
+///
+/// # Returns
+///
+/// A result containing a vector of `OggVorbisPageResult` if successful, or an `io::Error` if an error occurs.
+pub fn trim_ogg_vorbis_file<R: Read>(
+    reader: R,
+    tolerate: bool,
+    header_search_range: usize,
+) -> io::Result<Vec<OggVorbisPageResult>> {
+    let mut result = Vec::new();
+    let mut header_found = false;
+
+    for page_result in OggVorbisReader::new(reader, tolerate, header_search_range) {
+        if let Some(page_result) = trim_ogg_vorbis_page(page_result?, &mut header_found)? {
+            result.push(page_result);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Collects all pages of an Ogg Vorbis file.
+///
+/// # Arguments
+///
+/// * `reader` - A reader implementing `Read`.
+/// * `tolerate` - A boolean indicating whether to tolerate minor errors during reading.
+/// * `header_search_range` - The range in bytes to search for the header.
+///
+/// # Returns
+///
+/// A result containing a vector of `OggVorbisPageResult` if successful, or an `io::Error` if an error occurs.
+pub fn collect_ogg_vorbis_file<R: Read>(
+    reader: R,
+    tolerate: bool,
+    header_search_range: usize,
+) -> io::Result<Vec<OggVorbisPageResult>> {
+    OggVorbisReader::new(reader, tolerate, header_search_range).collect()
+}
+
+/// Seeks to the page containing a sample of an Ogg Vorbis stream, bisecting on granule positions.
+///
+/// This is the blocking counterpart of `ogg_seek::seek_to_sample`.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to a reader implementing `Read` and `Seek`.
+/// * `target_sample` - The sample to seek to.
+/// * `stream_serial_number` - The serial number of the stream to seek in, or `None` to use the stream of the first page.
+///
+/// # Returns
+///
+/// A `Result` containing the `OggSeekResult`, `None` if the sample lies past the end of the
+/// stream, or an `io::Error` on failure.
+pub fn seek_to_sample<R: Read + Seek>(
+    reader: &mut R,
+    target_sample: u64,
+    stream_serial_number: Option<u32>,
+) -> io::Result<Option<OggSeekResult>> {
+    let length = reader.seek(SeekFrom::End(0))?;
+    let mut seeker = OggGranuleSeeker::new(target_sample, stream_serial_number, length);
+
+    while let Some(offset) = seeker.next_offset() {
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut page_reader = OggPageReader::new(&mut *reader, true);
+
+        loop {
+            let Some(page) = page_reader.next_page()? else {
+                seeker.push_eof();
+                break;
+            };
+            let page_offset = offset + page_reader.last_page_offset() as u64;
+            if !seeker.push_page(page_offset, &page) {
+                break;
+            }
+        }
+    }
+
+    Ok(seeker.into_result())
+}
+
+/// Reads a physical stream in one pass and builds its seek index.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to a reader implementing `Read`.
+///
+/// # Returns
+///
+/// A `Result` containing the `OggSeekIndex` on success, or an `io::Error` on failure.
+pub fn build_ogg_seek_index<R: Read>(reader: &mut R) -> io::Result<OggSeekIndex> {
+    let mut page_reader = OggPageReader::new(reader, true);
+    let mut builder = OggSeekIndexBuilder::new();
+
+    while let Some(page) = page_reader.next_page()? {
+        builder.push_page(page_reader.last_page_offset() as u64, &page);
+    }
+
+    Ok(builder.finish())
+}
+
+/// Reads a physical stream and validates it, as `ogg_validator::validate_ogg_data` does.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to a reader implementing `Read`.
+///
+/// # Returns
+///
+/// A `Result` containing the `OggValidationReport` on success, or an `io::Error` if reading fails.
+pub fn validate_ogg_file<R: Read>(reader: &mut R) -> io::Result<OggValidationReport> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    Ok(validate_ogg_data(&data))
+}
@@ -1,25 +1,94 @@
 pub mod ogg_page;
-pub use ogg_page::{OggFormatError, OggPage};
+pub use ogg_page::{OggFormatError, OggLaceGroup, OggPage};
+
+pub mod ogg_page_ref;
+pub use ogg_page_ref::{OggPageRef, OggPageSegments};
+
+pub mod ogg_packet;
+pub use ogg_packet::{collect_ogg_packets, OggPacket, OggPacketAssembler};
+
+pub mod ogg_stream_writer;
+pub use ogg_stream_writer::OggStreamWriter;
+
+pub mod ogg_demuxer;
+pub use ogg_demuxer::{demux_ogg_file, demux_ogg_pages, OggCodec, OggDemuxer, OggLogicalStream};
 
 pub mod crc;
 pub use crc::vorbis_crc32;
 
 pub mod ogg_vorbis_page;
 pub use ogg_vorbis_page::{
-    float32_unpack, ilog, BitStreamReader, IVorbisCommentHeader, IVorbisFloor, IVorbisFloorType0,
-    IVorbisFloorType1, IVorbisIdentificationHeader, IVorbisMapping, IVorbisMode, IVorbisResidue,
-    IVorbisSetupCodebook, IVorbisSetupHeader, OggVorbisPage, VorbisHeaderType,
-    VorbisSetupCodebookLookupType,
+    float32_pack, float32_unpack, ilog, BitStreamReader, BitStreamWriter, IVorbisCommentHeader,
+    IVorbisFloor, IVorbisFloorType0, IVorbisFloorType1, IVorbisIdentificationHeader,
+    IVorbisMapping, IVorbisMode, IVorbisResidue, IVorbisSetupCodebook, IVorbisSetupHeader,
+    OggVorbisPage, VorbisFormatError, VorbisHeaderType, VorbisSetupCodebookLookupType,
+};
+
+pub mod ogg_page_parser;
+pub use ogg_page_parser::{OggPageParser, OggSkipReport};
+
+pub mod ogg_page_reader;
+pub use ogg_page_reader::{OggPageReader, OggVorbisReader};
+
+pub mod ogg_mmap_reader;
+pub use ogg_mmap_reader::{OggMmapPages, OggMmapReader};
+
+pub mod ogg_seek;
+pub use ogg_seek::{seek_to_sample, OggGranuleSeeker, OggSeekResult};
+
+pub mod ogg_seek_index;
+pub use ogg_seek_index::{
+    build_ogg_seek_index, OggSeekIndex, OggSeekIndexBuilder, OggSeekIndexEntry, OggSeekIndexStream,
 };
 
+pub mod read_ogg_pages;
+pub use read_ogg_pages::read_ogg_pages;
+
 pub mod read_ogg_vorbis_file;
 pub use read_ogg_vorbis_file::{
-    read_ogg_vorbis_file, OggParseResult, OggVorbisPacketsParseResult, OggVorbisPageResult,
-    OggVorbiseHeaderCommentParseResult, OggVorbiseHeaderIdentificationParseResult,
-    OggVorbiseHeaderSetupParseResult,
+    read_ogg_vorbis_file, read_ogg_vorbis_file_with_report, OggParseResult, OggVorbisPacketParser,
+    OggVorbisPacketsParseResult, OggVorbisPageResult, OggVorbiseHeaderCommentParseResult,
+    OggVorbiseHeaderIdentificationParseResult, OggVorbiseHeaderSetupParseResult,
+};
+
+pub mod ogg_vorbis_granule;
+pub use ogg_vorbis_granule::{calculate_ogg_vorbis_granule_positions, OggVorbisGranuleCalculator};
+
+pub mod ogg_vorbis_repaginate;
+pub use ogg_vorbis_repaginate::{
+    repaginate_ogg_vorbis_file, repaginate_ogg_vorbis_stream, OggRepaginateOptions,
+};
+
+pub mod ogg_vorbis_cut;
+pub use ogg_vorbis_cut::{cut_ogg_vorbis_file, cut_ogg_vorbis_stream};
+
+pub mod ogg_vorbis_join;
+pub use ogg_vorbis_join::{join_ogg_vorbis_files, join_ogg_vorbis_streams};
+
+pub mod ogg_vorbis_link;
+pub use ogg_vorbis_link::{find_ogg_vorbis_links, OggVorbisLink};
+
+pub mod ogg_skeleton;
+pub use ogg_skeleton::{
+    add_ogg_skeleton_to_vorbis_file, OggSkeleton, OggSkeletonFisbone, OggSkeletonFishead,
+    OggSkeletonIndex, OggSkeletonKeypoint,
+};
+
+pub mod ogg_validator;
+pub use ogg_validator::{
+    validate_ogg_data, validate_ogg_file, OggValidationFinding, OggValidationReport,
+    OggValidationRule, OggValidationSeverity, OggValidator,
 };
 
 pub mod utils;
 pub use utils::{
-    collect_ogg_vorbis_file, find_packet_by_type, trim_ogg_vorbis_file, update_ogg_vorbis_comments,
+    collect_ogg_vorbis_file, extract_ogg_stream, extract_ogg_vorbis_stream, find_packet_by_type,
+    find_packets_by_type, renumber_ogg_pages, renumber_ogg_vorbis_file, trim_ogg_vorbis_file,
+    trim_ogg_vorbis_stream, update_ogg_vorbis_comments, update_ogg_vorbis_link_comments,
+    OggSequenceChange,
 };
+
+pub mod blocking;
+
+#[cfg(test)]
+mod test_util;
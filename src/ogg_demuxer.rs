@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::io::{self};
+use tokio::io::AsyncReadExt;
+
+use crate::ogg_packet::{OggPacket, OggPacketAssembler};
+use crate::ogg_page::OggPage;
+use crate::read_ogg_pages::read_ogg_pages;
+
+/// Represents the codec of a logical stream, detected from its first packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OggCodec {
+    Vorbis,
+    Theora,
+    Opus,
+    Flac,
+    Speex,
+    Skeleton,
+    Unknown,
+}
+
+impl OggCodec {
+    /// Detects the codec from the first packet of a logical stream.
+    pub fn detect(packet: &[u8]) -> Self {
+        if packet.starts_with(b"\x01vorbis") {
+            OggCodec::Vorbis
+        } else if packet.starts_with(b"\x80theora") {
+            OggCodec::Theora
+        } else if packet.starts_with(b"OpusHead") {
+            OggCodec::Opus
+        } else if packet.starts_with(b"\x7fFLAC") {
+            OggCodec::Flac
+        } else if packet.starts_with(b"Speex   ") {
+            OggCodec::Speex
+        } else if packet.starts_with(b"fishead\0") {
+            OggCodec::Skeleton
+        } else {
+            OggCodec::Unknown
+        }
+    }
+
+    /// Returns the number of header packets announced by the first packet of a logical stream.
+    ///
+    /// Skeleton streams consist of header packets only and return `usize::MAX`.
+    pub fn header_packet_count(&self, packet: &[u8]) -> usize {
+        match self {
+            OggCodec::Vorbis | OggCodec::Theora => 3,
+            OggCodec::Opus => 2,
+            // The FLAC mapping stores the number of following header packets as a big endian u16
+            OggCodec::Flac => match packet.get(7..9) {
+                Some(count) => 1 + u16::from_be_bytes([count[0], count[1]]) as usize,
+                None => 1,
+            },
+            // Speex stores the number of extra headers after the comment as a little endian u32
+            OggCodec::Speex => match packet.get(68..72) {
+                Some(count) => {
+                    2 + u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize
+                }
+                None => 2,
+            },
+            OggCodec::Skeleton => usize::MAX,
+            OggCodec::Unknown => 1,
+        }
+    }
+}
+
+/// Represents one logical stream of a multiplexed Ogg file.
+#[derive(Debug, Clone)]
+pub struct OggLogicalStream {
+    /// The serial number shared by all pages of the stream.
+    pub stream_serial_number: u32,
+    /// The codec detected from the first packet.
+    pub codec: OggCodec,
+    /// The number of header packets the codec expects.
+    pub expected_header_packets: usize,
+    /// The indices of the pages of this stream in the physical stream.
+    pub page_indices: Vec<usize>,
+    /// The pages of this stream.
+    pub pages: Vec<OggPage>,
+    /// The header packets, in stream order.
+    pub header_packets: Vec<OggPacket>,
+    /// The data packets following the headers, in stream order.
+    pub packets: Vec<OggPacket>,
+    /// Whether the first page of the stream carried the BOS flag.
+    pub is_bos_seen: bool,
+    /// Whether a page carrying the EOS flag was seen.
+    pub is_eos_seen: bool,
+}
+
+impl OggLogicalStream {
+    /// Creates an empty logical stream with the given serial number.
+    fn new(stream_serial_number: u32) -> Self {
+        Self {
+            stream_serial_number,
+            codec: OggCodec::Unknown,
+            expected_header_packets: 0,
+            page_indices: Vec::new(),
+            pages: Vec::new(),
+            header_packets: Vec::new(),
+            packets: Vec::new(),
+            is_bos_seen: false,
+            is_eos_seen: false,
+        }
+    }
+
+    /// Checks if all header packets announced by the codec were found.
+    ///
+    /// Skeleton streams only consist of header packets and are complete once their EOS page is seen.
+    pub fn is_headers_complete(&self) -> bool {
+        if self.codec == OggCodec::Skeleton {
+            return self.is_eos_seen;
+        }
+        !self.header_packets.is_empty() && self.header_packets.len() >= self.expected_header_packets
+    }
+
+    /// Files a reassembled packet as a header or a data packet.
+    fn push_packet(&mut self, packet: OggPacket) {
+        if self.header_packets.is_empty() && self.packets.is_empty() {
+            self.codec = OggCodec::detect(&packet.data);
+            self.expected_header_packets = self.codec.header_packet_count(&packet.data);
+        }
+
+        if self.header_packets.len() < self.expected_header_packets && self.packets.is_empty() {
+            self.header_packets.push(packet);
+        } else {
+            self.packets.push(packet);
+        }
+    }
+}
+
+/// Splits the pages of a physical Ogg stream into logical streams by serial number.
+///
+/// Each logical stream keeps its own packet reassembly and header state, so interleaved pages
+/// of different streams never leak into each other. A BOS page for a serial number that already
+/// has pages starts a new logical stream, so chained links reusing a serial number stay apart.
+#[derive(Debug, Default)]
+pub struct OggDemuxer {
+    /// The logical streams, in the order their first page appeared.
+    pub streams: Vec<OggLogicalStream>,
+    /// The packet assembler of each logical stream, by stream index.
+    assemblers: Vec<OggPacketAssembler>,
+    /// The index of the latest logical stream of each serial number.
+    current_streams: HashMap<u32, usize>,
+    page_index: usize,
+    offset: usize,
+}
+
+impl OggDemuxer {
+    /// Creates a new `OggDemuxer`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pushes the next page of the physical stream into the demuxer.
+    ///
+    /// # Arguments
+    ///
+    /// * `page` - The next page of the physical stream.
+    ///
+    /// # Returns
+    ///
+    /// * `usize` - The index of the logical stream the page belongs to.
+    pub fn push_page(&mut self, page: &OggPage) -> usize {
+        let serial = page.stream_serial_number;
+        let stream_index = match self.current_streams.get(&serial) {
+            Some(&index) if !page.is_bos => index,
+            _ => {
+                let mut stream = OggLogicalStream::new(serial);
+                stream.is_bos_seen = page.is_bos;
+                self.streams.push(stream);
+                self.assemblers.push(OggPacketAssembler::default());
+                self.current_streams.insert(serial, self.streams.len() - 1);
+                self.streams.len() - 1
+            }
+        };
+
+        let packets =
+            self.assemblers[stream_index].push_page_at(page, self.page_index, self.offset);
+
+        let stream = &mut self.streams[stream_index];
+        stream.page_indices.push(self.page_index);
+        stream.pages.push(page.clone());
+        stream.is_eos_seen |= page.is_boe;
+        for packet in packets {
+            stream.push_packet(packet);
+        }
+
+        self.page_index += 1;
+        self.offset += page.page_size;
+
+        stream_index
+    }
+
+    /// Finds a logical stream by its serial number.
+    ///
+    /// If chained links reuse the serial number, the latest link is returned.
+    pub fn stream(&self, stream_serial_number: u32) -> Option<&OggLogicalStream> {
+        self.current_streams
+            .get(&stream_serial_number)
+            .map(|&index| &self.streams[index])
+    }
+
+    /// Finishes demuxing, filing the packets still waiting for continuation as incomplete.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<OggLogicalStream>` - The logical streams, in the order their first page appeared.
+    pub fn finish(mut self) -> Vec<OggLogicalStream> {
+        for (stream, assembler) in self.streams.iter_mut().zip(self.assemblers.iter_mut()) {
+            if let Some(packet) = assembler.finish() {
+                stream.push_packet(packet);
+            }
+        }
+
+        self.streams
+    }
+}
+
+/// Splits a slice of Ogg pages into logical streams by serial number.
+///
+/// # Arguments
+///
+/// * `pages` - A slice of `OggPage` in physical stream order.
+///
+/// # Returns
+///
+/// * `Vec<OggLogicalStream>` - The logical streams, in the order their first page appeared.
+pub fn demux_ogg_pages(pages: &[OggPage]) -> Vec<OggLogicalStream> {
+    let mut demuxer = OggDemuxer::new();
+    for page in pages {
+        demuxer.push_page(page);
+    }
+    demuxer.finish()
+}
+
+/// Reads an Ogg file asynchronously and splits it into logical streams by serial number.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to an asynchronous reader implementing `AsyncReadExt`.
+/// * `tolerate` - A boolean indicating whether to tolerate errors and continue parsing.
+///
+/// # Returns
+///
+/// A `Result` containing the logical streams on success, or an `io::Error` on failure.
+pub async fn demux_ogg_file<R: AsyncReadExt + Unpin>(
+    reader: &mut R,
+    tolerate: bool,
+) -> io::Result<Vec<OggLogicalStream>> {
+    let pages = read_ogg_pages(reader, tolerate).await?;
+    Ok(demux_ogg_pages(&pages))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::vorbis_stream;
+
+    #[test]
+    fn chained_links_sharing_a_serial_are_demuxed_apart() {
+        let first_link = vorbis_stream(7, "first", 10, 0);
+        let second_link = vorbis_stream(7, "second", 6, 0);
+        let pages: Vec<OggPage> = first_link.iter().chain(&second_link).cloned().collect();
+
+        let streams = demux_ogg_pages(&pages);
+        assert_eq!(streams.len(), 2);
+
+        for (stream, (link, audio_packet_count)) in
+            streams.iter().zip([(&first_link, 10), (&second_link, 6)])
+        {
+            assert_eq!(stream.stream_serial_number, 7);
+            assert_eq!(stream.codec, OggCodec::Vorbis);
+            assert!(stream.is_bos_seen && stream.is_eos_seen);
+            assert!(stream.is_headers_complete());
+            assert_eq!(stream.header_packets.len(), 3);
+            assert_eq!(stream.packets.len(), audio_packet_count);
+            assert_eq!(stream.pages.len(), link.len());
+        }
+
+        assert_eq!(streams[1].page_indices[0], first_link.len());
+        assert_eq!(
+            streams[1].header_packets[0].first_page_index,
+            first_link.len()
+        );
+        assert!(streams[1].header_packets[1].data.ends_with(b"second\x01"));
+    }
+}
@@ -0,0 +1,258 @@
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{self};
+use std::path::Path;
+
+use crate::ogg_page::OggFormatError;
+use crate::ogg_page_parser::{check_page_candidate, OggPageCandidate, OGG_CAPTURE_PATTERN};
+use crate::ogg_page_ref::OggPageRef;
+
+/// Reads Ogg pages straight from a memory mapped file.
+///
+/// Pages are borrowed from the mapping as `OggPageRef`, so looking at a page only touches the
+/// bytes of that page, and any page offset can be read without going through the pages before it.
+pub struct OggMmapReader {
+    mmap: Mmap,
+}
+
+impl OggMmapReader {
+    /// Memory maps a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<Self>` - The reader, or an error if the file cannot be opened or mapped.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the mapping is read only. Like any memory map, its content is undefined if
+        // another process truncates or rewrites the file while it is mapped.
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(Self { mmap })
+    }
+
+    /// Creates a new `OggMmapReader` from an existing mapping.
+    pub fn from_mmap(mmap: Mmap) -> Self {
+        Self { mmap }
+    }
+
+    /// Returns the mapped bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.mmap
+    }
+
+    /// Returns the size of the mapped file in bytes.
+    pub fn len(&self) -> usize {
+        self.mmap.len()
+    }
+
+    /// Checks if the mapped file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.mmap.is_empty()
+    }
+
+    /// Reads the page starting at a byte offset.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The byte offset of the page.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<OggPageRef, OggFormatError>` - The page, or an error if no valid page starts at the offset.
+    pub fn page_at(&self, offset: usize) -> Result<OggPageRef<'_>, OggFormatError> {
+        let buffer = self.mmap.get(offset..).ok_or_else(|| {
+            OggFormatError(format!(
+                "Offset {} is past the end of the file of {} bytes",
+                offset,
+                self.mmap.len()
+            ))
+        })?;
+        OggPageRef::new(buffer)
+    }
+
+    /// Finds the first valid page starting at or after a byte offset.
+    ///
+    /// A candidate page is only accepted if its structure version is 0 and its checksum is
+    /// correct, so this can be used to resynchronise at an arbitrary offset.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The byte offset to search from.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<(usize, OggPageRef)>` - The offset and the page, or `None` if no page follows.
+    pub fn find_page_from(&self, offset: usize) -> Option<(usize, OggPageRef<'_>)> {
+        find_page_from(&self.mmap, offset)
+    }
+
+    /// Returns an iterator over the pages of the file, with their byte offsets.
+    ///
+    /// # Arguments
+    ///
+    /// * `tolerate` - A boolean indicating whether to skip invalid data instead of failing.
+    pub fn pages(&self, tolerate: bool) -> OggMmapPages<'_> {
+        self.pages_from(0, tolerate)
+    }
+
+    /// Returns an iterator over the pages of the file starting at a byte offset.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The byte offset of the first page.
+    /// * `tolerate` - A boolean indicating whether to skip invalid data instead of failing.
+    pub fn pages_from(&self, offset: usize, tolerate: bool) -> OggMmapPages<'_> {
+        OggMmapPages {
+            data: &self.mmap,
+            position: offset.min(self.mmap.len()),
+            tolerate,
+        }
+    }
+}
+
+/// An iterator over the pages of a memory mapped file, yielding each page with its byte offset.
+///
+/// In tolerant mode invalid data is skipped up to the next valid page, otherwise the iterator
+/// yields an error and stops.
+#[derive(Debug, Clone)]
+pub struct OggMmapPages<'a> {
+    data: &'a [u8],
+    position: usize,
+    tolerate: bool,
+}
+
+impl<'a> Iterator for OggMmapPages<'a> {
+    type Item = Result<(usize, OggPageRef<'a>), OggFormatError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.data.len() {
+            return None;
+        }
+
+        if self.tolerate {
+            let (offset, page) = find_page_from(self.data, self.position).or_else(|| {
+                self.position = self.data.len();
+                None
+            })?;
+            self.position = offset + page.page_size();
+            return Some(Ok((offset, page)));
+        }
+
+        match OggPageRef::new(&self.data[self.position..]) {
+            Ok(page) => {
+                let offset = self.position;
+                self.position += page.page_size();
+                Some(Ok((offset, page)))
+            }
+            Err(error) => {
+                let offset = self.position;
+                self.position = self.data.len();
+                Some(Err(OggFormatError(format!(
+                    "{}, at offset {}",
+                    error.0, offset
+                ))))
+            }
+        }
+    }
+}
+
+/// Finds the first page with a valid header and checksum at or after a byte offset.
+fn find_page_from(data: &[u8], offset: usize) -> Option<(usize, OggPageRef<'_>)> {
+    let mut position = offset;
+
+    while let Some(remaining) = data.get(position..) {
+        let candidate = remaining
+            .windows(OGG_CAPTURE_PATTERN.len())
+            .position(|window| window == OGG_CAPTURE_PATTERN)?;
+        position += candidate;
+
+        if let OggPageCandidate::Page(page) = check_page_candidate(&data[position..]) {
+            return Some((position, page));
+        }
+
+        position += 1;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ogg_page_parser::OggPageParser;
+    use crate::test_util::vorbis_stream;
+    use memmap2::MmapMut;
+
+    fn reader(data: &[u8]) -> OggMmapReader {
+        let mut mmap = MmapMut::map_anon(data.len()).unwrap();
+        mmap.copy_from_slice(data);
+        OggMmapReader::from_mmap(mmap.make_read_only().unwrap())
+    }
+
+    #[test]
+    fn random_access_matches_sequential_parsing() {
+        let pages = vorbis_stream(7, "title", 40, 0);
+        let mut data = Vec::new();
+        for (index, page) in pages.iter().enumerate() {
+            data.extend(&page.buffer);
+            if index == 3 {
+                data.extend(b"OggS garbage");
+            }
+        }
+
+        let mut parser = OggPageParser::new(true);
+        parser.push_data(&data);
+        let mut parsed = Vec::new();
+        while let Some(page) = parser.next_page(true).unwrap() {
+            parsed.push((parser.last_page_offset(), page.buffer));
+        }
+        assert_eq!(parsed.len(), pages.len());
+
+        let reader = reader(&data);
+        let iterated: Vec<(usize, Vec<u8>)> = reader
+            .pages(true)
+            .map(|result| {
+                let (offset, page) = result.unwrap();
+                (offset, page.as_bytes().to_vec())
+            })
+            .collect();
+        assert_eq!(iterated, parsed);
+
+        for (index, (offset, buffer)) in parsed.iter().enumerate() {
+            assert_eq!(reader.page_at(*offset).unwrap().as_bytes(), &buffer[..]);
+
+            let (found_offset, page) = reader.find_page_from(*offset).unwrap();
+            assert_eq!((found_offset, page.as_bytes()), (*offset, &buffer[..]));
+
+            // Searching from inside a page or the garbage finds the following page
+            let next = reader.find_page_from(offset + 1);
+            match parsed.get(index + 1) {
+                Some((next_offset, next_buffer)) => {
+                    let (found_offset, page) = next.unwrap();
+                    assert_eq!(
+                        (found_offset, page.as_bytes()),
+                        (*next_offset, &next_buffer[..])
+                    );
+                }
+                None => assert!(next.is_none()),
+            }
+        }
+
+        let garbage_offset = parsed[3].0 + parsed[3].1.len();
+        assert!(
+            reader.page_at(garbage_offset).is_err()
+                || !reader
+                    .page_at(garbage_offset)
+                    .unwrap()
+                    .if_page_checksum_correct()
+        );
+        assert_eq!(
+            reader.find_page_from(garbage_offset).unwrap().0,
+            parsed[4].0
+        );
+        assert!(reader.pages(false).any(|result| result.is_err()));
+    }
+}
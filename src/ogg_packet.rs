@@ -0,0 +1,179 @@
+use std::ops::Range;
+
+use crate::ogg_page::OggPage;
+
+/// Represents a logical packet reassembled from the lace groups of one or more Ogg pages.
+#[derive(Debug, Clone)]
+pub struct OggPacket {
+    /// The payload of the packet, joined across page boundaries.
+    pub data: Vec<u8>,
+    /// The byte range in the physical stream from the first to the last byte of the packet,
+    /// including the headers of any pages the packet spans.
+    pub byte_range: Range<usize>,
+    /// The index of the page on which the packet starts.
+    pub first_page_index: usize,
+    /// The index of the lace group within the first page.
+    pub first_segment_index: usize,
+    /// The index of the page on which the packet ends.
+    pub last_page_index: usize,
+    /// The index of the lace group within the last page.
+    pub last_segment_index: usize,
+    /// The granule position of the last page, if this is the last packet completed on it.
+    pub granule_position: Option<u64>,
+    /// Whether both the beginning and the end of the packet were found.
+    pub is_complete: bool,
+}
+
+/// Reassembles packets from a sequence of Ogg pages belonging to one logical stream.
+///
+/// Pages are pushed in stream order, lace groups ending with a lacing value of 255 are held
+/// back and joined with the first lace group of the next continuation page.
+#[derive(Debug, Default)]
+pub struct OggPacketAssembler {
+    pending: Option<OggPacket>,
+    pending_has_start: bool,
+    page_index: usize,
+    offset: usize,
+}
+
+impl OggPacketAssembler {
+    /// Creates a new `OggPacketAssembler` starting at page index 0 and byte offset 0.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pushes the next page of the stream into the assembler.
+    ///
+    /// # Arguments
+    ///
+    /// * `page` - The next page of the logical stream.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<OggPacket>` - The packets that ended on this page, plus any pending packet that
+    ///   was abandoned because this page does not continue it.
+    pub fn push_page(&mut self, page: &OggPage) -> Vec<OggPacket> {
+        self.push_page_at(page, self.page_index, self.offset)
+    }
+
+    /// Pushes the next page of the stream, located at the given position of the physical stream.
+    ///
+    /// This is used when the pages of several logical streams are interleaved, so the page
+    /// indices and byte ranges of the packets refer to the physical stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `page` - The next page of the logical stream.
+    /// * `page_index` - The index of the page in the physical stream.
+    /// * `page_offset` - The byte offset of the page in the physical stream.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<OggPacket>` - The packets that ended on this page, plus any pending packet that
+    ///   was abandoned because this page does not continue it.
+    pub fn push_page_at(
+        &mut self,
+        page: &OggPage,
+        page_index: usize,
+        page_offset: usize,
+    ) -> Vec<OggPacket> {
+        let mut packets = Vec::new();
+
+        if page.is_fresh_packet {
+            if let Some(packet) = self.pending.take() {
+                packets.push(packet);
+            }
+        }
+
+        let segment_count = page.parsed_segment_table.len();
+        let mut position = 27 + page.segment_table.len();
+        let mut last_completed_packet = None;
+
+        for (segment_index, &segment_length) in page.parsed_segment_table.iter().enumerate() {
+            let segment = &page.buffer[position..position + segment_length];
+            let new_packet = || OggPacket {
+                data: segment.to_vec(),
+                byte_range: page_offset + position..page_offset + position,
+                first_page_index: page_index,
+                first_segment_index: segment_index,
+                last_page_index: page_index,
+                last_segment_index: segment_index,
+                granule_position: None,
+                is_complete: false,
+            };
+
+            let (mut packet, has_start) = if segment_index == 0 && !page.is_fresh_packet {
+                match self.pending.take() {
+                    Some(mut packet) => {
+                        packet.data.extend_from_slice(segment);
+                        (packet, self.pending_has_start)
+                    }
+                    // The beginning of this packet was never seen
+                    None => (new_packet(), false),
+                }
+            } else {
+                (new_packet(), true)
+            };
+
+            position += segment_length;
+            packet.byte_range.end = page_offset + position;
+            packet.last_page_index = page_index;
+            packet.last_segment_index = segment_index;
+
+            if segment_index + 1 == segment_count && page.is_last_packet_continued {
+                self.pending = Some(packet);
+                self.pending_has_start = has_start;
+            } else {
+                packet.is_complete = has_start;
+                if packet.is_complete {
+                    last_completed_packet = Some(packets.len());
+                }
+                packets.push(packet);
+            }
+        }
+
+        // Only the last packet completed on a page carries the granule position of the page
+        if let Some(index) = last_completed_packet {
+            if page.absolute_granule_position != u64::MAX {
+                packets[index].granule_position = Some(page.absolute_granule_position);
+            }
+        }
+
+        self.page_index = page_index + 1;
+        self.offset = page_offset + page.page_size;
+
+        packets
+    }
+
+    /// Finishes the stream and returns the packet still waiting for continuation, if any.
+    ///
+    /// The returned packet is always marked as incomplete.
+    pub fn finish(&mut self) -> Option<OggPacket> {
+        self.pending.take()
+    }
+}
+
+/// Reassembles all packets from a slice of Ogg pages belonging to one logical stream.
+///
+/// # Arguments
+///
+/// * `pages` - A slice of `OggPage` in stream order.
+///
+/// # Returns
+///
+/// * `Vec<OggPacket>` - The packets in stream order, including a trailing incomplete packet if the
+///   last page ends with a continued lace group.
+pub fn collect_ogg_packets(pages: &[OggPage]) -> Vec<OggPacket> {
+    let mut assembler = OggPacketAssembler::new();
+    let mut packets = Vec::new();
+
+    for page in pages {
+        packets.extend(assembler.push_page(page));
+    }
+
+    if let Some(packet) = assembler.finish() {
+        packets.push(packet);
+    }
+
+    packets
+}
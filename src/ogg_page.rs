@@ -4,6 +4,7 @@ use std::error::Error;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::crc;
+use crate::ogg_page_ref::OggPageRef;
 use crc::vorbis_crc32;
 
 
@@ -12,7 +13,7 @@ const FOUR_ZERO_BYTES: [u8; 4] = [0, 0, 0, 0];
 
 /// Error type representing issues with Ogg format.
 #[derive(Debug)]
-pub struct OggFormatError(String);
+pub struct OggFormatError(pub(crate) String);
 
 impl fmt::Display for OggFormatError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -39,9 +40,29 @@ pub struct OggPage {
     pub page_segments: usize,
     pub segment_table: Vec<u8>,
     pub parsed_segment_table: Vec<usize>,
+    pub is_last_packet_continued: bool,
     pub page_size: usize,
 }
 
+/// Describes one lace group of a page, that is a packet or the part of a packet held by the page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OggLaceGroup {
+    /// The number of bytes of the group, zero for an empty packet or for the terminating lacing
+    /// value of a packet continued from the previous page.
+    pub length: usize,
+    /// Whether the group continues a packet started on a previous page.
+    pub is_continuation: bool,
+    /// Whether the packet continues on the next page.
+    pub is_continued: bool,
+}
+
+impl OggLaceGroup {
+    /// Checks if the group is a complete packet without any data.
+    pub fn is_empty_packet(&self) -> bool {
+        self.length == 0 && !self.is_continuation && !self.is_continued
+    }
+}
+
 impl OggPage {
     /// Creates a new `OggPage` from a buffer.
     ///
@@ -52,8 +73,14 @@ impl OggPage {
     /// # Returns
     ///
     /// * `Result<Self, OggFormatError>` - An instance of `OggPage` or an error if the buffer is invalid.
-    pub fn new(buffer: Vec<u8>) -> Result<Self, OggFormatError> {
-        let (page_size, page_segments_count) = Self::validate_page(&buffer)?;
+    pub fn new(mut buffer: Vec<u8>) -> Result<Self, OggFormatError> {
+        let page_ref = OggPageRef::new(&buffer)?;
+        let page_size = page_ref.page_size();
+        let segment_table = page_ref.segment_table().to_vec();
+        let parsed_segment_table: Vec<usize> =
+            page_ref.segments().map(|segment| segment.len()).collect();
+        // A lace group ending with 255 is not terminated, the packet continues on the next page
+        let is_last_packet_continued = page_ref.is_last_packet_continued();
 
         let mut cursor = Cursor::new(&buffer);
         cursor.set_position(4);
@@ -67,31 +94,13 @@ impl OggPage {
         let stream_serial_number = cursor.read_u32::<LittleEndian>().unwrap();
         let page_sequence_number = cursor.read_u32::<LittleEndian>().unwrap();
         let page_checksum = cursor.read_u32::<LittleEndian>().unwrap();
-        let segment_table = buffer[27..27 + page_segments_count].to_vec();
-
-        let mut parsed_segment_table = Vec::new();
-        let mut accumulated_size = 0;
-        for &segment_size in &segment_table {
-            if segment_size == 255 {
-                accumulated_size += segment_size as usize;
-                continue;
-            }
-            if accumulated_size > 0 {
-                accumulated_size += segment_size as usize;
-                parsed_segment_table.push(accumulated_size);
-                accumulated_size = 0;
-                continue;
-            }
-            if segment_size != 0 {
-                parsed_segment_table.push(segment_size as usize);
-            }
-        }
 
         let page_segments = parsed_segment_table.len();
+        buffer.truncate(page_size);
 
         Ok(Self {
             ready: false,
-            buffer: buffer[..page_size].to_vec(),
+            buffer,
             structure_version,
             header_type,
             is_fresh_packet,
@@ -104,10 +113,66 @@ impl OggPage {
             page_segments,
             segment_table,
             parsed_segment_table,
+            is_last_packet_continued,
             page_size,
         })
     }
 
+    /// Builds a new `OggPage` from its header fields and contents, and computes its checksum.
+    ///
+    /// # Arguments
+    ///
+    /// * `header_type` - The header type flags of the page.
+    /// * `absolute_granule_position` - The granule position of the page.
+    /// * `stream_serial_number` - The serial number of the logical stream.
+    /// * `page_sequence_number` - The sequence number of the page.
+    /// * `segment_table` - The lacing values of the page.
+    /// * `body` - The page body, its length must match the sum of the lacing values.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, OggFormatError>` - An instance of `OggPage` or an error if the contents are invalid.
+    pub fn build(
+        header_type: u8,
+        absolute_granule_position: u64,
+        stream_serial_number: u32,
+        page_sequence_number: u32,
+        segment_table: &[u8],
+        body: &[u8],
+    ) -> Result<Self, OggFormatError> {
+        if segment_table.len() > 255 {
+            return Err(OggFormatError(format!(
+                "Too many lacing values for a single page: {}",
+                segment_table.len()
+            )));
+        }
+
+        let page_body_size: usize = segment_table.iter().map(|&x| x as usize).sum();
+        if page_body_size != body.len() {
+            return Err(OggFormatError(format!(
+                "Segment table describes {} bytes but the body has {} bytes",
+                page_body_size,
+                body.len()
+            )));
+        }
+
+        let mut buffer = Vec::with_capacity(27 + segment_table.len() + body.len());
+        buffer.extend_from_slice(&OGG_MAGIC_SIGNATURE);
+        buffer.push(0); // Structure version
+        buffer.push(header_type);
+        buffer.extend_from_slice(&absolute_granule_position.to_le_bytes());
+        buffer.extend_from_slice(&stream_serial_number.to_le_bytes());
+        buffer.extend_from_slice(&page_sequence_number.to_le_bytes());
+        buffer.extend_from_slice(&FOUR_ZERO_BYTES);
+        buffer.push(segment_table.len() as u8);
+        buffer.extend_from_slice(segment_table);
+        buffer.extend_from_slice(body);
+
+        let mut page = Self::new(buffer)?;
+        page.update_page_checksum();
+        Ok(page)
+    }
+
     /// Validates the Ogg page from the buffer.
     ///
     /// # Arguments
@@ -117,7 +182,7 @@ impl OggPage {
     /// # Returns
     ///
     /// * `Result<(usize, usize), OggFormatError>` - A tuple containing the page size and segment count or an error if the buffer is invalid.
-    fn validate_page(buffer: &[u8]) -> Result<(usize, usize), OggFormatError> {
+    pub(crate) fn validate_page(buffer: &[u8]) -> Result<(usize, usize), OggFormatError> {
         if buffer.len() < 27 {
             return Err(OggFormatError("Incomplete buffer length".to_string()));
         }
@@ -214,18 +279,31 @@ impl OggPage {
     where
         F: Fn(&[u8], usize) -> T,
     {
-        let parsed_segment_length = self.parsed_segment_table.len();
-        let mut result = Vec::with_capacity(parsed_segment_length);
+        let mut position = 27 + self.segment_table.len();
+        let mut result = Vec::with_capacity(self.parsed_segment_table.len());
 
-        for i in 0..parsed_segment_length {
-            result.push(callback(&self.get_page_segment(i).unwrap(), i));
+        for (i, &segment_length) in self.parsed_segment_table.iter().enumerate() {
+            result.push(callback(&self.buffer[position..position + segment_length], i));
+            position += segment_length;
         }
 
         result
     }
 
+    /// Borrows the page as an `OggPageRef`, to read its segments without copying them.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<OggPageRef, OggFormatError>` - The borrowed page or an error if the buffer is invalid.
+    pub fn as_page_ref(&self) -> Result<OggPageRef<'_>, OggFormatError> {
+        OggPageRef::new(&self.buffer)
+    }
+
     /// Creates a laced vector from a slice of segment lengths.
     ///
+    /// Every length is encoded as a run of 255 followed by a terminating value below 255, so a
+    /// zero-length packet becomes a single 0 and a multiple of 255 ends with a 0.
+    ///
     /// # Arguments
     ///
     /// * `input` - A slice of segment lengths.
@@ -235,7 +313,7 @@ impl OggPage {
     /// * `Vec<u8>` - A vector of bytes representing the laced segments.
     pub fn create_laced_vec(input: &[usize]) -> Vec<u8> {
         let mut result = Vec::new();
-    
+
         for mut value in input.iter().cloned() {
             while value >= 255 {
                 result.push(255);
@@ -243,14 +321,80 @@ impl OggPage {
             }
             result.push(value as u8);
         }
-    
-        if *result.last().unwrap() == 255 {
-            result.push(0);
-        }
-    
+
         result
     }
 
+    /// Creates a laced vector for this page from a slice of segment lengths.
+    ///
+    /// If `keep_continuation` is set, the last lace group is left unterminated so the
+    /// packet keeps continuing on the next page.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - A slice of segment lengths.
+    /// * `keep_continuation` - Whether the last segment continues on the next page.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<u8>, OggFormatError>` - A vector of bytes representing the laced segments or an error if the last segment cannot be continued.
+    fn create_page_laced_vec(
+        input: &[usize],
+        keep_continuation: bool,
+    ) -> Result<Vec<u8>, OggFormatError> {
+        let mut result = Self::create_laced_vec(input);
+
+        if keep_continuation {
+            let last_segment_length = *input.last().unwrap();
+            if last_segment_length == 0 || last_segment_length % 255 != 0 {
+                return Err(OggFormatError(format!(
+                    "The last segment continues on the next page, its length must be a non-zero multiple of 255, got {}",
+                    last_segment_length
+                )));
+            }
+            result.pop();
+        }
+
+        if result.len() > 255 {
+            return Err(OggFormatError(format!(
+                "Too many lacing values for a single page: {}",
+                result.len()
+            )));
+        }
+
+        Ok(result)
+    }
+
+    /// Describes the lace groups of the page, one for each entry of `parsed_segment_table`.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<OggLaceGroup>` - The lace groups, in page order.
+    pub fn lace_groups(&self) -> Vec<OggLaceGroup> {
+        let segment_count = self.parsed_segment_table.len();
+
+        self.parsed_segment_table
+            .iter()
+            .enumerate()
+            .map(|(index, &length)| OggLaceGroup {
+                length,
+                is_continuation: index == 0 && !self.is_fresh_packet,
+                is_continued: index + 1 == segment_count && self.is_last_packet_continued,
+            })
+            .collect()
+    }
+
+    /// Re-encodes the segment table from `parsed_segment_table`.
+    ///
+    /// The lacing model is exact, so for a page read by `new` this returns its original segment table.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<u8>, OggFormatError>` - The lacing values or an error if they cannot be encoded.
+    pub fn build_segment_table(&self) -> Result<Vec<u8>, OggFormatError> {
+        Self::create_page_laced_vec(&self.parsed_segment_table, self.is_last_packet_continued)
+    }
+
     /// Removes a segment from the Ogg page and returns the raw result.
     ///
     /// # Arguments
@@ -293,7 +437,10 @@ impl OggPage {
         let mut new_parsed_segment_table = self.parsed_segment_table.clone();
         new_parsed_segment_table.drain(index..index + n);
 
-        let new_segment_table = Self::create_laced_vec(&new_parsed_segment_table);
+        let keep_continuation =
+            self.is_last_packet_continued && index + n < self.parsed_segment_table.len();
+        let new_segment_table =
+            Self::create_page_laced_vec(&new_parsed_segment_table, keep_continuation)?;
         let new_segments = new_segment_table.len();
 
         let new_buffer_size = self.buffer.len() - total_remove_length - self.segment_table.len()
@@ -355,7 +502,10 @@ impl OggPage {
         new_parsed_segment_table.extend_from_slice(&new_segments_lengths);
         new_parsed_segment_table.extend_from_slice(&self.parsed_segment_table[index..]);
 
-        let new_segment_table = Self::create_laced_vec(&new_parsed_segment_table);
+        let keep_continuation =
+            self.is_last_packet_continued && index < self.parsed_segment_table.len();
+        let new_segment_table =
+            Self::create_page_laced_vec(&new_parsed_segment_table, keep_continuation)?;
         let new_segments = new_segment_table.len();
 
         let total_new_length: usize = new_segments_lengths.iter().sum();
@@ -422,7 +572,8 @@ impl OggPage {
         let mut new_parsed_segment_table = self.parsed_segment_table.clone();
         new_parsed_segment_table[index] = new_segment_length;
 
-        let new_segment_table = Self::create_laced_vec(&new_parsed_segment_table);
+        let new_segment_table =
+            Self::create_page_laced_vec(&new_parsed_segment_table, self.is_last_packet_continued)?;
         let new_segments = new_segment_table.len();
 
         let new_buffer_size =
@@ -524,6 +675,87 @@ impl OggPage {
         Ok(new_page)
     }
 
+    /// Sets the granule position, updating the buffer and the checksum.
+    ///
+    /// # Arguments
+    ///
+    /// * `absolute_granule_position` - The new granule position, `u64::MAX` if no packet ends on the page.
+    pub fn set_absolute_granule_position(&mut self, absolute_granule_position: u64) {
+        let mut cursor = Cursor::new(&mut self.buffer[6..14]);
+        cursor
+            .write_u64::<LittleEndian>(absolute_granule_position)
+            .unwrap();
+        self.absolute_granule_position = absolute_granule_position;
+        self.update_page_checksum();
+    }
+
+    /// Sets the serial number of the logical stream, updating the buffer and the checksum.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_serial_number` - The new serial number.
+    pub fn set_stream_serial_number(&mut self, stream_serial_number: u32) {
+        let mut cursor = Cursor::new(&mut self.buffer[14..18]);
+        cursor
+            .write_u32::<LittleEndian>(stream_serial_number)
+            .unwrap();
+        self.stream_serial_number = stream_serial_number;
+        self.update_page_checksum();
+    }
+
+    /// Sets the sequence number of the page, updating the buffer and the checksum.
+    ///
+    /// # Arguments
+    ///
+    /// * `page_sequence_number` - The new sequence number.
+    pub fn set_page_sequence_number(&mut self, page_sequence_number: u32) {
+        let mut cursor = Cursor::new(&mut self.buffer[18..22]);
+        cursor
+            .write_u32::<LittleEndian>(page_sequence_number)
+            .unwrap();
+        self.page_sequence_number = page_sequence_number;
+        self.update_page_checksum();
+    }
+
+    /// Sets the header type flags, updating the derived flags, the buffer and the checksum.
+    ///
+    /// # Arguments
+    ///
+    /// * `header_type` - The new header type flags.
+    pub fn set_header_type(&mut self, header_type: u8) {
+        self.buffer[5] = header_type;
+        self.header_type = header_type;
+        self.is_fresh_packet = (header_type & 0x1) == 0;
+        self.is_bos = (header_type & 0x2) != 0;
+        self.is_boe = (header_type & 0x4) != 0;
+        self.update_page_checksum();
+    }
+
+    /// Sets whether the page starts with a fresh packet rather than continuing one.
+    pub fn set_is_fresh_packet(&mut self, is_fresh_packet: bool) {
+        self.set_header_flag(0x1, !is_fresh_packet);
+    }
+
+    /// Sets whether the page is the first page of its logical stream.
+    pub fn set_is_bos(&mut self, is_bos: bool) {
+        self.set_header_flag(0x2, is_bos);
+    }
+
+    /// Sets whether the page is the last page of its logical stream.
+    pub fn set_is_boe(&mut self, is_boe: bool) {
+        self.set_header_flag(0x4, is_boe);
+    }
+
+    /// Sets or clears a flag of the header type.
+    fn set_header_flag(&mut self, flag: u8, value: bool) {
+        let header_type = if value {
+            self.header_type | flag
+        } else {
+            self.header_type & !flag
+        };
+        self.set_header_type(header_type);
+    }
+
     /// Calculates the checksum of the Ogg page.
     ///
     /// # Returns
@@ -567,3 +799,119 @@ impl OggPage {
         self.calculate_page_checksum() == self.page_checksum
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_laced_vec_terminates_every_packet() {
+        assert_eq!(OggPage::create_laced_vec(&[0]), [0]);
+        assert_eq!(OggPage::create_laced_vec(&[255]), [255, 0]);
+        assert_eq!(OggPage::create_laced_vec(&[510]), [255, 255, 0]);
+        assert_eq!(
+            OggPage::create_laced_vec(&[0, 255, 510, 17]),
+            [0, 255, 0, 255, 255, 0, 17]
+        );
+    }
+
+    #[test]
+    fn create_page_laced_vec_leaves_continued_packets_open() {
+        assert_eq!(
+            OggPage::create_page_laced_vec(&[100, 510], true).unwrap(),
+            [100, 255, 255]
+        );
+        assert_eq!(
+            OggPage::create_page_laced_vec(&[100, 510], false).unwrap(),
+            [100, 255, 255, 0]
+        );
+        assert!(OggPage::create_page_laced_vec(&[100, 300], true).is_err());
+        assert!(OggPage::create_page_laced_vec(&[0], true).is_err());
+    }
+
+    #[test]
+    fn create_page_laced_vec_rejects_more_than_255_lacing_values() {
+        assert_eq!(
+            OggPage::create_page_laced_vec(&[255 * 254], false)
+                .unwrap()
+                .len(),
+            255
+        );
+        assert_eq!(
+            OggPage::create_page_laced_vec(&[255 * 255], true)
+                .unwrap()
+                .len(),
+            255
+        );
+        assert!(OggPage::create_page_laced_vec(&[255 * 255], false).is_err());
+        assert!(OggPage::create_page_laced_vec(&[1; 256], false).is_err());
+    }
+
+    #[test]
+    fn build_segment_table_returns_the_original_lacing_values() {
+        // A page ending with a packet that continues on the next page
+        let segment_table = [0, 255, 0, 255, 255, 0, 255];
+        let body = vec![0; 255 * 4];
+        let page = OggPage::build(0, 0, 1, 0, &segment_table, &body).unwrap();
+        assert_eq!(page.parsed_segment_table, [0, 255, 510, 255]);
+        assert!(page.is_last_packet_continued);
+        assert_eq!(page.build_segment_table().unwrap(), segment_table);
+    }
+
+    #[test]
+    fn setters_keep_the_checksum_correct() {
+        let page = OggPage::build(0x1, 100, 7, 3, &[10, 255, 0], &[1; 265]).unwrap();
+        let setters: [fn(&mut OggPage); 7] = [
+            |page| page.set_absolute_granule_position(u64::MAX),
+            |page| page.set_stream_serial_number(0x12345678),
+            |page| page.set_page_sequence_number(42),
+            |page| page.set_header_type(0x6),
+            |page| page.set_is_fresh_packet(true),
+            |page| page.set_is_bos(true),
+            |page| page.set_is_boe(true),
+        ];
+
+        for setter in setters {
+            let mut edited = page.clone();
+            setter(&mut edited);
+            assert!(edited.if_page_checksum_correct());
+
+            let parsed = OggPage::new(edited.buffer.clone()).unwrap();
+            assert!(parsed.if_page_checksum_correct());
+            assert_eq!(parsed.page_checksum, edited.page_checksum);
+            assert_eq!(parsed.header_type, edited.header_type);
+            assert_eq!(parsed.is_fresh_packet, edited.is_fresh_packet);
+            assert_eq!(parsed.is_bos, edited.is_bos);
+            assert_eq!(parsed.is_boe, edited.is_boe);
+            assert_eq!(
+                parsed.absolute_granule_position,
+                edited.absolute_granule_position
+            );
+            assert_eq!(parsed.stream_serial_number, edited.stream_serial_number);
+            assert_eq!(parsed.page_sequence_number, edited.page_sequence_number);
+        }
+
+        let mut edited = page.clone();
+        edited.set_absolute_granule_position(u64::MAX);
+        edited.set_stream_serial_number(0x12345678);
+        edited.set_page_sequence_number(42);
+        edited.set_is_fresh_packet(true);
+        edited.set_is_bos(true);
+        edited.set_is_boe(true);
+        let parsed = OggPage::new(edited.buffer).unwrap();
+        assert!(parsed.if_page_checksum_correct());
+        assert_eq!(parsed.absolute_granule_position, u64::MAX);
+        assert_eq!(parsed.stream_serial_number, 0x12345678);
+        assert_eq!(parsed.page_sequence_number, 42);
+        assert_eq!(parsed.header_type, 0x6);
+        assert!(parsed.is_fresh_packet && parsed.is_bos && parsed.is_boe);
+        assert_eq!(parsed.segment_table, page.segment_table);
+
+        edited = parsed;
+        edited.set_is_bos(false);
+        edited.set_is_fresh_packet(false);
+        assert_eq!(edited.header_type, 0x5);
+        assert_eq!(edited.buffer[5], 0x5);
+        assert!(edited.if_page_checksum_correct());
+    }
+}
@@ -0,0 +1,297 @@
+use std::ops::Range;
+
+use crate::ogg_page::{OggFormatError, OggPage};
+use crate::ogg_page_ref::OggPageRef;
+
+pub(crate) const OGG_CAPTURE_PATTERN: &[u8; 4] = b"OggS";
+
+/// Records the byte ranges skipped while resynchronising on a damaged stream.
+#[derive(Debug, Clone, Default)]
+pub struct OggSkipReport {
+    /// The skipped byte ranges of the stream, in stream order. Adjacent ranges are merged.
+    pub skipped_ranges: Vec<Range<usize>>,
+}
+
+impl OggSkipReport {
+    /// Returns the total number of skipped bytes.
+    pub fn skipped_bytes(&self) -> usize {
+        self.skipped_ranges.iter().map(|range| range.len()).sum()
+    }
+
+    /// Checks if no byte was skipped.
+    pub fn is_clean(&self) -> bool {
+        self.skipped_ranges.is_empty()
+    }
+
+    /// Records a skipped byte range, merging it with the previous one if they are adjacent.
+    fn push(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        match self.skipped_ranges.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => self.skipped_ranges.push(range),
+        }
+    }
+}
+
+/// The state of a candidate page at the start of the buffer.
+pub(crate) enum OggPageCandidate<P> {
+    /// A valid page.
+    Page(P),
+    /// More data is needed to decide.
+    Incomplete,
+    /// The capture pattern does not start a valid page.
+    Invalid,
+}
+
+/// Incrementally splits a byte stream into Ogg pages without performing any I/O.
+///
+/// Data is pushed as it arrives and every page is drained from the buffer once parsed, so only
+/// the bytes not yet consumed by a page are kept in memory.
+///
+/// In tolerant mode the parser resynchronises on damaged data: it scans for the `OggS` capture
+/// pattern, only accepts a candidate page once its header and checksum are valid, and records
+/// every skipped byte range in a `OggSkipReport`. Otherwise any invalid data is an error.
+#[derive(Debug, Default)]
+pub struct OggPageParser {
+    /// Whether to skip invalid data instead of failing.
+    pub tolerate: bool,
+    buffer: Vec<u8>,
+    offset: usize,
+    last_page_offset: usize,
+    skip_report: OggSkipReport,
+}
+
+impl OggPageParser {
+    /// Creates a new `OggPageParser`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tolerate` - A boolean indicating whether to tolerate errors and continue parsing.
+    pub fn new(tolerate: bool) -> Self {
+        Self {
+            tolerate,
+            ..Self::default()
+        }
+    }
+
+    /// Appends data read from the stream.
+    pub fn push_data(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Returns the number of buffered bytes not yet consumed by a page.
+    pub fn buffered_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Returns the stream offset of the first buffered byte.
+    pub fn position(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the stream offset of the last page returned by `next_page`.
+    pub fn last_page_offset(&self) -> usize {
+        self.last_page_offset
+    }
+
+    /// Returns the byte ranges skipped so far.
+    pub fn skip_report(&self) -> &OggSkipReport {
+        &self.skip_report
+    }
+
+    /// Takes the next page out of the buffered data.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_eof` - Whether the end of the stream was reached, so no more data will be pushed.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<OggPage>, OggFormatError>` - The next page, `None` if more data is needed
+    ///   or the stream is exhausted, or an error if the data is invalid and errors are not tolerated.
+    pub fn next_page(&mut self, is_eof: bool) -> Result<Option<OggPage>, OggFormatError> {
+        if self.tolerate {
+            self.next_page_tolerant(is_eof)
+        } else {
+            self.next_page_strict(is_eof)
+        }
+    }
+
+    /// Takes the next page, failing as soon as the buffered data cannot start a page.
+    fn next_page_strict(&mut self, is_eof: bool) -> Result<Option<OggPage>, OggFormatError> {
+        if self.buffer.len() < OGG_CAPTURE_PATTERN.len() && is_eof {
+            // Trailing bytes too short to hold a capture pattern are ignored
+            self.skip(self.buffer.len());
+            return Ok(None);
+        }
+
+        match OggPage::validate_page(&self.buffer) {
+            Ok((page_size, _)) => {
+                let page = OggPage::new(self.buffer[..page_size].to_vec())?;
+                self.consume_page(page_size);
+                Ok(Some(page))
+            }
+            Err(error) => {
+                let is_garbage = self.buffer.len() >= OGG_CAPTURE_PATTERN.len()
+                    && !self.buffer.starts_with(OGG_CAPTURE_PATTERN);
+                if is_garbage || is_eof {
+                    self.buffer.clear();
+                    return Err(OggFormatError(format!(
+                        "{}, at offset {}",
+                        error.0, self.offset
+                    )));
+                }
+                // Wait for more data
+                Ok(None)
+            }
+        }
+    }
+
+    /// Takes the next page, skipping data until a valid page is found.
+    fn next_page_tolerant(&mut self, is_eof: bool) -> Result<Option<OggPage>, OggFormatError> {
+        loop {
+            let Some(position) = self
+                .buffer
+                .windows(OGG_CAPTURE_PATTERN.len())
+                .position(|window| window == OGG_CAPTURE_PATTERN)
+            else {
+                // Keep a possibly truncated capture pattern at the end of the buffer
+                let kept = if is_eof {
+                    0
+                } else {
+                    self.buffer.len().min(OGG_CAPTURE_PATTERN.len() - 1)
+                };
+                self.skip(self.buffer.len() - kept);
+                return Ok(None);
+            };
+
+            self.skip(position);
+
+            match self.check_candidate(is_eof)? {
+                OggPageCandidate::Page(page) => {
+                    self.consume_page(page.page_size);
+                    return Ok(Some(page));
+                }
+                OggPageCandidate::Incomplete => return Ok(None),
+                // Skip the capture pattern and search for the next one
+                OggPageCandidate::Invalid => self.skip(1),
+            }
+        }
+    }
+
+    /// Checks if the capture pattern at the start of the buffer starts a valid page.
+    fn check_candidate(&self, is_eof: bool) -> Result<OggPageCandidate<OggPage>, OggFormatError> {
+        Ok(match check_page_candidate(&self.buffer) {
+            // Only copy the page out of the buffer once its checksum is known to be valid
+            OggPageCandidate::Page(page_ref) => OggPageCandidate::Page(page_ref.to_owned_page()),
+            OggPageCandidate::Incomplete if !is_eof => OggPageCandidate::Incomplete,
+            OggPageCandidate::Incomplete | OggPageCandidate::Invalid => OggPageCandidate::Invalid,
+        })
+    }
+
+    /// Drops a page from the start of the buffer.
+    fn consume_page(&mut self, page_size: usize) {
+        self.buffer.drain(..page_size);
+        self.last_page_offset = self.offset;
+        self.offset += page_size;
+    }
+
+    /// Drops bytes from the start of the buffer and records them as skipped.
+    fn skip(&mut self, length: usize) {
+        self.buffer.drain(..length);
+        self.skip_report.push(self.offset..self.offset + length);
+        self.offset += length;
+    }
+}
+
+/// Checks if the capture pattern at the start of a slice starts a valid page.
+///
+/// # Arguments
+///
+/// * `data` - A slice of bytes starting with the capture pattern.
+///
+/// # Returns
+///
+/// * `OggPageCandidate<OggPageRef>` - The page borrowed from `data` if its header and checksum are
+///   valid, `Incomplete` if the page runs past the end of `data`, or `Invalid` otherwise.
+pub(crate) fn check_page_candidate(data: &[u8]) -> OggPageCandidate<OggPageRef<'_>> {
+    if data.len() < 27 {
+        return OggPageCandidate::Incomplete;
+    }
+
+    // Only structure version 0 is defined
+    if data[4] != 0 {
+        return OggPageCandidate::Invalid;
+    }
+
+    match OggPageRef::new(data) {
+        Ok(page_ref) if page_ref.if_page_checksum_correct() => OggPageCandidate::Page(page_ref),
+        Ok(_) => OggPageCandidate::Invalid,
+        Err(_) => OggPageCandidate::Incomplete,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::vorbis_stream;
+
+    #[test]
+    fn tolerant_parser_resynchronises_over_garbage() {
+        let pages = vorbis_stream(7, "title", 12, 0);
+        let mut false_page = OGG_CAPTURE_PATTERN.to_vec();
+        false_page.extend([0; 30]);
+
+        let mut data = Vec::new();
+        let mut expected_ranges = Vec::new();
+        for (index, page) in pages.iter().enumerate() {
+            data.extend(&page.buffer);
+            let garbage: &[u8] = match index {
+                0 => b"junk data",
+                2 => &false_page,
+                _ if index + 1 == pages.len() => b"tail",
+                _ => continue,
+            };
+            expected_ranges.push(data.len()..data.len() + garbage.len());
+            data.extend(garbage);
+        }
+
+        let mut parser = OggPageParser::new(true);
+        let mut parsed = Vec::new();
+        for chunk in data.chunks(7) {
+            parser.push_data(chunk);
+            while let Some(page) = parser.next_page(false).unwrap() {
+                parsed.push((parser.last_page_offset(), page));
+            }
+        }
+        while let Some(page) = parser.next_page(true).unwrap() {
+            parsed.push((parser.last_page_offset(), page));
+        }
+
+        assert_eq!(parsed.len(), pages.len());
+        for ((offset, page), expected) in parsed.iter().zip(&pages) {
+            assert_eq!(page.buffer, expected.buffer);
+            assert_eq!(&data[*offset..*offset + page.page_size], &page.buffer[..]);
+        }
+        assert_eq!(parser.skip_report().skipped_ranges, expected_ranges);
+        assert_eq!(
+            parser.skip_report().skipped_bytes(),
+            9 + false_page.len() + 4
+        );
+        assert_eq!(parser.position(), data.len());
+    }
+
+    #[test]
+    fn strict_parser_fails_on_garbage() {
+        let pages = vorbis_stream(7, "title", 12, 0);
+        let mut data = pages[0].buffer.clone();
+        data.extend(b"junk data");
+
+        let mut parser = OggPageParser::new(false);
+        parser.push_data(&data);
+        assert!(parser.next_page(false).unwrap().is_some());
+        assert!(parser.next_page(false).is_err());
+    }
+}
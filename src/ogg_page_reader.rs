@@ -0,0 +1,181 @@
+use futures_util::stream::{self, Stream};
+use std::collections::VecDeque;
+use std::io::{self};
+use tokio::io::AsyncReadExt;
+
+use crate::ogg_packet::OggPacket;
+use crate::ogg_page::OggPage;
+use crate::ogg_page_parser::{OggPageParser, OggSkipReport};
+use crate::read_ogg_vorbis_file::{OggVorbisPacketParser, OggVorbisPageResult};
+
+/// The number of bytes requested from the reader at a time.
+const CHUNK_SIZE: usize = 4096;
+
+/// Reads Ogg pages one at a time from an asynchronous reader.
+///
+/// Only the page being parsed is buffered, so arbitrarily large files can be processed with
+/// bounded memory.
+pub struct OggPageReader<R> {
+    reader: R,
+    parser: OggPageParser,
+    chunk: Vec<u8>,
+    is_eof: bool,
+}
+
+impl<R: AsyncReadExt + Unpin> OggPageReader<R> {
+    /// Creates a new `OggPageReader`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - An asynchronous reader implementing `AsyncReadExt`.
+    /// * `tolerate` - A boolean indicating whether to tolerate errors and continue parsing.
+    pub fn new(reader: R, tolerate: bool) -> Self {
+        Self {
+            reader,
+            parser: OggPageParser::new(tolerate),
+            chunk: vec![0; CHUNK_SIZE],
+            is_eof: false,
+        }
+    }
+
+    /// Reads the next page.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the next `OggPage`, `None` at the end of the stream, or an
+    /// `io::Error` on failure. Once an error is returned, the reader yields no more pages.
+    pub async fn next_page(&mut self) -> io::Result<Option<OggPage>> {
+        loop {
+            match self.parser.next_page(self.is_eof) {
+                Ok(Some(page)) => return Ok(Some(page)),
+                Ok(None) if self.is_eof => return Ok(None),
+                Ok(None) => {}
+                Err(error) => {
+                    self.is_eof = true;
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+                }
+            }
+
+            let n = self.reader.read(&mut self.chunk).await?;
+            if n == 0 {
+                self.is_eof = true;
+            } else {
+                self.parser.push_data(&self.chunk[..n]);
+            }
+        }
+    }
+
+    /// Converts the reader into a `Stream` of pages.
+    pub fn into_stream(self) -> impl Stream<Item = io::Result<OggPage>> {
+        stream::unfold(self, |mut reader| async move {
+            reader
+                .next_page()
+                .await
+                .transpose()
+                .map(|page| (page, reader))
+        })
+    }
+
+    /// Returns the stream offset of the last page returned by `next_page`.
+    pub fn last_page_offset(&self) -> usize {
+        self.parser.last_page_offset()
+    }
+
+    /// Returns the byte ranges skipped so far while resynchronising.
+    pub fn skip_report(&self) -> &OggSkipReport {
+        self.parser.skip_report()
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Reads the pages of an Ogg Vorbis file one at a time from an asynchronous reader, parsing
+/// their packets as they arrive.
+pub struct OggVorbisReader<R> {
+    page_reader: OggPageReader<R>,
+    parser: OggVorbisPacketParser,
+    pending_packets: VecDeque<OggPacket>,
+}
+
+impl<R: AsyncReadExt + Unpin> OggVorbisReader<R> {
+    /// Creates a new `OggVorbisReader`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - An asynchronous reader implementing `AsyncReadExt`.
+    /// * `tolerate` - A boolean indicating whether to tolerate errors and continue parsing.
+    /// * `header_search_range` - The range within which to search for headers.
+    pub fn new(reader: R, tolerate: bool, header_search_range: usize) -> Self {
+        Self {
+            page_reader: OggPageReader::new(reader, tolerate),
+            parser: OggVorbisPacketParser::new(header_search_range),
+            pending_packets: VecDeque::new(),
+        }
+    }
+
+    /// Reads and parses the next page.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the next `OggVorbisPageResult`, `None` at the end of the stream, or
+    /// an `io::Error` on failure.
+    pub async fn next_page(&mut self) -> io::Result<Option<OggVorbisPageResult>> {
+        match self.page_reader.next_page().await? {
+            Some(page) => self
+                .parser
+                .parse_page(page)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the byte ranges skipped so far while resynchronising.
+    pub fn skip_report(&self) -> &OggSkipReport {
+        self.page_reader.skip_report()
+    }
+
+    /// Reads the next reassembled packet, reading as many pages as needed.
+    ///
+    /// Reading packets and pages from the same reader is not supported, since packets are taken
+    /// from the pages read by this function only.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the next `OggPacket`, `None` at the end of the stream, or an
+    /// `io::Error` on failure.
+    pub async fn next_packet(&mut self) -> io::Result<Option<OggPacket>> {
+        while self.pending_packets.is_empty() {
+            match self.next_page().await? {
+                Some(result) => self.pending_packets.extend(result.completed_packets),
+                None => return Ok(None),
+            }
+        }
+        Ok(self.pending_packets.pop_front())
+    }
+
+    /// Converts the reader into a `Stream` of parsed pages.
+    pub fn into_stream(self) -> impl Stream<Item = io::Result<OggVorbisPageResult>> {
+        stream::unfold(self, |mut reader| async move {
+            reader
+                .next_page()
+                .await
+                .transpose()
+                .map(|result| (result, reader))
+        })
+    }
+
+    /// Converts the reader into a `Stream` of reassembled packets.
+    pub fn into_packet_stream(self) -> impl Stream<Item = io::Result<OggPacket>> {
+        stream::unfold(self, |mut reader| async move {
+            reader
+                .next_packet()
+                .await
+                .transpose()
+                .map(|packet| (packet, reader))
+        })
+    }
+}
@@ -0,0 +1,244 @@
+use crate::crc::vorbis_crc32;
+use crate::ogg_page::{OggFormatError, OggPage};
+
+/// A borrowed view of an Ogg page, reading its header fields and segments straight from the
+/// input slice without copying.
+///
+/// This is useful to inspect pages of a memory mapped file or a read buffer, and can be promoted
+/// to an owned `OggPage` once a page needs to be edited.
+#[derive(Debug, Clone, Copy)]
+pub struct OggPageRef<'a> {
+    buffer: &'a [u8],
+    segment_table_length: usize,
+}
+
+impl<'a> OggPageRef<'a> {
+    /// Creates a new `OggPageRef` borrowing the page at the start of a slice.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - A slice of bytes starting with the Ogg page, any bytes after the page are ignored.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, OggFormatError>` - An instance of `OggPageRef` or an error if the buffer is invalid.
+    pub fn new(buffer: &'a [u8]) -> Result<Self, OggFormatError> {
+        let (page_size, segment_table_length) = OggPage::validate_page(buffer)?;
+
+        Ok(Self {
+            buffer: &buffer[..page_size],
+            segment_table_length,
+        })
+    }
+
+    /// Returns the raw bytes of the page.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.buffer
+    }
+
+    /// Returns the size of the page in bytes.
+    pub fn page_size(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Returns the version of the page structure.
+    pub fn structure_version(&self) -> u8 {
+        self.buffer[4]
+    }
+
+    /// Returns the header type flags of the page.
+    pub fn header_type(&self) -> u8 {
+        self.buffer[5]
+    }
+
+    /// Checks if the page starts with a fresh packet rather than continuing one.
+    pub fn is_fresh_packet(&self) -> bool {
+        self.header_type() & 0x1 == 0
+    }
+
+    /// Checks if the page is the first page of its logical stream.
+    pub fn is_bos(&self) -> bool {
+        self.header_type() & 0x2 != 0
+    }
+
+    /// Checks if the page is the last page of its logical stream.
+    pub fn is_boe(&self) -> bool {
+        self.header_type() & 0x4 != 0
+    }
+
+    /// Returns the granule position of the page.
+    pub fn absolute_granule_position(&self) -> u64 {
+        u64::from_le_bytes(self.buffer[6..14].try_into().unwrap())
+    }
+
+    /// Returns the serial number of the logical stream of the page.
+    pub fn stream_serial_number(&self) -> u32 {
+        u32::from_le_bytes(self.buffer[14..18].try_into().unwrap())
+    }
+
+    /// Returns the sequence number of the page.
+    pub fn page_sequence_number(&self) -> u32 {
+        u32::from_le_bytes(self.buffer[18..22].try_into().unwrap())
+    }
+
+    /// Returns the checksum stored in the page.
+    pub fn page_checksum(&self) -> u32 {
+        u32::from_le_bytes(self.buffer[22..26].try_into().unwrap())
+    }
+
+    /// Returns the lacing values of the page.
+    pub fn segment_table(&self) -> &'a [u8] {
+        &self.buffer[27..27 + self.segment_table_length]
+    }
+
+    /// Returns the body of the page.
+    pub fn body(&self) -> &'a [u8] {
+        &self.buffer[27 + self.segment_table_length..]
+    }
+
+    /// Checks if the last packet of the page continues on the next page.
+    pub fn is_last_packet_continued(&self) -> bool {
+        self.segment_table().last() == Some(&255)
+    }
+
+    /// Returns an iterator over the segments of the page, one slice per lace group.
+    pub fn segments(&self) -> OggPageSegments<'a> {
+        OggPageSegments {
+            segment_table: self.segment_table(),
+            body: self.body(),
+        }
+    }
+
+    /// Retrieves a specific segment from the page.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the segment to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&[u8]>` - The segment, or `None` if the index is out of range.
+    pub fn get_page_segment(&self, index: usize) -> Option<&'a [u8]> {
+        self.segments().nth(index)
+    }
+
+    /// Calculates the checksum of the page.
+    ///
+    /// # Returns
+    ///
+    /// * `u32` - The calculated checksum.
+    pub fn calculate_page_checksum(&self) -> u32 {
+        let mut calculated_checksum = vorbis_crc32(self.buffer, 0, 0, 22);
+        calculated_checksum = vorbis_crc32(&[0; 4], calculated_checksum, 0, 4);
+        vorbis_crc32(self.buffer, calculated_checksum, 26, self.buffer.len())
+    }
+
+    /// Checks if the checksum of the page is correct.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - `true` if the checksum is correct, `false` otherwise.
+    pub fn if_page_checksum_correct(&self) -> bool {
+        self.calculate_page_checksum() == self.page_checksum()
+    }
+
+    /// Copies the page into an owned `OggPage`, so it can be edited.
+    pub fn to_owned_page(&self) -> OggPage {
+        OggPage::new(self.buffer.to_vec()).expect("A borrowed page is always valid")
+    }
+}
+
+/// An iterator over the segments of an Ogg page, yielding one slice per lace group.
+///
+/// Every lace group is yielded, so an empty packet yields an empty slice, matching
+/// `OggPage::parsed_segment_table`.
+#[derive(Debug, Clone)]
+pub struct OggPageSegments<'a> {
+    segment_table: &'a [u8],
+    body: &'a [u8],
+}
+
+impl<'a> Iterator for OggPageSegments<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let lacing_values = match self.segment_table.iter().position(|&x| x < 255) {
+            Some(position) => position + 1,
+            None => self.segment_table.len(),
+        };
+        if lacing_values == 0 {
+            return None;
+        }
+
+        let length = self.segment_table[..lacing_values]
+            .iter()
+            .map(|&x| x as usize)
+            .sum();
+        let (segment, body) = self.body.split_at(length);
+        self.segment_table = &self.segment_table[lacing_values..];
+        self.body = body;
+
+        Some(segment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ogg_stream_writer::OggStreamWriter;
+    use crate::test_util::vorbis_stream;
+
+    fn assert_same_page(page_ref: &OggPageRef, page: &OggPage) {
+        assert_eq!(page_ref.as_bytes(), &page.buffer[..]);
+        assert_eq!(page_ref.page_size(), page.page_size);
+        assert_eq!(page_ref.structure_version(), page.structure_version);
+        assert_eq!(page_ref.header_type(), page.header_type);
+        assert_eq!(page_ref.is_fresh_packet(), page.is_fresh_packet);
+        assert_eq!(page_ref.is_bos(), page.is_bos);
+        assert_eq!(page_ref.is_boe(), page.is_boe);
+        assert_eq!(
+            page_ref.absolute_granule_position(),
+            page.absolute_granule_position
+        );
+        assert_eq!(page_ref.stream_serial_number(), page.stream_serial_number);
+        assert_eq!(page_ref.page_sequence_number(), page.page_sequence_number);
+        assert_eq!(page_ref.page_checksum(), page.page_checksum);
+        assert_eq!(page_ref.segment_table(), &page.segment_table[..]);
+        assert_eq!(
+            page_ref.is_last_packet_continued(),
+            page.is_last_packet_continued
+        );
+        assert!(page_ref.if_page_checksum_correct());
+
+        let segments: Vec<&[u8]> = page_ref.segments().collect();
+        assert_eq!(segments.len(), page.parsed_segment_table.len());
+        for (index, segment) in segments.iter().enumerate() {
+            assert_eq!(*segment, &page.get_page_segment(index).unwrap()[..]);
+            assert_eq!(page_ref.get_page_segment(index), Some(*segment));
+        }
+        assert_eq!(page_ref.get_page_segment(segments.len()), None);
+        assert_eq!(page_ref.to_owned_page().buffer, page.buffer);
+    }
+
+    #[test]
+    fn page_ref_matches_the_parsed_page() {
+        let mut pages = vorbis_stream(7, "title", 12, 0);
+
+        // Pages continuing a packet, and a page with an empty packet and a packet of 510 bytes
+        let mut writer = OggStreamWriter::new(8);
+        pages.extend(writer.write_packet(&[1; 10000], 100, true).unwrap());
+        let segment_table = [0, 255, 255, 0, 255];
+        pages.push(OggPage::build(0x1, 200, 9, 3, &segment_table, &[2; 255 * 3]).unwrap());
+
+        for page in &pages {
+            let page = OggPage::new(page.buffer.clone()).unwrap();
+            assert_same_page(&page.as_page_ref().unwrap(), &page);
+
+            // Trailing bytes after the page are not part of it
+            let mut data = page.buffer.clone();
+            data.extend(b"OggS");
+            assert_same_page(&OggPageRef::new(&data).unwrap(), &page);
+            assert!(OggPageRef::new(&page.buffer[..page.page_size - 1]).is_err());
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use std::io::{self, SeekFrom};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::ogg_page::OggPage;
+use crate::ogg_page_reader::OggPageReader;
+
+/// The size of the byte range below which bisection stops and pages are scanned linearly.
+const LINEAR_SCAN_THRESHOLD: u64 = 65536;
+
+/// Represents the result of seeking to a sample.
+#[derive(Debug, Clone)]
+pub struct OggSeekResult {
+    /// The byte offset of the page containing the requested sample.
+    pub page_offset: u64,
+    /// The page containing the requested sample, the first page of the stream whose granule
+    /// position is at or after it.
+    pub page: OggPage,
+    /// The byte offset of the page on which the packet preceding the requested sample starts.
+    /// Decoding from this page primes the Vorbis decoder with one packet of pre-roll.
+    pub preroll_offset: u64,
+}
+
+/// The phase of a seek.
+#[derive(Debug)]
+enum OggSeekPhase {
+    /// Reading the first page to learn the serial number of the stream.
+    Probe,
+    /// Bisecting the byte range between the last page known to end before the target and the
+    /// first page known to end at or after it.
+    Bisect {
+        begin: u64,
+        begin_page_offset: u64,
+        end: u64,
+        mid: u64,
+    },
+    /// Scanning pages linearly, tracking where the packets start.
+    Scan {
+        from: u64,
+        preroll: OggPrerollTracker,
+    },
+    /// The seek has finished.
+    Done,
+}
+
+/// Tracks where the audio packets of a logical stream start, to find the page a decoder has to
+/// start reading from to pre-roll before a given page.
+#[derive(Debug, Default)]
+pub(crate) struct OggPrerollTracker {
+    /// The offset of the page on which the packet still in progress starts, and whether it is an
+    /// audio packet.
+    open_packet: Option<(u64, bool)>,
+    /// The offset of the page on which the last completed audio packet starts.
+    last_audio_packet_offset: Option<u64>,
+}
+
+impl OggPrerollTracker {
+    /// Returns the offset to start decoding from to pre-roll before the page at `page_offset`.
+    pub(crate) fn preroll_offset(&self, page_offset: u64) -> u64 {
+        self.last_audio_packet_offset.unwrap_or(page_offset)
+    }
+
+    /// Records the packets starting and completing on the next page of the logical stream.
+    pub(crate) fn push_page(&mut self, page_offset: u64, page: &OggPage) {
+        let segment_count = page.parsed_segment_table.len();
+        let mut position = 27 + page.segment_table.len();
+        for (segment_index, &segment_length) in page.parsed_segment_table.iter().enumerate() {
+            if segment_index > 0 || page.is_fresh_packet {
+                // Vorbis audio packets have the lowest bit of their first byte cleared
+                let is_audio = segment_length > 0 && page.buffer[position] & 1 == 0;
+                self.open_packet = Some((page_offset, is_audio));
+            }
+            position += segment_length;
+
+            let is_continued = segment_index + 1 == segment_count && page.is_last_packet_continued;
+            if !is_continued {
+                if let Some((packet_offset, true)) = self.open_packet.take() {
+                    self.last_audio_packet_offset = Some(packet_offset);
+                }
+            }
+        }
+    }
+}
+
+/// Seeks to the page containing a sample by bisecting on granule positions, without performing
+/// any I/O.
+///
+/// The seeker asks for the byte offset to read from with `next_offset`, and is fed the pages
+/// found from that offset with `push_page` until it returns `false`. Granule positions must
+/// increase monotonically, so chained files must be seeked one link at a time. Pages with a
+/// granule position of zero, such as the header pages, hold no samples and are never a result.
+#[derive(Debug)]
+pub struct OggGranuleSeeker {
+    /// The sample to seek to.
+    pub target_sample: u64,
+    /// The serial number of the stream to seek in, learned from the first page if not given.
+    pub stream_serial_number: Option<u32>,
+    phase: OggSeekPhase,
+    length: u64,
+    result: Option<OggSeekResult>,
+}
+
+impl OggGranuleSeeker {
+    /// Creates a new `OggGranuleSeeker`.
+    ///
+    /// # Arguments
+    ///
+    /// * `target_sample` - The sample to seek to.
+    /// * `stream_serial_number` - The serial number of the stream to seek in, or `None` to use the stream of the first page.
+    /// * `length` - The length of the physical stream in bytes.
+    pub fn new(target_sample: u64, stream_serial_number: Option<u32>, length: u64) -> Self {
+        let mut seeker = Self {
+            target_sample,
+            stream_serial_number,
+            phase: OggSeekPhase::Probe,
+            length,
+            result: None,
+        };
+        if stream_serial_number.is_some() {
+            seeker.start_bisect();
+        }
+        seeker
+    }
+
+    /// Returns the byte offset to read pages from next, or `None` once the seek has finished.
+    pub fn next_offset(&mut self) -> Option<u64> {
+        if let OggSeekPhase::Bisect {
+            begin,
+            begin_page_offset,
+            end,
+            ..
+        } = self.phase
+        {
+            if end.saturating_sub(begin) <= LINEAR_SCAN_THRESHOLD {
+                self.phase = OggSeekPhase::Scan {
+                    from: begin_page_offset,
+                    preroll: OggPrerollTracker::default(),
+                };
+            } else {
+                let mid = begin + (end - begin) / 2;
+                self.phase = OggSeekPhase::Bisect {
+                    begin,
+                    begin_page_offset,
+                    end,
+                    mid,
+                };
+            }
+        }
+
+        match self.phase {
+            OggSeekPhase::Probe => Some(0),
+            OggSeekPhase::Bisect { mid, .. } => Some(mid),
+            OggSeekPhase::Scan { from, .. } => Some(from),
+            OggSeekPhase::Done => None,
+        }
+    }
+
+    /// Feeds the next page found after the offset returned by `next_offset`.
+    ///
+    /// # Arguments
+    ///
+    /// * `page_offset` - The byte offset of the page in the physical stream.
+    /// * `page` - The page.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - `true` if more pages from the same offset are needed, `false` to continue with `next_offset`.
+    pub fn push_page(&mut self, page_offset: u64, page: &OggPage) -> bool {
+        let target_sample = self.target_sample;
+        let is_target_stream = self.stream_serial_number == Some(page.stream_serial_number);
+        // Header pages carry a granule position of zero, as do audio pages on which only the
+        // first packet ends, so neither holds the target sample
+        let granule_position = Some(page.absolute_granule_position)
+            .filter(|&granule| granule != u64::MAX && granule != 0);
+
+        match &mut self.phase {
+            OggSeekPhase::Probe => {
+                self.stream_serial_number = Some(page.stream_serial_number);
+                self.start_bisect();
+                false
+            }
+            OggSeekPhase::Bisect {
+                begin,
+                begin_page_offset,
+                end,
+                mid,
+            } => {
+                if page_offset >= *end {
+                    *end = *mid;
+                    return false;
+                }
+                let Some(granule_position) = granule_position.filter(|_| is_target_stream) else {
+                    return true;
+                };
+                if granule_position < target_sample {
+                    *begin = page_offset + page.page_size as u64;
+                    *begin_page_offset = page_offset;
+                } else {
+                    *end = page_offset;
+                }
+                false
+            }
+            OggSeekPhase::Scan { preroll, .. } => {
+                if !is_target_stream {
+                    return true;
+                }
+
+                if granule_position.is_some_and(|granule| granule >= target_sample) {
+                    self.result = Some(OggSeekResult {
+                        page_offset,
+                        page: page.clone(),
+                        preroll_offset: preroll.preroll_offset(page_offset),
+                    });
+                    self.phase = OggSeekPhase::Done;
+                    return false;
+                }
+
+                preroll.push_page(page_offset, page);
+                true
+            }
+            OggSeekPhase::Done => false,
+        }
+    }
+
+    /// Signals that the end of the stream was reached after the offset returned by `next_offset`.
+    pub fn push_eof(&mut self) {
+        match &mut self.phase {
+            OggSeekPhase::Bisect { end, mid, .. } => *end = *mid,
+            _ => self.phase = OggSeekPhase::Done,
+        }
+    }
+
+    /// Returns the result of the seek, or `None` if the sample lies past the end of the stream.
+    pub fn into_result(self) -> Option<OggSeekResult> {
+        self.result
+    }
+
+    /// Starts bisecting over the whole stream.
+    fn start_bisect(&mut self) {
+        self.phase = OggSeekPhase::Bisect {
+            begin: 0,
+            begin_page_offset: 0,
+            end: self.length,
+            mid: 0,
+        };
+    }
+}
+
+/// Seeks to the page containing a sample of an Ogg Vorbis stream, bisecting on granule positions.
+///
+/// Pages are found by resynchronising on capture patterns and validating their checksums, so
+/// the reader may be positioned anywhere in the file. The reader is left at an unspecified position.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to an asynchronous reader implementing `AsyncReadExt` and `AsyncSeekExt`.
+/// * `target_sample` - The sample to seek to.
+/// * `stream_serial_number` - The serial number of the stream to seek in, or `None` to use the stream of the first page.
+///
+/// # Returns
+///
+/// A `Result` containing the `OggSeekResult`, `None` if the sample lies past the end of the
+/// stream, or an `io::Error` on failure.
+pub async fn seek_to_sample<R: AsyncReadExt + AsyncSeekExt + Unpin>(
+    reader: &mut R,
+    target_sample: u64,
+    stream_serial_number: Option<u32>,
+) -> io::Result<Option<OggSeekResult>> {
+    let length = reader.seek(SeekFrom::End(0)).await?;
+    let mut seeker = OggGranuleSeeker::new(target_sample, stream_serial_number, length);
+
+    while let Some(offset) = seeker.next_offset() {
+        reader.seek(SeekFrom::Start(offset)).await?;
+        let mut page_reader = OggPageReader::new(&mut *reader, true);
+
+        loop {
+            let Some(page) = page_reader.next_page().await? else {
+                seeker.push_eof();
+                break;
+            };
+            let page_offset = offset + page_reader.last_page_offset() as u64;
+            if !seeker.push_page(page_offset, &page) {
+                break;
+            }
+        }
+    }
+
+    Ok(seeker.into_result())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{audio_granules, page_bytes, vorbis_stream};
+    use std::io::Cursor;
+
+    #[tokio::test]
+    async fn seek_bisects_to_the_page_holding_the_sample() {
+        let pages = vorbis_stream(7, "title", 8000, 0);
+        let data = page_bytes(&pages);
+        assert!(data.len() as u64 > 2 * LINEAR_SCAN_THRESHOLD);
+        let granules = audio_granules(8000, 0);
+
+        let mut page_offsets = Vec::new();
+        let mut offset = 0;
+        for page in &pages {
+            page_offsets.push(offset as u64);
+            offset += page.page_size;
+        }
+
+        for target_sample in [0, 1, granules[4000] - 1, granules[7999]] {
+            let mut reader = Cursor::new(data.clone());
+            let result = seek_to_sample(&mut reader, target_sample, None)
+                .await
+                .unwrap()
+                .unwrap();
+
+            // The first audio page ending at or after the sample, skipping the header pages
+            let page_index = (2..pages.len())
+                .find(|&index| pages[index].absolute_granule_position >= target_sample.max(1))
+                .unwrap();
+            assert_eq!(result.page_offset, page_offsets[page_index]);
+            assert_eq!(
+                result.page.absolute_granule_position,
+                pages[page_index].absolute_granule_position
+            );
+            assert!(result.preroll_offset <= result.page_offset);
+            assert!(result.preroll_offset >= page_offsets[page_index.max(3) - 1]);
+        }
+
+        let mut reader = Cursor::new(data);
+        let past_the_end = seek_to_sample(&mut reader, granules[7999] + 1, Some(7));
+        assert!(past_the_end.await.unwrap().is_none());
+    }
+}
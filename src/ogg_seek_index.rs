@@ -0,0 +1,405 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
+use std::io::{self, Cursor};
+
+use crate::ogg_page::{OggFormatError, OggPage};
+use crate::ogg_page_reader::OggPageReader;
+use crate::ogg_seek::OggPrerollTracker;
+
+const SEEK_INDEX_MAGIC_SIGNATURE: &[u8; 8] = b"OggSIdx\0";
+const SEEK_INDEX_VERSION: u32 = 1;
+
+/// Represents a page of a logical stream carrying a granule position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OggSeekIndexEntry {
+    /// The byte offset of the page in the physical stream.
+    pub page_offset: u64,
+    /// The granule position of the page.
+    pub granule_position: u64,
+    /// The byte offset of the page on which the last audio packet completed before this page
+    /// starts, as `OggSeekResult::preroll_offset`.
+    pub preroll_offset: u64,
+}
+
+/// Represents the indexed pages of one logical stream, in stream order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OggSeekIndexStream {
+    pub stream_serial_number: u32,
+    pub entries: Vec<OggSeekIndexEntry>,
+}
+
+impl OggSeekIndexStream {
+    /// Finds the first page whose granule position is at or after a sample.
+    ///
+    /// # Arguments
+    ///
+    /// * `target_sample` - The sample to seek to.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&OggSeekIndexEntry>` - The page, or `None` if the sample lies past the end of the stream.
+    pub fn find_sample(&self, target_sample: u64) -> Option<&OggSeekIndexEntry> {
+        let position = self
+            .entries
+            .partition_point(|entry| entry.granule_position < target_sample);
+        self.entries.get(position)
+    }
+}
+
+/// Maps granule positions to byte offsets for every logical stream of a physical stream.
+///
+/// The index is built in one pass with `OggSeekIndexBuilder`, and can be stored next to the
+/// file with `build` and reloaded with `parse`, so seeking no longer reads the file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OggSeekIndex {
+    /// The byte offset of the end of the last indexed page, to detect a stale index.
+    pub length: u64,
+    /// The logical streams, in the order of their BOS pages.
+    pub streams: Vec<OggSeekIndexStream>,
+}
+
+impl OggSeekIndex {
+    /// Finds the indexed stream with the given serial number.
+    pub fn stream(&self, stream_serial_number: u32) -> Option<&OggSeekIndexStream> {
+        self.streams
+            .iter()
+            .find(|stream| stream.stream_serial_number == stream_serial_number)
+    }
+
+    /// Finds the first page of a stream whose granule position is at or after a sample.
+    ///
+    /// # Arguments
+    ///
+    /// * `target_sample` - The sample to seek to.
+    /// * `stream_serial_number` - The serial number of the stream to seek in, or `None` to use the first stream.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&OggSeekIndexEntry>` - The page, or `None` if the stream is unknown or the sample
+    ///   lies past its end.
+    pub fn find_sample(
+        &self,
+        target_sample: u64,
+        stream_serial_number: Option<u32>,
+    ) -> Option<&OggSeekIndexEntry> {
+        let stream = match stream_serial_number {
+            Some(stream_serial_number) => self.stream(stream_serial_number)?,
+            None => self.streams.first()?,
+        };
+        stream.find_sample(target_sample)
+    }
+
+    /// Serializes the index into a compact binary form.
+    ///
+    /// Entries are stored as LEB128 encoded deltas from the previous entry of the same stream.
+    pub fn build(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(SEEK_INDEX_MAGIC_SIGNATURE);
+        result
+            .write_u32::<LittleEndian>(SEEK_INDEX_VERSION)
+            .unwrap();
+        result.write_u64::<LittleEndian>(self.length).unwrap();
+        result
+            .write_u32::<LittleEndian>(self.streams.len() as u32)
+            .unwrap();
+
+        for stream in &self.streams {
+            result
+                .write_u32::<LittleEndian>(stream.stream_serial_number)
+                .unwrap();
+            write_leb128(&mut result, stream.entries.len() as u64);
+
+            let mut previous = OggSeekIndexEntry {
+                page_offset: 0,
+                granule_position: 0,
+                preroll_offset: 0,
+            };
+            for entry in &stream.entries {
+                write_leb128(
+                    &mut result,
+                    entry.page_offset.wrapping_sub(previous.page_offset),
+                );
+                write_leb128(
+                    &mut result,
+                    entry
+                        .granule_position
+                        .wrapping_sub(previous.granule_position),
+                );
+                write_leb128(
+                    &mut result,
+                    entry.page_offset.wrapping_sub(entry.preroll_offset),
+                );
+                previous = *entry;
+            }
+        }
+
+        result
+    }
+
+    /// Parses an index serialized by `build`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The serialized index.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, OggFormatError>` - The index, or an error if the data is not a valid index.
+    pub fn parse(data: &[u8]) -> Result<Self, OggFormatError> {
+        let truncated = || OggFormatError("The seek index is truncated".to_string());
+
+        if !data.starts_with(SEEK_INDEX_MAGIC_SIGNATURE) {
+            return Err(OggFormatError(
+                "Invalid seek index magic signature".to_string(),
+            ));
+        }
+
+        let mut cursor = Cursor::new(data);
+        cursor.set_position(SEEK_INDEX_MAGIC_SIGNATURE.len() as u64);
+        let version = cursor.read_u32::<LittleEndian>().map_err(|_| truncated())?;
+        if version != SEEK_INDEX_VERSION {
+            return Err(OggFormatError(format!(
+                "Unsupported seek index version: {}",
+                version
+            )));
+        }
+        let length = cursor.read_u64::<LittleEndian>().map_err(|_| truncated())?;
+        let stream_count = cursor.read_u32::<LittleEndian>().map_err(|_| truncated())?;
+
+        let mut streams = Vec::new();
+        for _ in 0..stream_count {
+            let stream_serial_number =
+                cursor.read_u32::<LittleEndian>().map_err(|_| truncated())?;
+            let entry_count = read_leb128(&mut cursor)?;
+
+            let mut entries = Vec::new();
+            let mut previous = OggSeekIndexEntry {
+                page_offset: 0,
+                granule_position: 0,
+                preroll_offset: 0,
+            };
+            for _ in 0..entry_count {
+                let page_offset = previous.page_offset.wrapping_add(read_leb128(&mut cursor)?);
+                let granule_position = previous
+                    .granule_position
+                    .wrapping_add(read_leb128(&mut cursor)?);
+                let preroll_offset = page_offset.wrapping_sub(read_leb128(&mut cursor)?);
+                previous = OggSeekIndexEntry {
+                    page_offset,
+                    granule_position,
+                    preroll_offset,
+                };
+                entries.push(previous);
+            }
+
+            streams.push(OggSeekIndexStream {
+                stream_serial_number,
+                entries,
+            });
+        }
+
+        Ok(Self { length, streams })
+    }
+}
+
+/// The packet state of one logical stream while building a seek index.
+#[derive(Debug, Default)]
+struct OggSeekIndexStreamState {
+    stream_index: usize,
+    preroll: OggPrerollTracker,
+}
+
+/// Builds an `OggSeekIndex` from the pages of a physical stream without performing any I/O.
+///
+/// Pages are pushed in physical stream order together with their byte offset. Only pages
+/// holding samples are indexed, so pages without a granule position and header pages with a
+/// granule position of zero are skipped as by `OggGranuleSeeker`. A BOS page for a serial number already seen starts
+/// a new stream, as in chained files reusing serial numbers.
+#[derive(Debug, Default)]
+pub struct OggSeekIndexBuilder {
+    index: OggSeekIndex,
+    states: HashMap<u32, OggSeekIndexStreamState>,
+}
+
+impl OggSeekIndexBuilder {
+    /// Creates a new `OggSeekIndexBuilder`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds the next page of the physical stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `page_offset` - The byte offset of the page in the physical stream.
+    /// * `page` - The page.
+    pub fn push_page(&mut self, page_offset: u64, page: &OggPage) {
+        let serial = page.stream_serial_number;
+        self.index.length = self.index.length.max(page_offset + page.page_size as u64);
+
+        if page.is_bos || !self.states.contains_key(&serial) {
+            self.states.insert(
+                serial,
+                OggSeekIndexStreamState {
+                    stream_index: self.index.streams.len(),
+                    ..OggSeekIndexStreamState::default()
+                },
+            );
+            self.index.streams.push(OggSeekIndexStream {
+                stream_serial_number: serial,
+                entries: Vec::new(),
+            });
+        }
+        let state = self.states.get_mut(&serial).unwrap();
+
+        let granule_position = page.absolute_granule_position;
+        if granule_position != u64::MAX && granule_position != 0 {
+            self.index.streams[state.stream_index]
+                .entries
+                .push(OggSeekIndexEntry {
+                    page_offset,
+                    granule_position,
+                    preroll_offset: state.preroll.preroll_offset(page_offset),
+                });
+        }
+
+        state.preroll.push_page(page_offset, page);
+    }
+
+    /// Finishes the index.
+    pub fn finish(self) -> OggSeekIndex {
+        self.index
+    }
+}
+
+/// Reads a physical stream asynchronously in one pass and builds its seek index.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to an asynchronous reader implementing `AsyncReadExt`.
+///
+/// # Returns
+///
+/// A `Result` containing the `OggSeekIndex` on success, or an `io::Error` on failure.
+pub async fn build_ogg_seek_index<R: tokio::io::AsyncReadExt + Unpin>(
+    reader: &mut R,
+) -> io::Result<OggSeekIndex> {
+    let mut page_reader = OggPageReader::new(reader, true);
+    let mut builder = OggSeekIndexBuilder::new();
+
+    while let Some(page) = page_reader.next_page().await? {
+        builder.push_page(page_reader.last_page_offset() as u64, &page);
+    }
+
+    Ok(builder.finish())
+}
+
+/// Writes an unsigned LEB128 integer.
+fn write_leb128(result: &mut Vec<u8>, mut value: u64) {
+    while value > 0x7f {
+        result.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    result.push(value as u8);
+}
+
+/// Reads an unsigned LEB128 integer.
+fn read_leb128(cursor: &mut Cursor<&[u8]>) -> Result<u64, OggFormatError> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = cursor
+            .read_u8()
+            .map_err(|_| OggFormatError("The seek index is truncated".to_string()))?;
+        if shift < 64 {
+            value |= ((byte & 0x7f) as u64) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ogg_seek::seek_to_sample;
+    use crate::test_util::{audio_granules, page_bytes, vorbis_stream};
+
+    #[tokio::test]
+    async fn index_round_trips_and_applies_the_preroll() {
+        let pages: Vec<OggPage> = vorbis_stream(7, "first", 1000, 0)
+            .into_iter()
+            .chain(vorbis_stream(8, "second", 40, 100000))
+            .collect();
+        let data = page_bytes(&pages);
+        let index = build_ogg_seek_index(&mut data.as_slice()).await.unwrap();
+
+        assert_eq!(index.length, data.len() as u64);
+        assert_eq!(
+            index
+                .streams
+                .iter()
+                .map(|stream| stream.stream_serial_number)
+                .collect::<Vec<_>>(),
+            [7, 8]
+        );
+        assert_eq!(OggSeekIndex::parse(&index.build()).unwrap(), index);
+
+        // The first entry of the second stream needs more than two LEB128 bytes per delta
+        let entries = &index.stream(8).unwrap().entries;
+        assert!(entries[0].page_offset > 0x3fff && entries[0].granule_position > 0x3fff);
+        assert!(entries.iter().all(|entry| entry.granule_position != 0));
+
+        let granules = audio_granules(1000, 0);
+        for target_sample in [0, 1, granules[500] - 1, granules[500], granules[999]] {
+            let entry = *index.find_sample(target_sample, Some(7)).unwrap();
+            let mut reader = io::Cursor::new(data.clone());
+            let result = seek_to_sample(&mut reader, target_sample, Some(7))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(entry.page_offset, result.page_offset);
+            assert_eq!(entry.preroll_offset, result.preroll_offset);
+        }
+
+        // Past the first page, decoding starts one audio packet before the page
+        let entry = index.find_sample(granules[500], None).unwrap();
+        let previous = previous_entry(&index, 7, entry.page_offset);
+        assert_eq!(entry.preroll_offset, previous.page_offset);
+        let first = index.find_sample(0, Some(7)).unwrap();
+        assert_eq!(first.preroll_offset, first.page_offset);
+
+        assert!(index.find_sample(granules[999] + 1, Some(7)).is_none());
+        assert!(index.find_sample(0, Some(9)).is_none());
+    }
+
+    #[test]
+    fn parse_rejects_invalid_data() {
+        let index = OggSeekIndex {
+            length: 1000,
+            streams: vec![OggSeekIndexStream {
+                stream_serial_number: 7,
+                entries: vec![OggSeekIndexEntry {
+                    page_offset: 500,
+                    granule_position: 300,
+                    preroll_offset: 200,
+                }],
+            }],
+        };
+        let data = index.build();
+        assert_eq!(OggSeekIndex::parse(&data).unwrap(), index);
+        assert!(OggSeekIndex::parse(&data[..data.len() - 1]).is_err());
+        assert!(OggSeekIndex::parse(&data[1..]).is_err());
+    }
+
+    fn previous_entry(index: &OggSeekIndex, serial: u32, page_offset: u64) -> OggSeekIndexEntry {
+        let entries = &index.stream(serial).unwrap().entries;
+        let position = entries
+            .iter()
+            .position(|entry| entry.page_offset == page_offset)
+            .unwrap();
+        entries[position - 1]
+    }
+}
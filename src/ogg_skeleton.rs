@@ -0,0 +1,768 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::Cursor;
+
+use crate::ogg_demuxer::{demux_ogg_pages, OggCodec, OggLogicalStream};
+use crate::ogg_page::{OggFormatError, OggPage};
+use crate::ogg_stream_writer::OggStreamWriter;
+use crate::ogg_vorbis_page::{OggVorbisPage, VorbisFormatError};
+
+const FISHEAD_MAGIC_SIGNATURE: &[u8; 8] = b"fishead\0";
+const FISBONE_MAGIC_SIGNATURE: &[u8; 8] = b"fisbone\0";
+const INDEX_MAGIC_SIGNATURE: &[u8; 6] = b"index\0";
+
+/// The size of a Skeleton 3 fishead packet, which lacks the segment length and content offset.
+const FISHEAD_V3_SIZE: usize = 64;
+const FISHEAD_V4_SIZE: usize = 80;
+const FISBONE_HEADER_SIZE: usize = 52;
+const INDEX_HEADER_SIZE: usize = 42;
+
+/// Represents the fishead packet, the first packet of a Skeleton stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OggSkeletonFishead {
+    pub version_major: u16,
+    pub version_minor: u16,
+    pub presentation_time_numerator: i64,
+    pub presentation_time_denominator: i64,
+    pub base_time_numerator: i64,
+    pub base_time_denominator: i64,
+    pub utc: [u8; 20],
+    /// The length of the whole physical stream in bytes, only present since Skeleton 4.
+    pub segment_length: Option<u64>,
+    /// The byte offset of the first page that is not a header page, only present since Skeleton 4.
+    pub content_byte_offset: Option<u64>,
+}
+
+impl OggSkeletonFishead {
+    /// Parses a fishead packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet payload.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, OggFormatError>` - The fishead, or an error if the packet is not a valid fishead.
+    pub fn parse(packet: &[u8]) -> Result<Self, OggFormatError> {
+        if !packet.starts_with(FISHEAD_MAGIC_SIGNATURE) {
+            return Err(OggFormatError(
+                "Invalid fishead magic signature".to_string(),
+            ));
+        }
+        if packet.len() < FISHEAD_V3_SIZE {
+            return Err(OggFormatError(format!(
+                "The fishead packet is truncated, got {} bytes",
+                packet.len()
+            )));
+        }
+
+        let mut cursor = Cursor::new(packet);
+        cursor.set_position(FISHEAD_MAGIC_SIGNATURE.len() as u64);
+        let version_major = cursor.read_u16::<LittleEndian>().unwrap();
+        let version_minor = cursor.read_u16::<LittleEndian>().unwrap();
+        let presentation_time_numerator = cursor.read_i64::<LittleEndian>().unwrap();
+        let presentation_time_denominator = cursor.read_i64::<LittleEndian>().unwrap();
+        let base_time_numerator = cursor.read_i64::<LittleEndian>().unwrap();
+        let base_time_denominator = cursor.read_i64::<LittleEndian>().unwrap();
+        let mut utc = [0; 20];
+        utc.copy_from_slice(&packet[44..64]);
+
+        let (segment_length, content_byte_offset) =
+            if version_major >= 4 && packet.len() >= FISHEAD_V4_SIZE {
+                cursor.set_position(FISHEAD_V3_SIZE as u64);
+                (
+                    Some(cursor.read_u64::<LittleEndian>().unwrap()),
+                    Some(cursor.read_u64::<LittleEndian>().unwrap()),
+                )
+            } else {
+                (None, None)
+            };
+
+        Ok(Self {
+            version_major,
+            version_minor,
+            presentation_time_numerator,
+            presentation_time_denominator,
+            base_time_numerator,
+            base_time_denominator,
+            utc,
+            segment_length,
+            content_byte_offset,
+        })
+    }
+
+    /// Builds the fishead packet.
+    ///
+    /// The Skeleton 4 fields are written when both are set and `version_major` is at least 4.
+    /// Otherwise the packet is a Skeleton 3 fishead, and a `version_major` above 3 is written as 3.
+    pub fn build(&self) -> Vec<u8> {
+        let skeleton_4_fields = match (self.segment_length, self.content_byte_offset) {
+            (Some(segment_length), Some(content_byte_offset)) if self.version_major >= 4 => {
+                Some((segment_length, content_byte_offset))
+            }
+            _ => None,
+        };
+        let version_major = match skeleton_4_fields {
+            Some(_) => self.version_major,
+            None => self.version_major.min(3),
+        };
+
+        let mut result = Vec::with_capacity(FISHEAD_V4_SIZE);
+        result.extend_from_slice(FISHEAD_MAGIC_SIGNATURE);
+        result.write_u16::<LittleEndian>(version_major).unwrap();
+        result
+            .write_u16::<LittleEndian>(self.version_minor)
+            .unwrap();
+        for value in [
+            self.presentation_time_numerator,
+            self.presentation_time_denominator,
+            self.base_time_numerator,
+            self.base_time_denominator,
+        ] {
+            result.write_i64::<LittleEndian>(value).unwrap();
+        }
+        result.extend_from_slice(&self.utc);
+
+        if let Some((segment_length, content_byte_offset)) = skeleton_4_fields {
+            result.write_u64::<LittleEndian>(segment_length).unwrap();
+            result
+                .write_u64::<LittleEndian>(content_byte_offset)
+                .unwrap();
+        }
+
+        result
+    }
+}
+
+/// Represents a fisbone packet, describing one logical stream of the physical stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OggSkeletonFisbone {
+    /// The serial number of the described stream.
+    pub stream_serial_number: u32,
+    pub header_packet_count: u32,
+    pub granule_rate_numerator: i64,
+    pub granule_rate_denominator: i64,
+    pub base_granule: i64,
+    /// The number of packets to decode before the first packet of a seek target.
+    pub preroll: u32,
+    pub granule_shift: u8,
+    /// The message header fields, such as `Content-Type`, in packet order.
+    pub message_headers: Vec<(String, String)>,
+}
+
+impl OggSkeletonFisbone {
+    /// Parses a fisbone packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet payload.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, OggFormatError>` - The fisbone, or an error if the packet is not a valid fisbone.
+    pub fn parse(packet: &[u8]) -> Result<Self, OggFormatError> {
+        if !packet.starts_with(FISBONE_MAGIC_SIGNATURE) {
+            return Err(OggFormatError(
+                "Invalid fisbone magic signature".to_string(),
+            ));
+        }
+        if packet.len() < FISBONE_HEADER_SIZE {
+            return Err(OggFormatError(format!(
+                "The fisbone packet is truncated, got {} bytes",
+                packet.len()
+            )));
+        }
+
+        let mut cursor = Cursor::new(packet);
+        cursor.set_position(FISBONE_MAGIC_SIGNATURE.len() as u64);
+        let message_headers_offset = cursor.read_u32::<LittleEndian>().unwrap() as usize;
+        let stream_serial_number = cursor.read_u32::<LittleEndian>().unwrap();
+        let header_packet_count = cursor.read_u32::<LittleEndian>().unwrap();
+        let granule_rate_numerator = cursor.read_i64::<LittleEndian>().unwrap();
+        let granule_rate_denominator = cursor.read_i64::<LittleEndian>().unwrap();
+        let base_granule = cursor.read_i64::<LittleEndian>().unwrap();
+        let preroll = cursor.read_u32::<LittleEndian>().unwrap();
+        let granule_shift = cursor.read_u8().unwrap();
+
+        // The offset is counted from the offset field itself
+        let message_headers_start = FISBONE_MAGIC_SIGNATURE.len() + message_headers_offset;
+        let message_headers_array = packet.get(message_headers_start..).ok_or_else(|| {
+            OggFormatError(format!(
+                "Message headers offset out of range: {}",
+                message_headers_offset
+            ))
+        })?;
+        let message_headers = std::str::from_utf8(message_headers_array)
+            .map_err(|_| OggFormatError("Invalid UTF-8 sequence".to_string()))?
+            .split("\r\n")
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+
+        Ok(Self {
+            stream_serial_number,
+            header_packet_count,
+            granule_rate_numerator,
+            granule_rate_denominator,
+            base_granule,
+            preroll,
+            granule_shift,
+            message_headers,
+        })
+    }
+
+    /// Builds the fisbone packet.
+    pub fn build(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(FISBONE_HEADER_SIZE);
+        result.extend_from_slice(FISBONE_MAGIC_SIGNATURE);
+        result
+            .write_u32::<LittleEndian>((FISBONE_HEADER_SIZE - FISBONE_MAGIC_SIGNATURE.len()) as u32)
+            .unwrap();
+        result
+            .write_u32::<LittleEndian>(self.stream_serial_number)
+            .unwrap();
+        result
+            .write_u32::<LittleEndian>(self.header_packet_count)
+            .unwrap();
+        for value in [
+            self.granule_rate_numerator,
+            self.granule_rate_denominator,
+            self.base_granule,
+        ] {
+            result.write_i64::<LittleEndian>(value).unwrap();
+        }
+        result.write_u32::<LittleEndian>(self.preroll).unwrap();
+        result.push(self.granule_shift);
+        result.extend_from_slice(&[0; 3]); // Padding
+
+        for (name, value) in &self.message_headers {
+            result.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+
+        result
+    }
+
+    /// Finds the value of a message header field, ignoring the case of its name.
+    pub fn message_header(&self, name: &str) -> Option<&str> {
+        self.message_headers
+            .iter()
+            .find(|(field_name, _)| field_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Represents a keypoint of a Skeleton index, a page from which decoding can start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OggSkeletonKeypoint {
+    /// The byte offset of the page in the physical stream.
+    pub offset: u64,
+    /// The presentation time of the keypoint, over the timestamp denominator of the index.
+    pub time_numerator: i64,
+}
+
+/// Represents a Skeleton 4 index packet, listing the keypoints of one logical stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OggSkeletonIndex {
+    /// The serial number of the indexed stream.
+    pub stream_serial_number: u32,
+    pub timestamp_denominator: i64,
+    pub first_sample_time_numerator: i64,
+    pub last_sample_time_numerator: i64,
+    /// The keypoints, in increasing offset and time order.
+    pub keypoints: Vec<OggSkeletonKeypoint>,
+}
+
+impl OggSkeletonIndex {
+    /// Parses an index packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet payload.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, OggFormatError>` - The index, or an error if the packet is not a valid index.
+    pub fn parse(packet: &[u8]) -> Result<Self, OggFormatError> {
+        if !packet.starts_with(INDEX_MAGIC_SIGNATURE) {
+            return Err(OggFormatError("Invalid index magic signature".to_string()));
+        }
+        if packet.len() < INDEX_HEADER_SIZE {
+            return Err(OggFormatError(format!(
+                "The index packet is truncated, got {} bytes",
+                packet.len()
+            )));
+        }
+
+        let mut cursor = Cursor::new(packet);
+        cursor.set_position(INDEX_MAGIC_SIGNATURE.len() as u64);
+        let stream_serial_number = cursor.read_u32::<LittleEndian>().unwrap();
+        let keypoint_count = cursor.read_u64::<LittleEndian>().unwrap();
+        let timestamp_denominator = cursor.read_i64::<LittleEndian>().unwrap();
+        let first_sample_time_numerator = cursor.read_i64::<LittleEndian>().unwrap();
+        let last_sample_time_numerator = cursor.read_i64::<LittleEndian>().unwrap();
+
+        let mut position = INDEX_HEADER_SIZE;
+        let mut offset = 0u64;
+        let mut time_numerator = 0i64;
+        let mut keypoints = Vec::new();
+        for _ in 0..keypoint_count {
+            offset = offset.wrapping_add(Self::read_variable_length(packet, &mut position)?);
+            time_numerator = time_numerator
+                .wrapping_add(Self::read_variable_length(packet, &mut position)? as i64);
+            keypoints.push(OggSkeletonKeypoint {
+                offset,
+                time_numerator,
+            });
+        }
+
+        Ok(Self {
+            stream_serial_number,
+            timestamp_denominator,
+            first_sample_time_numerator,
+            last_sample_time_numerator,
+            keypoints,
+        })
+    }
+
+    /// Builds the index packet, encoding the keypoints as deltas from the previous one.
+    pub fn build(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(INDEX_HEADER_SIZE);
+        result.extend_from_slice(INDEX_MAGIC_SIGNATURE);
+        result
+            .write_u32::<LittleEndian>(self.stream_serial_number)
+            .unwrap();
+        result
+            .write_u64::<LittleEndian>(self.keypoints.len() as u64)
+            .unwrap();
+        for value in [
+            self.timestamp_denominator,
+            self.first_sample_time_numerator,
+            self.last_sample_time_numerator,
+        ] {
+            result.write_i64::<LittleEndian>(value).unwrap();
+        }
+
+        let mut offset = 0u64;
+        let mut time_numerator = 0i64;
+        for keypoint in &self.keypoints {
+            Self::write_variable_length(&mut result, keypoint.offset.wrapping_sub(offset));
+            Self::write_variable_length(
+                &mut result,
+                keypoint.time_numerator.wrapping_sub(time_numerator) as u64,
+            );
+            offset = keypoint.offset;
+            time_numerator = keypoint.time_numerator;
+        }
+
+        result
+    }
+
+    /// Finds the last keypoint at or before a presentation time.
+    ///
+    /// # Arguments
+    ///
+    /// * `time_numerator` - The presentation time, over the timestamp denominator of the index.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&OggSkeletonKeypoint>` - The keypoint, or `None` if the time lies before the first keypoint.
+    pub fn find_keypoint(&self, time_numerator: i64) -> Option<&OggSkeletonKeypoint> {
+        let position = self
+            .keypoints
+            .partition_point(|keypoint| keypoint.time_numerator <= time_numerator);
+        position.checked_sub(1).map(|index| &self.keypoints[index])
+    }
+
+    /// Reads a variable length integer, seven bits per byte with the high bit set on the last byte.
+    fn read_variable_length(packet: &[u8], position: &mut usize) -> Result<u64, OggFormatError> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let &byte = packet
+                .get(*position)
+                .ok_or_else(|| OggFormatError("The index packet is truncated".to_string()))?;
+            *position += 1;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// Writes a variable length integer, seven bits per byte with the high bit set on the last byte.
+    fn write_variable_length(result: &mut Vec<u8>, mut value: u64) {
+        while value > 0x7f {
+            result.push((value & 0x7f) as u8);
+            value >>= 7;
+        }
+        result.push(value as u8 | 0x80);
+    }
+}
+
+/// Represents the packets of a Skeleton stream.
+#[derive(Debug, Clone)]
+pub struct OggSkeleton {
+    pub fishead: OggSkeletonFishead,
+    /// The fisbones, one for each described logical stream.
+    pub fisbones: Vec<OggSkeletonFisbone>,
+    /// The Skeleton 4 indexes, one for each indexed logical stream.
+    pub indexes: Vec<OggSkeletonIndex>,
+}
+
+impl OggSkeleton {
+    /// Parses the packets of a Skeleton stream, as split by `OggDemuxer`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The Skeleton stream.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, OggFormatError>` - The parsed Skeleton, or an error if a packet is invalid.
+    pub fn parse(stream: &OggLogicalStream) -> Result<Self, OggFormatError> {
+        let mut packets = stream
+            .header_packets
+            .iter()
+            .chain(&stream.packets)
+            .map(|packet| packet.data.as_slice());
+
+        let fishead = OggSkeletonFishead::parse(packets.next().unwrap_or_default())?;
+        let mut skeleton = Self {
+            fishead,
+            fisbones: Vec::new(),
+            indexes: Vec::new(),
+        };
+
+        for packet in packets {
+            if packet.starts_with(FISBONE_MAGIC_SIGNATURE) {
+                skeleton.fisbones.push(OggSkeletonFisbone::parse(packet)?);
+            } else if packet.starts_with(INDEX_MAGIC_SIGNATURE) {
+                skeleton.indexes.push(OggSkeletonIndex::parse(packet)?);
+            }
+            // The empty packet on the EOS page and unknown packets are ignored
+        }
+
+        Ok(skeleton)
+    }
+
+    /// Finds the fisbone describing a logical stream.
+    pub fn fisbone(&self, stream_serial_number: u32) -> Option<&OggSkeletonFisbone> {
+        self.fisbones
+            .iter()
+            .find(|fisbone| fisbone.stream_serial_number == stream_serial_number)
+    }
+
+    /// Finds the index of a logical stream.
+    pub fn index(&self, stream_serial_number: u32) -> Option<&OggSkeletonIndex> {
+        self.indexes
+            .iter()
+            .find(|index| index.stream_serial_number == stream_serial_number)
+    }
+
+    /// Builds the pages of the Skeleton stream: the fishead alone on the BOS page, then the
+    /// fisbones and indexes, and an empty packet on the EOS page.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_serial_number` - The serial number of the Skeleton stream.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<OggPage>, OggFormatError>` - The pages of the Skeleton stream.
+    pub fn build_pages(&self, stream_serial_number: u32) -> Result<Vec<OggPage>, OggFormatError> {
+        let mut writer = OggStreamWriter::new(stream_serial_number);
+        let mut pages = Vec::new();
+
+        pages.extend(writer.write_packet(&self.fishead.build(), 0, false)?);
+        pages.extend(writer.flush()?);
+        for fisbone in &self.fisbones {
+            pages.extend(writer.write_packet(&fisbone.build(), 0, false)?);
+        }
+        pages.extend(writer.flush()?);
+        for index in &self.indexes {
+            pages.extend(writer.write_packet(&index.build(), 0, false)?);
+        }
+        pages.extend(writer.write_packet(&[], 0, true)?);
+
+        Ok(pages)
+    }
+}
+
+/// Adds a Skeleton 4 track with a keyframe index to an Ogg Vorbis file.
+///
+/// The Skeleton BOS page is put first, followed by the Vorbis BOS page, the rest of the
+/// Skeleton stream, and the remaining Vorbis pages. Every Vorbis audio page is a keypoint, but
+/// keypoints are kept at least `keypoint_interval` samples apart.
+///
+/// # Arguments
+///
+/// * `pages` - The pages of an Ogg Vorbis file holding a single Vorbis stream. Chained files
+///   are rejected, even if their links share a serial number.
+/// * `skeleton_serial_number` - The serial number of the new Skeleton stream.
+/// * `keypoint_interval` - The minimum number of samples between two keypoints.
+///
+/// # Returns
+///
+/// * `Result<Vec<OggPage>, VorbisFormatError>` - The pages of the new file, or an error if the
+///   file does not hold a single Vorbis stream.
+pub fn add_ogg_skeleton_to_vorbis_file(
+    pages: &[OggPage],
+    skeleton_serial_number: u32,
+    keypoint_interval: u64,
+) -> Result<Vec<OggPage>, VorbisFormatError> {
+    let streams = demux_ogg_pages(pages);
+    let [stream] = streams.as_slice() else {
+        return Err(VorbisFormatError(format!(
+            "Expected a single logical stream, got {}",
+            streams.len()
+        )));
+    };
+    if stream.codec != OggCodec::Vorbis || !stream.is_headers_complete() {
+        return Err(VorbisFormatError(
+            "Expected a Vorbis stream with complete headers".to_string(),
+        ));
+    }
+    if stream.stream_serial_number == skeleton_serial_number {
+        return Err(VorbisFormatError(format!(
+            "The serial number {} is already used by the Vorbis stream",
+            skeleton_serial_number
+        )));
+    }
+
+    let identification =
+        OggVorbisPage::parse_identification_packet(&stream.header_packets[0].data)?;
+    let sample_rate = identification.audio_sample_rate as i64;
+    let content_page = stream.header_packets[stream.header_packets.len() - 1].last_page_index + 1;
+    let vorbis_size: usize = pages.iter().map(|page| page.page_size).sum();
+    let last_granule_position = pages
+        .iter()
+        .rev()
+        .map(|page| page.absolute_granule_position)
+        .find(|&granule_position| granule_position != u64::MAX)
+        .unwrap_or(0);
+
+    let mut skeleton = OggSkeleton {
+        fishead: OggSkeletonFishead {
+            version_major: 4,
+            version_minor: 0,
+            presentation_time_numerator: 0,
+            presentation_time_denominator: 1000,
+            base_time_numerator: 0,
+            base_time_denominator: 1000,
+            utc: [0; 20],
+            segment_length: None,
+            content_byte_offset: None,
+        },
+        fisbones: vec![OggSkeletonFisbone {
+            stream_serial_number: stream.stream_serial_number,
+            header_packet_count: 3,
+            granule_rate_numerator: sample_rate,
+            granule_rate_denominator: 1,
+            base_granule: 0,
+            preroll: 2,
+            granule_shift: 0,
+            message_headers: vec![
+                ("Content-Type".to_string(), "audio/vorbis".to_string()),
+                ("Role".to_string(), "audio/main".to_string()),
+            ],
+        }],
+        indexes: Vec::new(),
+    };
+
+    // The offsets written into the Skeleton depend on its own size, so lay it out until the
+    // size settles
+    let mut skeleton_size = 0;
+    let skeleton_pages = loop {
+        let vorbis_offset = |page_index: usize| -> u64 {
+            let offset: usize = pages[..page_index].iter().map(|page| page.page_size).sum();
+            (skeleton_size + offset) as u64
+        };
+
+        let mut keypoints = Vec::new();
+        let mut time_numerator = 0;
+        for (page_index, page) in pages.iter().enumerate().skip(content_page) {
+            let is_due = keypoints
+                .last()
+                .map_or(true, |keypoint: &OggSkeletonKeypoint| {
+                    (time_numerator - keypoint.time_numerator) as u64 >= keypoint_interval
+                });
+            if is_due {
+                keypoints.push(OggSkeletonKeypoint {
+                    offset: vorbis_offset(page_index),
+                    time_numerator,
+                });
+            }
+            if page.absolute_granule_position != u64::MAX {
+                time_numerator = page.absolute_granule_position as i64;
+            }
+        }
+
+        skeleton.fishead.segment_length = Some((skeleton_size + vorbis_size) as u64);
+        skeleton.fishead.content_byte_offset = Some(vorbis_offset(content_page));
+        skeleton.indexes = vec![OggSkeletonIndex {
+            stream_serial_number: stream.stream_serial_number,
+            timestamp_denominator: sample_rate,
+            first_sample_time_numerator: 0,
+            last_sample_time_numerator: last_granule_position as i64,
+            keypoints,
+        }];
+
+        let skeleton_pages = skeleton
+            .build_pages(skeleton_serial_number)
+            .map_err(|e| VorbisFormatError(e.to_string()))?;
+        let new_skeleton_size = skeleton_pages.iter().map(|page| page.page_size).sum();
+        if new_skeleton_size == skeleton_size {
+            break skeleton_pages;
+        }
+        skeleton_size = new_skeleton_size;
+    };
+
+    let mut result = Vec::with_capacity(skeleton_pages.len() + pages.len());
+    let mut skeleton_pages = skeleton_pages.into_iter();
+    result.extend(skeleton_pages.next());
+    result.push(pages[0].clone());
+    result.extend(skeleton_pages);
+    result.extend(pages[1..].iter().cloned());
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{page_bytes, vorbis_stream};
+    use std::collections::HashMap;
+
+    fn fishead(version_major: u16, skeleton_4_fields: Option<(u64, u64)>) -> OggSkeletonFishead {
+        OggSkeletonFishead {
+            version_major,
+            version_minor: 0,
+            presentation_time_numerator: 1,
+            presentation_time_denominator: 1000,
+            base_time_numerator: -2,
+            base_time_denominator: 1000,
+            utc: *b"20261017T000000.000Z",
+            segment_length: skeleton_4_fields.map(|(segment_length, _)| segment_length),
+            content_byte_offset: skeleton_4_fields
+                .map(|(_, content_byte_offset)| content_byte_offset),
+        }
+    }
+
+    #[test]
+    fn fishead_round_trips() {
+        let v3 = fishead(3, None);
+        let packet = v3.build();
+        assert_eq!(packet.len(), FISHEAD_V3_SIZE);
+        assert_eq!(OggSkeletonFishead::parse(&packet).unwrap(), v3);
+
+        let v4 = fishead(4, Some((123456, 7890)));
+        let packet = v4.build();
+        assert_eq!(packet.len(), FISHEAD_V4_SIZE);
+        assert_eq!(OggSkeletonFishead::parse(&packet).unwrap(), v4);
+    }
+
+    #[test]
+    fn fishead_without_skeleton_4_fields_is_written_as_skeleton_3() {
+        let mut incomplete = fishead(4, Some((123456, 7890)));
+        incomplete.content_byte_offset = None;
+
+        let packet = incomplete.build();
+        assert_eq!(packet.len(), FISHEAD_V3_SIZE);
+        assert_eq!(
+            OggSkeletonFishead::parse(&packet).unwrap(),
+            fishead(3, None)
+        );
+    }
+
+    #[test]
+    fn fisbone_round_trips() {
+        let fisbone = OggSkeletonFisbone {
+            stream_serial_number: 0x1234_5678,
+            header_packet_count: 3,
+            granule_rate_numerator: 44100,
+            granule_rate_denominator: 1,
+            base_granule: -5,
+            preroll: 2,
+            granule_shift: 6,
+            message_headers: vec![
+                ("Content-Type".to_string(), "audio/vorbis".to_string()),
+                ("Role".to_string(), "audio/main".to_string()),
+                ("Name".to_string(), "a: b".to_string()),
+            ],
+        };
+
+        let parsed = OggSkeletonFisbone::parse(&fisbone.build()).unwrap();
+        assert_eq!(parsed, fisbone);
+        assert_eq!(parsed.message_header("content-type"), Some("audio/vorbis"));
+    }
+
+    #[test]
+    fn index_round_trips_multi_byte_deltas() {
+        let index = OggSkeletonIndex {
+            stream_serial_number: 7,
+            timestamp_denominator: 44100,
+            first_sample_time_numerator: 0,
+            last_sample_time_numerator: 1 << 40,
+            keypoints: vec![
+                OggSkeletonKeypoint {
+                    offset: 0x7f,
+                    time_numerator: 0,
+                },
+                OggSkeletonKeypoint {
+                    offset: 0x7f + 0x80,
+                    time_numerator: 44100,
+                },
+                OggSkeletonKeypoint {
+                    offset: 5_000_000_000,
+                    time_numerator: 1 << 40,
+                },
+            ],
+        };
+
+        let packet = index.build();
+        // One byte for the deltas below 0x80, two for 0x80, three for 44100 and more for the last
+        // keypoint
+        assert_eq!(packet.len(), INDEX_HEADER_SIZE + 1 + 1 + 2 + 3 + 5 + 6);
+        assert_eq!(OggSkeletonIndex::parse(&packet).unwrap(), index);
+    }
+
+    #[test]
+    fn keypoints_point_at_pages_of_the_vorbis_stream() {
+        let pages = vorbis_stream(7, "title", 400, 0);
+        let result = add_ogg_skeleton_to_vorbis_file(&pages, 9, 1000).unwrap();
+        let data = page_bytes(&result);
+
+        let mut offset = 0;
+        let mut page_offsets = HashMap::new();
+        for page in &result {
+            page_offsets.insert(offset as u64, page);
+            offset += page.page_size;
+        }
+
+        let streams = demux_ogg_pages(&result);
+        let skeleton = OggSkeleton::parse(&streams[0]).unwrap();
+        assert_eq!(skeleton.fishead.segment_length, Some(data.len() as u64));
+        let content_byte_offset = skeleton.fishead.content_byte_offset.unwrap();
+        assert!(page_offsets[&content_byte_offset].is_fresh_packet);
+
+        let index = skeleton.index(7).unwrap();
+        assert!(index.keypoints.len() > 1);
+        assert_eq!(index.keypoints[0].offset, content_byte_offset);
+        for keypoint in &index.keypoints {
+            let offset = keypoint.offset as usize;
+            assert_eq!(&data[offset..offset + 4], b"OggS");
+            assert_eq!(page_offsets[&keypoint.offset].stream_serial_number, 7);
+        }
+    }
+
+    #[test]
+    fn skeleton_is_not_added_to_a_chain() {
+        let pages: Vec<OggPage> = vorbis_stream(7, "first", 10, 0)
+            .into_iter()
+            .chain(vorbis_stream(7, "second", 6, 0))
+            .collect();
+        assert!(add_ogg_skeleton_to_vorbis_file(&pages, 9, 1000).is_err());
+    }
+}
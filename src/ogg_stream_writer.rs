@@ -0,0 +1,257 @@
+use crate::ogg_page::{OggFormatError, OggPage};
+
+/// The body size at which a page is emitted, matching the behaviour of libogg.
+const DEFAULT_MAX_PAGE_BODY_SIZE: usize = 4096;
+
+/// Paginates packets of one logical stream into Ogg pages.
+///
+/// Packets are laced into the pending page, which is emitted once its segment table is full,
+/// its body reaches `max_page_body_size`, or it would otherwise grow past `max_page_size`. The
+/// first page is flagged as BOS, the page holding the last packet as EOS, and pages starting in
+/// the middle of a packet are flagged as continued.
+#[derive(Debug, Clone)]
+pub struct OggStreamWriter {
+    /// The serial number written into every page.
+    pub stream_serial_number: u32,
+    /// The sequence number of the next emitted page.
+    pub page_sequence_number: u32,
+    /// The body size after which the pending page is emitted.
+    pub max_page_body_size: usize,
+    /// The size no page may exceed, including its header and segment table. If set, the
+    /// pending page is emitted early rather than grow past it.
+    pub max_page_size: Option<usize>,
+    segment_table: Vec<u8>,
+    body: Vec<u8>,
+    granule_position: u64,
+    is_continued: bool,
+    is_bos_written: bool,
+    is_eos_written: bool,
+}
+
+impl OggStreamWriter {
+    /// Creates a new `OggStreamWriter` for the logical stream with the given serial number.
+    pub fn new(stream_serial_number: u32) -> Self {
+        Self {
+            stream_serial_number,
+            page_sequence_number: 0,
+            max_page_body_size: DEFAULT_MAX_PAGE_BODY_SIZE,
+            max_page_size: None,
+            segment_table: Vec::new(),
+            body: Vec::new(),
+            granule_position: u64::MAX,
+            is_continued: false,
+            is_bos_written: false,
+            is_eos_written: false,
+        }
+    }
+
+    /// Writes a packet into the stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet payload.
+    /// * `granule_position` - The granule position at the end of the packet.
+    /// * `end_of_stream` - Whether this is the last packet of the stream, the pending page is
+    ///   emitted with the EOS flag if set.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<OggPage>, OggFormatError>` - The pages completed by this packet, or an error if
+    ///   the stream has already ended.
+    pub fn write_packet(
+        &mut self,
+        packet: &[u8],
+        granule_position: u64,
+        end_of_stream: bool,
+    ) -> Result<Vec<OggPage>, OggFormatError> {
+        if self.is_eos_written {
+            return Err(OggFormatError(
+                "Cannot write a packet after the end of stream".to_string(),
+            ));
+        }
+
+        let mut pages = Vec::new();
+        let mut remaining = packet;
+
+        loop {
+            let lacing_value = remaining.len().min(255);
+
+            let is_packet_started = remaining.len() < packet.len();
+            let page_size = 27 + self.segment_table.len() + 1 + self.body.len() + lacing_value;
+            if !self.segment_table.is_empty()
+                && self
+                    .max_page_size
+                    .is_some_and(|max_page_size| page_size > max_page_size)
+            {
+                pages.push(self.emit_page(false, is_packet_started)?);
+            }
+
+            self.segment_table.push(lacing_value as u8);
+            self.body.extend_from_slice(&remaining[..lacing_value]);
+            remaining = &remaining[lacing_value..];
+
+            let is_packet_finished = lacing_value < 255;
+            if is_packet_finished {
+                self.granule_position = granule_position;
+            }
+
+            let is_page_full =
+                self.segment_table.len() == 255 || self.body.len() >= self.max_page_body_size;
+            // The last packet of the stream is emitted below with the EOS flag instead
+            if is_page_full && !(is_packet_finished && end_of_stream) {
+                pages.push(self.emit_page(false, !is_packet_finished)?);
+            }
+
+            if is_packet_finished {
+                break;
+            }
+        }
+
+        if end_of_stream {
+            pages.push(self.emit_page(true, false)?);
+        }
+
+        Ok(pages)
+    }
+
+    /// Returns the granule position of the pending page, `None` if no packet ends on it yet.
+    pub fn pending_granule_position(&self) -> Option<u64> {
+        Some(self.granule_position).filter(|&granule_position| granule_position != u64::MAX)
+    }
+
+    /// Emits the pending page even if it is not full.
+    ///
+    /// This is used to put packets on a page of their own, such as the identification header.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<OggPage>, OggFormatError>` - The emitted page, or `None` if nothing is pending.
+    pub fn flush(&mut self) -> Result<Option<OggPage>, OggFormatError> {
+        if self.segment_table.is_empty() {
+            return Ok(None);
+        }
+        self.emit_page(false, false).map(Some)
+    }
+
+    /// Ends the stream, emitting the pending page with the EOS flag.
+    ///
+    /// If nothing is pending, an empty EOS page is emitted instead.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<OggPage>, OggFormatError>` - The EOS page, or `None` if the stream has already ended.
+    pub fn finish(&mut self) -> Result<Option<OggPage>, OggFormatError> {
+        if self.is_eos_written {
+            return Ok(None);
+        }
+        self.emit_page(true, false).map(Some)
+    }
+
+    /// Builds a page from the pending lacing values and resets the pending state.
+    fn emit_page(
+        &mut self,
+        end_of_stream: bool,
+        continues_on_next_page: bool,
+    ) -> Result<OggPage, OggFormatError> {
+        let mut header_type = 0;
+        if self.is_continued {
+            header_type |= 0x1;
+        }
+        if !self.is_bos_written {
+            header_type |= 0x2;
+        }
+        if end_of_stream {
+            header_type |= 0x4;
+        }
+
+        let page = OggPage::build(
+            header_type,
+            self.granule_position,
+            self.stream_serial_number,
+            self.page_sequence_number,
+            &self.segment_table,
+            &self.body,
+        )?;
+
+        self.page_sequence_number = self.page_sequence_number.wrapping_add(1);
+        self.segment_table.clear();
+        self.body.clear();
+        self.is_continued = continues_on_next_page;
+        self.is_bos_written = true;
+        self.is_eos_written = end_of_stream;
+        // Pages without a packet ending on them carry a granule position of -1
+        self.granule_position = u64::MAX;
+
+        Ok(page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writer_flags_pages_and_marks_pages_without_packet_end() {
+        let mut writer = OggStreamWriter::new(7);
+        let short_packet = vec![1; 10];
+        let long_packet = vec![2; 10000];
+
+        let mut pages = writer.write_packet(&short_packet, 100, false).unwrap();
+        assert!(pages.is_empty());
+        assert_eq!(writer.pending_granule_position(), Some(100));
+        pages.extend(writer.write_packet(&long_packet, 200, false).unwrap());
+        pages.extend(writer.flush().unwrap());
+        pages.extend(writer.finish().unwrap());
+
+        let flags: Vec<(bool, bool, bool)> = pages
+            .iter()
+            .map(|page| (page.is_bos, !page.is_fresh_packet, page.is_boe))
+            .collect();
+        assert_eq!(
+            flags,
+            [
+                (true, false, false),
+                (false, true, false),
+                (false, true, false),
+                (false, false, true),
+            ]
+        );
+
+        // Only the second page holds no packet end, and the empty EOS page ends no packet either
+        let granules: Vec<u64> = pages
+            .iter()
+            .map(|page| page.absolute_granule_position)
+            .collect();
+        assert_eq!(granules, [100, u64::MAX, 200, u64::MAX]);
+        assert!(pages[0].is_last_packet_continued && pages[1].is_last_packet_continued);
+        assert!(!pages[2].is_last_packet_continued);
+
+        for (index, page) in pages.iter().enumerate() {
+            assert_eq!(page.page_sequence_number, index as u32);
+            assert_eq!(page.stream_serial_number, 7);
+            assert!(page.if_page_checksum_correct());
+        }
+        let body: Vec<u8> = pages
+            .iter()
+            .flat_map(|page| page.buffer[27 + page.segment_table.len()..].to_vec())
+            .collect();
+        assert_eq!(body, [short_packet, long_packet].concat());
+
+        assert!(writer.write_packet(&[3], 300, false).is_err());
+        assert!(writer.finish().unwrap().is_none());
+    }
+
+    #[test]
+    fn last_packet_is_flagged_eos_on_its_own_page() {
+        let mut writer = OggStreamWriter::new(7);
+        let mut pages = writer.write_packet(&[1; 10], 0, false).unwrap();
+        pages.extend(writer.flush().unwrap());
+        assert!(writer.flush().unwrap().is_none());
+        pages.extend(writer.write_packet(&[2; 10], 50, true).unwrap());
+
+        assert_eq!(pages.len(), 2);
+        assert!(pages[0].is_bos && !pages[0].is_boe);
+        assert!(!pages[1].is_bos && pages[1].is_boe && pages[1].is_fresh_packet);
+        assert_eq!(pages[1].absolute_granule_position, 50);
+    }
+}
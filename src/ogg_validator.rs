@@ -0,0 +1,904 @@
+use std::collections::HashMap;
+use std::io::{self};
+use tokio::io::AsyncReadExt;
+
+use crate::ogg_demuxer::OggCodec;
+use crate::ogg_packet::{OggPacket, OggPacketAssembler};
+use crate::ogg_page_parser::{check_page_candidate, OggPageCandidate, OGG_CAPTURE_PATTERN};
+use crate::ogg_page_ref::OggPageRef;
+use crate::ogg_vorbis_page::{OggVorbisPage, VorbisHeaderType};
+
+/// The severity of a validation finding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OggValidationSeverity {
+    /// The stream is playable, but deviates from what the specification recommends.
+    Warning,
+    /// The stream violates the specification.
+    Error,
+}
+
+/// The rule of the specification a validation finding is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OggValidationRule {
+    /// Data that does not start with the `OggS` capture pattern.
+    CapturePattern,
+    /// A page with a structure version other than 0.
+    StructureVersion,
+    /// A page cut short by the end of the data.
+    TruncatedPage,
+    /// A page whose checksum does not match its content.
+    PageChecksum,
+    /// A logical stream that does not start with a single BOS page, or whose BOS page comes after
+    /// the data pages of the other streams of its group.
+    BeginningOfStream,
+    /// A logical stream that does not end with an EOS page.
+    EndOfStream,
+    /// A gap or repetition in the page sequence numbers of a logical stream.
+    PageSequence,
+    /// A granule position going backwards, or set on a page where no packet ends.
+    GranulePosition,
+    /// A header packet sharing a page it should have on its own.
+    HeaderPagination,
+    /// Header packets out of order.
+    HeaderOrder,
+    /// A Vorbis header that cannot be parsed or breaks a constraint of the Vorbis specification.
+    VorbisHeader,
+}
+
+/// Represents a single problem found by `OggValidator`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OggValidationFinding {
+    pub severity: OggValidationSeverity,
+    pub rule: OggValidationRule,
+    /// The byte offset in the physical stream where the problem is located.
+    pub offset: u64,
+    /// The serial number of the logical stream concerned, if any.
+    pub stream_serial_number: Option<u32>,
+    /// A human readable description of the problem.
+    pub message: String,
+}
+
+/// The findings of a validation, ordered by byte offset.
+#[derive(Debug, Clone, Default)]
+pub struct OggValidationReport {
+    pub findings: Vec<OggValidationFinding>,
+}
+
+impl OggValidationReport {
+    /// Checks if no error was found. Warnings do not make a stream invalid.
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    /// Returns an iterator over the findings with the `Error` severity.
+    pub fn errors(&self) -> impl Iterator<Item = &OggValidationFinding> {
+        self.findings
+            .iter()
+            .filter(|finding| finding.severity == OggValidationSeverity::Error)
+    }
+
+    /// Returns an iterator over the findings with the `Warning` severity.
+    pub fn warnings(&self) -> impl Iterator<Item = &OggValidationFinding> {
+        self.findings
+            .iter()
+            .filter(|finding| finding.severity == OggValidationSeverity::Warning)
+    }
+}
+
+/// The state of one logical stream seen by the validator.
+#[derive(Debug)]
+struct OggValidatorStream {
+    page_count: usize,
+    last_page_offset: u64,
+    last_page_sequence_number: u32,
+    last_granule_position: Option<u64>,
+    is_eos_seen: bool,
+    assembler: OggPacketAssembler,
+    header_packets: Vec<OggPacket>,
+    /// Whether the Vorbis headers were checked, or the stream is not a Vorbis stream.
+    is_headers_done: bool,
+    audio_channels: Option<u8>,
+}
+
+/// Checks pages of a physical stream against the Ogg and Vorbis specifications without
+/// performing any I/O.
+///
+/// Pages are pushed in physical stream order together with their byte offset. Every logical
+/// stream must start with a BOS page and end with an EOS page, keep its page sequence numbers
+/// contiguous and its granule positions monotonic. The headers of Vorbis streams are
+/// reassembled and checked as well.
+#[derive(Debug, Default)]
+pub struct OggValidator {
+    streams: HashMap<u32, OggValidatorStream>,
+    /// Whether a page other than a BOS page was seen since the last group of streams ended.
+    is_group_data_started: bool,
+    findings: Vec<OggValidationFinding>,
+}
+
+impl OggValidator {
+    /// Creates a new `OggValidator`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records data that could not be read as a page.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The byte offset of the data in the physical stream.
+    /// * `rule` - The rule the data breaks.
+    /// * `message` - A description of the problem.
+    pub fn push_invalid_data(&mut self, offset: u64, rule: OggValidationRule, message: String) {
+        self.push_finding(OggValidationSeverity::Error, rule, offset, None, message);
+    }
+
+    /// Validates the next page of the physical stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The byte offset of the page in the physical stream.
+    /// * `page` - The page.
+    pub fn push_page(&mut self, offset: u64, page: &OggPageRef) {
+        let serial = page.stream_serial_number();
+
+        if page.structure_version() != 0 {
+            self.push_error(
+                OggValidationRule::StructureVersion,
+                offset,
+                Some(serial),
+                format!(
+                    "Unsupported page structure version {}",
+                    page.structure_version()
+                ),
+            );
+        }
+
+        if !page.if_page_checksum_correct() {
+            self.push_error(
+                OggValidationRule::PageChecksum,
+                offset,
+                Some(serial),
+                format!(
+                    "Page checksum mismatch, stored {:#010x} but calculated {:#010x}",
+                    page.page_checksum(),
+                    page.calculate_page_checksum()
+                ),
+            );
+        }
+
+        if page.is_bos() {
+            self.push_bos_page(offset, page);
+        } else {
+            self.is_group_data_started = true;
+        }
+
+        let Some(stream) = self.streams.get(&serial) else {
+            self.push_error(
+                OggValidationRule::BeginningOfStream,
+                offset,
+                Some(serial),
+                "The first page of the logical stream is not flagged as BOS".to_string(),
+            );
+            self.streams
+                .insert(serial, Self::new_stream(OggCodec::Unknown));
+            self.push_stream_page(offset, page);
+            return;
+        };
+
+        if stream.page_count > 0 {
+            let is_after_eos = stream.is_eos_seen;
+            let expected = stream.last_page_sequence_number.wrapping_add(1);
+
+            if is_after_eos && !page.is_bos() {
+                self.push_error(
+                    OggValidationRule::EndOfStream,
+                    offset,
+                    Some(serial),
+                    "Page found after the EOS page of the logical stream".to_string(),
+                );
+            }
+
+            if !page.is_bos() && page.page_sequence_number() != expected {
+                self.push_error(
+                    OggValidationRule::PageSequence,
+                    offset,
+                    Some(serial),
+                    format!(
+                        "Expected page sequence number {}, got {}",
+                        expected,
+                        page.page_sequence_number()
+                    ),
+                );
+            }
+        }
+
+        self.push_stream_page(offset, page);
+    }
+
+    /// Finishes the validation, checking that every logical stream was ended.
+    ///
+    /// # Returns
+    ///
+    /// * `OggValidationReport` - The findings, ordered by byte offset.
+    pub fn finish(mut self) -> OggValidationReport {
+        let mut unfinished: Vec<(u32, u64)> = self
+            .streams
+            .iter()
+            .filter(|(_, stream)| !stream.is_eos_seen)
+            .map(|(&serial, stream)| (serial, stream.last_page_offset))
+            .collect();
+        unfinished.sort_by_key(|&(serial, offset)| (offset, serial));
+
+        for (serial, offset) in unfinished {
+            self.push_error(
+                OggValidationRule::EndOfStream,
+                offset,
+                Some(serial),
+                "The logical stream has no EOS page".to_string(),
+            );
+        }
+
+        // The sort is stable, so findings at the same offset keep the order they were found in
+        self.findings.sort_by_key(|finding| finding.offset);
+        OggValidationReport {
+            findings: self.findings,
+        }
+    }
+
+    /// Checks a BOS page and starts tracking its logical stream.
+    fn push_bos_page(&mut self, offset: u64, page: &OggPageRef) {
+        let serial = page.stream_serial_number();
+        let is_group_active = self.streams.values().any(|stream| !stream.is_eos_seen);
+
+        if !is_group_active {
+            // Every stream of the previous group ended, so this starts a new link of a chain
+            self.is_group_data_started = false;
+        } else if self.is_group_data_started {
+            self.push_error(
+                OggValidationRule::BeginningOfStream,
+                offset,
+                Some(serial),
+                "BOS page found after the data pages of the other logical streams".to_string(),
+            );
+        }
+
+        match self.streams.get(&serial) {
+            Some(stream) if !stream.is_eos_seen => self.push_error(
+                OggValidationRule::BeginningOfStream,
+                offset,
+                Some(serial),
+                "BOS page found in the middle of the logical stream".to_string(),
+            ),
+            Some(_) => self.push_finding(
+                OggValidationSeverity::Warning,
+                OggValidationRule::BeginningOfStream,
+                offset,
+                Some(serial),
+                "The serial number of an ended logical stream is reused".to_string(),
+            ),
+            None => {}
+        }
+
+        let codec = page
+            .segments()
+            .next()
+            .map_or(OggCodec::Unknown, OggCodec::detect);
+        self.streams.insert(serial, Self::new_stream(codec));
+
+        if codec == OggCodec::Vorbis
+            && (page.segments().count() != 1 || page.is_last_packet_continued())
+        {
+            self.push_error(
+                OggValidationRule::HeaderPagination,
+                offset,
+                Some(serial),
+                "The Vorbis identification header must be alone on the first page".to_string(),
+            );
+        }
+
+        if page.absolute_granule_position() != 0 {
+            self.push_finding(
+                OggValidationSeverity::Warning,
+                OggValidationRule::GranulePosition,
+                offset,
+                Some(serial),
+                format!(
+                    "The BOS page has a granule position of {} instead of 0",
+                    page.absolute_granule_position()
+                ),
+            );
+        }
+    }
+
+    /// Updates the state of the logical stream of a page, which is known to the validator.
+    fn push_stream_page(&mut self, offset: u64, page: &OggPageRef) {
+        let serial = page.stream_serial_number();
+        let stream = self.streams.get_mut(&serial).unwrap();
+        let granule_position = page.absolute_granule_position();
+        let mut findings = Vec::new();
+
+        if granule_position != u64::MAX {
+            if let Some(last_granule_position) = stream.last_granule_position {
+                if granule_position < last_granule_position {
+                    findings.push((
+                        OggValidationSeverity::Error,
+                        OggValidationRule::GranulePosition,
+                        format!(
+                            "Granule position {} goes back from {}",
+                            granule_position, last_granule_position
+                        ),
+                    ));
+                }
+            }
+            stream.last_granule_position = Some(granule_position);
+
+            let is_packet_ending = page.segment_table().iter().any(|&x| x < 255);
+            if !is_packet_ending {
+                findings.push((
+                    OggValidationSeverity::Warning,
+                    OggValidationRule::GranulePosition,
+                    format!(
+                        "No packet ends on the page, but it has a granule position of {}",
+                        granule_position
+                    ),
+                ));
+            }
+        }
+
+        stream.page_count += 1;
+        stream.last_page_offset = offset;
+        stream.last_page_sequence_number = page.page_sequence_number();
+        stream.is_eos_seen |= page.is_boe();
+
+        if !stream.is_headers_done {
+            let packets = stream.assembler.push_page_at(
+                &page.to_owned_page(),
+                stream.page_count - 1,
+                offset as usize,
+            );
+            for packet in packets {
+                if stream.is_headers_done {
+                    break;
+                }
+                if stream.header_packets.len() == 3 {
+                    // The first audio packet must start on a fresh page
+                    stream.is_headers_done = true;
+                    let setup = &stream.header_packets[2];
+                    if packet.first_page_index == setup.last_page_index {
+                        findings.push((
+                            OggValidationSeverity::Error,
+                            OggValidationRule::HeaderPagination,
+                            "The first audio packet shares a page with the setup header"
+                                .to_string(),
+                        ));
+                    }
+                    break;
+                }
+                let finding = Self::check_vorbis_header(stream, &packet);
+                // Later packets cannot be checked once the header order is broken
+                if let Some((_, OggValidationRule::HeaderOrder, _)) = finding {
+                    stream.is_headers_done = true;
+                }
+                findings.extend(finding);
+                stream.header_packets.push(packet);
+            }
+        }
+
+        for (severity, rule, message) in findings {
+            self.push_finding(severity, rule, offset, Some(serial), message);
+        }
+    }
+
+    /// Checks a header packet of a Vorbis stream.
+    fn check_vorbis_header(
+        stream: &mut OggValidatorStream,
+        packet: &OggPacket,
+    ) -> Option<(OggValidationSeverity, OggValidationRule, String)> {
+        let error = |rule, message: String| Some((OggValidationSeverity::Error, rule, message));
+
+        if !packet.is_complete {
+            return error(
+                OggValidationRule::VorbisHeader,
+                "The Vorbis header packet is incomplete".to_string(),
+            );
+        }
+
+        let (expected_type, name) = match stream.header_packets.len() {
+            0 => (VorbisHeaderType::Identification, "identification"),
+            1 => (VorbisHeaderType::Comment, "comment"),
+            _ => (VorbisHeaderType::Setup, "setup"),
+        };
+        if packet.data.first() != Some(&(expected_type as u8)) {
+            return error(
+                OggValidationRule::HeaderOrder,
+                format!(
+                    "Expected the Vorbis {} header, got a packet of type {:?}",
+                    name,
+                    packet.data.first()
+                ),
+            );
+        }
+
+        let result = match expected_type {
+            VorbisHeaderType::Identification => {
+                match OggVorbisPage::parse_identification_packet(&packet.data) {
+                    Ok(header) => {
+                        stream.audio_channels = Some(header.audio_channels);
+                        if header.audio_channels == 0 {
+                            return error(
+                                OggValidationRule::VorbisHeader,
+                                "The number of audio channels must be greater than 0".to_string(),
+                            );
+                        }
+                        if header.audio_sample_rate == 0 {
+                            return error(
+                                OggValidationRule::VorbisHeader,
+                                "The sample rate must be greater than 0".to_string(),
+                            );
+                        }
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            VorbisHeaderType::Comment => {
+                OggVorbisPage::parse_comment_packet(&packet.data).map(|_| ())
+            }
+            VorbisHeaderType::Setup => match stream.audio_channels {
+                Some(audio_channels) => {
+                    OggVorbisPage::parse_setup_packet(&packet.data, audio_channels).map(|_| ())
+                }
+                // The identification header was already reported as invalid
+                None => Ok(()),
+            },
+        };
+
+        result
+            .err()
+            .and_then(|e| error(OggValidationRule::VorbisHeader, e.0))
+    }
+
+    /// Creates the state of a newly seen logical stream.
+    fn new_stream(codec: OggCodec) -> OggValidatorStream {
+        OggValidatorStream {
+            page_count: 0,
+            last_page_offset: 0,
+            last_page_sequence_number: 0,
+            last_granule_position: None,
+            is_eos_seen: false,
+            assembler: OggPacketAssembler::new(),
+            header_packets: Vec::new(),
+            is_headers_done: codec != OggCodec::Vorbis,
+            audio_channels: None,
+        }
+    }
+
+    fn push_error(
+        &mut self,
+        rule: OggValidationRule,
+        offset: u64,
+        stream_serial_number: Option<u32>,
+        message: String,
+    ) {
+        self.push_finding(
+            OggValidationSeverity::Error,
+            rule,
+            offset,
+            stream_serial_number,
+            message,
+        );
+    }
+
+    fn push_finding(
+        &mut self,
+        severity: OggValidationSeverity,
+        rule: OggValidationRule,
+        offset: u64,
+        stream_serial_number: Option<u32>,
+        message: String,
+    ) {
+        self.findings.push(OggValidationFinding {
+            severity,
+            rule,
+            offset,
+            stream_serial_number,
+            message,
+        });
+    }
+}
+
+/// Validates the bytes of a whole physical stream.
+///
+/// Data that does not start a page is reported and skipped up to the next capture pattern, so
+/// the pages after a damaged region are validated too. A page running past the end of the data
+/// is only reported as truncated if no complete page follows it; otherwise its capture pattern
+/// is taken for a false match and skipped.
+///
+/// # Arguments
+///
+/// * `data` - The bytes of the physical stream.
+///
+/// # Returns
+///
+/// * `OggValidationReport` - The findings, ordered by byte offset.
+pub fn validate_ogg_data(data: &[u8]) -> OggValidationReport {
+    let mut validator = OggValidator::new();
+    let mut position = 0;
+    let mut next_page_offset = None;
+
+    while position < data.len() {
+        let remaining = &data[position..];
+
+        if !remaining.starts_with(OGG_CAPTURE_PATTERN) {
+            let length = remaining
+                .windows(OGG_CAPTURE_PATTERN.len())
+                .position(|window| window == OGG_CAPTURE_PATTERN)
+                .unwrap_or(remaining.len());
+            validator.push_invalid_data(
+                position as u64,
+                OggValidationRule::CapturePattern,
+                format!("Skipped {} bytes without an Ogg capture pattern", length),
+            );
+            position += length;
+            continue;
+        }
+
+        match OggPageRef::new(remaining) {
+            Ok(page) => {
+                validator.push_page(position as u64, &page);
+                position += page.page_size();
+            }
+            Err(e) => {
+                if next_page_offset.map_or(true, |offset| offset <= position) {
+                    next_page_offset = find_complete_page(data, position + 1);
+                }
+                if next_page_offset.is_none() {
+                    validator.push_invalid_data(
+                        position as u64,
+                        OggValidationRule::TruncatedPage,
+                        format!("The last page is truncated: {}", e.0),
+                    );
+                    break;
+                }
+
+                // Resynchronise from the byte after the false capture pattern
+                let length = remaining[1..]
+                    .windows(OGG_CAPTURE_PATTERN.len())
+                    .position(|window| window == OGG_CAPTURE_PATTERN)
+                    .map_or(remaining.len(), |offset| offset + 1);
+                validator.push_invalid_data(
+                    position as u64,
+                    OggValidationRule::CapturePattern,
+                    format!(
+                        "Skipped {} bytes from a capture pattern that does not start a page: {}",
+                        length, e.0
+                    ),
+                );
+                position += length;
+            }
+        }
+    }
+
+    validator.finish()
+}
+
+/// Finds the offset of the first complete page with a valid checksum at or after a byte offset.
+fn find_complete_page(data: &[u8], offset: usize) -> Option<usize> {
+    let mut position = offset;
+
+    while let Some(remaining) = data.get(position..) {
+        position += remaining
+            .windows(OGG_CAPTURE_PATTERN.len())
+            .position(|window| window == OGG_CAPTURE_PATTERN)?;
+        if let OggPageCandidate::Page(_) = check_page_candidate(&data[position..]) {
+            return Some(position);
+        }
+        position += 1;
+    }
+
+    None
+}
+
+/// Reads a physical stream asynchronously and validates it, as `validate_ogg_data` does.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to an asynchronous reader implementing `AsyncReadExt`.
+///
+/// # Returns
+///
+/// A `Result` containing the `OggValidationReport` on success, or an `io::Error` if reading fails.
+pub async fn validate_ogg_file<R: AsyncReadExt + Unpin>(
+    reader: &mut R,
+) -> io::Result<OggValidationReport> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await?;
+    Ok(validate_ogg_data(&data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ogg_page::OggPage;
+    use crate::ogg_stream_writer::OggStreamWriter;
+    use crate::test_util::{
+        audio_packet, comment_packet, page_bytes, vorbis_stream, IDENTIFICATION_PACKET,
+        SETUP_PACKET,
+    };
+
+    fn stream_pages() -> Vec<Vec<u8>> {
+        let mut writer = OggStreamWriter::new(1);
+        let mut pages = Vec::new();
+        for (index, length) in [30, 5000, 700, 9000].into_iter().enumerate() {
+            let packet = vec![1; length];
+            let written = writer.write_packet(&packet, index as u64, index == 3);
+            pages.extend(written.unwrap());
+            pages.extend(writer.flush().unwrap());
+        }
+        pages.into_iter().map(|page| page.buffer).collect()
+    }
+
+    fn rules(report: &OggValidationReport) -> Vec<OggValidationRule> {
+        report.findings.iter().map(|finding| finding.rule).collect()
+    }
+
+    #[test]
+    fn validate_ogg_data_resynchronises_after_a_false_capture_pattern() {
+        let pages = stream_pages();
+        let clean = validate_ogg_data(&pages.concat());
+
+        // A capture pattern whose segment table claims more data than the stream holds
+        let mut false_page = b"OggS\0\0".to_vec();
+        false_page.extend([0; 20]);
+        false_page.push(255);
+        false_page.extend([255; 40]);
+
+        let mut data = pages[0].clone();
+        let false_page_offset = data.len() as u64;
+        data.extend(&false_page);
+        data.extend(pages[1..].concat());
+        let report = validate_ogg_data(&data);
+
+        let finding = &report.findings[0];
+        assert_eq!(finding.rule, OggValidationRule::CapturePattern);
+        assert_eq!(finding.offset, false_page_offset);
+        assert_eq!(rules(&report)[1..], rules(&clean)[..]);
+    }
+
+    #[test]
+    fn validate_ogg_data_reports_a_truncated_last_page() {
+        let pages = stream_pages();
+        let mut data = pages[..pages.len() - 1].concat();
+        let last_page_offset = data.len() as u64;
+        data.extend(&pages[pages.len() - 1][..100]);
+
+        let report = validate_ogg_data(&data);
+        let finding = report
+            .findings
+            .iter()
+            .find(|finding| finding.rule == OggValidationRule::TruncatedPage)
+            .unwrap();
+        assert_eq!(finding.offset, last_page_offset);
+        assert!(!rules(&report).contains(&OggValidationRule::CapturePattern));
+    }
+
+    /// Returns the byte offset of every page.
+    fn page_offsets(pages: &[OggPage]) -> Vec<u64> {
+        let mut offset = 0;
+        pages
+            .iter()
+            .map(|page| {
+                let page_offset = offset;
+                offset += page.page_size as u64;
+                page_offset
+            })
+            .collect()
+    }
+
+    /// Validates the pages and checks that the only finding matches the given one.
+    fn assert_single_finding(
+        pages: &[OggPage],
+        severity: OggValidationSeverity,
+        rule: OggValidationRule,
+        offset: u64,
+    ) {
+        let report = validate_ogg_data(&page_bytes(pages));
+        let [finding] = report.findings.as_slice() else {
+            panic!("expected a single finding, got {:?}", report.findings);
+        };
+        assert_eq!(
+            (finding.severity, finding.rule, finding.offset),
+            (severity, rule, offset),
+            "{}",
+            finding.message
+        );
+    }
+
+    /// Writes a Vorbis stream with the given header packets followed by eight audio packets,
+    /// flushing the pending page after the header packets whose flag is set.
+    fn stream_with_headers(headers: &[(&[u8], bool)]) -> Vec<OggPage> {
+        let mut writer = OggStreamWriter::new(7);
+        let mut pages = Vec::new();
+        for &(header, is_flushed) in headers {
+            pages.extend(writer.write_packet(header, 0, false).unwrap());
+            if is_flushed {
+                pages.extend(writer.flush().unwrap());
+            }
+        }
+        for index in 0..8 {
+            let written = writer.write_packet(&audio_packet(index), index as u64 * 128, index == 7);
+            pages.extend(written.unwrap());
+        }
+        pages
+    }
+
+    #[test]
+    fn a_valid_stream_has_no_finding() {
+        let report = validate_ogg_data(&page_bytes(&vorbis_stream(7, "title", 30, 0)));
+        assert!(report.findings.is_empty(), "{:?}", report.findings);
+    }
+
+    #[test]
+    fn a_missing_bos_page_is_an_error() {
+        let mut pages = vorbis_stream(7, "title", 30, 0);
+        pages[0].set_is_bos(false);
+        assert_single_finding(
+            &pages,
+            OggValidationSeverity::Error,
+            OggValidationRule::BeginningOfStream,
+            0,
+        );
+    }
+
+    #[test]
+    fn a_bos_page_after_data_pages_is_an_error() {
+        let first = vorbis_stream(7, "first", 30, 0);
+        let second = vorbis_stream(8, "second", 30, 0);
+        let pages: Vec<OggPage> = first[..2]
+            .iter()
+            .chain(&second)
+            .chain(&first[2..])
+            .cloned()
+            .collect();
+        let offsets = page_offsets(&pages);
+        assert_single_finding(
+            &pages,
+            OggValidationSeverity::Error,
+            OggValidationRule::BeginningOfStream,
+            offsets[2],
+        );
+    }
+
+    #[test]
+    fn a_missing_eos_page_is_an_error() {
+        let mut pages = vorbis_stream(7, "title", 30, 0);
+        pages.last_mut().unwrap().set_is_boe(false);
+        let offsets = page_offsets(&pages);
+        assert_single_finding(
+            &pages,
+            OggValidationSeverity::Error,
+            OggValidationRule::EndOfStream,
+            offsets[offsets.len() - 1],
+        );
+    }
+
+    #[test]
+    fn a_page_after_the_eos_page_is_an_error() {
+        let mut pages = vorbis_stream(7, "title", 30, 0);
+        let mut extra_page = pages.last().unwrap().clone();
+        extra_page.set_page_sequence_number(extra_page.page_sequence_number + 1);
+        pages.push(extra_page);
+        let offsets = page_offsets(&pages);
+        assert_single_finding(
+            &pages,
+            OggValidationSeverity::Error,
+            OggValidationRule::EndOfStream,
+            offsets[offsets.len() - 1],
+        );
+    }
+
+    #[test]
+    fn a_page_sequence_gap_is_an_error() {
+        let mut pages = vorbis_stream(7, "title", 30, 0);
+        pages.remove(4);
+        let offsets = page_offsets(&pages);
+        assert_single_finding(
+            &pages,
+            OggValidationSeverity::Error,
+            OggValidationRule::PageSequence,
+            offsets[4],
+        );
+    }
+
+    #[test]
+    fn a_granule_position_going_backwards_is_an_error() {
+        let mut pages = vorbis_stream(7, "title", 30, 0);
+        let granule_position = pages[3].absolute_granule_position;
+        pages[5].set_absolute_granule_position(granule_position - 1);
+        let offsets = page_offsets(&pages);
+        assert_single_finding(
+            &pages,
+            OggValidationSeverity::Error,
+            OggValidationRule::GranulePosition,
+            offsets[5],
+        );
+    }
+
+    #[test]
+    fn an_identification_header_sharing_its_page_is_an_error() {
+        let comment = comment_packet("title");
+        let pages = stream_with_headers(&[
+            (&IDENTIFICATION_PACKET, false),
+            (&comment, false),
+            (&SETUP_PACKET, true),
+        ]);
+        assert_single_finding(
+            &pages,
+            OggValidationSeverity::Error,
+            OggValidationRule::HeaderPagination,
+            0,
+        );
+    }
+
+    #[test]
+    fn a_first_audio_packet_sharing_the_setup_page_is_an_error() {
+        let comment = comment_packet("title");
+        let pages = stream_with_headers(&[
+            (&IDENTIFICATION_PACKET, true),
+            (&comment, false),
+            (&SETUP_PACKET, false),
+        ]);
+        let offsets = page_offsets(&pages);
+        assert_single_finding(
+            &pages,
+            OggValidationSeverity::Error,
+            OggValidationRule::HeaderPagination,
+            offsets[1],
+        );
+    }
+
+    #[test]
+    fn headers_out_of_order_are_an_error() {
+        let comment = comment_packet("title");
+        let pages = stream_with_headers(&[
+            (&IDENTIFICATION_PACKET, true),
+            (&SETUP_PACKET, false),
+            (&comment, true),
+        ]);
+        let offsets = page_offsets(&pages);
+        assert_single_finding(
+            &pages,
+            OggValidationSeverity::Error,
+            OggValidationRule::HeaderOrder,
+            offsets[1],
+        );
+    }
+
+    #[test]
+    fn a_checksum_mismatch_is_an_error() {
+        let mut pages = vorbis_stream(7, "title", 30, 0);
+        let last_byte = pages[3].buffer.len() - 1;
+        pages[3].buffer[last_byte] ^= 0xff;
+        let offsets = page_offsets(&pages);
+        assert_single_finding(
+            &pages,
+            OggValidationSeverity::Error,
+            OggValidationRule::PageChecksum,
+            offsets[3],
+        );
+    }
+
+    #[test]
+    fn a_structure_version_other_than_zero_is_an_error() {
+        let mut pages = vorbis_stream(7, "title", 30, 0);
+        pages[3].buffer[4] = 1;
+        pages[3].update_page_checksum();
+        let offsets = page_offsets(&pages);
+        assert_single_finding(
+            &pages,
+            OggValidationSeverity::Error,
+            OggValidationRule::StructureVersion,
+            offsets[3],
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use std::ops::Range;
+
+use crate::ogg_demuxer::{demux_ogg_pages, OggCodec, OggLogicalStream};
+use crate::ogg_page::{OggFormatError, OggPage};
+use crate::ogg_stream_writer::OggStreamWriter;
+use crate::ogg_vorbis_page::VorbisFormatError;
+use crate::ogg_vorbis_repaginate::{prepare_ogg_vorbis_stream, write_ogg_vorbis_headers};
+
+/// Cuts a range of samples out of a Vorbis stream without decoding it.
+///
+/// The header packets are kept, followed by the audio packets covering the range and the packet
+/// before them, which primes the decoder. The granule positions are shifted so the new stream
+/// starts at the first sample of the range: the first audio page then carries a granule position
+/// lower than the samples decoded up to it, so decoders drop the samples before the range. The
+/// last page ends the stream with the granule position of the last sample of the range, so
+/// decoders drop the samples after it.
+///
+/// # Arguments
+///
+/// * `stream` - The Vorbis stream, as split by `OggDemuxer`.
+/// * `sample_range` - The range of samples to keep. An end past the end of the stream keeps the
+///   rest of the stream.
+///
+/// # Returns
+///
+/// * `Result<Vec<OggPage>, VorbisFormatError>` - The pages of the new stream, or an error if the
+///   stream is not a Vorbis stream with valid headers or the range holds no sample of it.
+pub fn cut_ogg_vorbis_stream(
+    stream: &OggLogicalStream,
+    sample_range: Range<u64>,
+) -> Result<Vec<OggPage>, VorbisFormatError> {
+    if sample_range.is_empty() {
+        return Err(VorbisFormatError(format!(
+            "The sample range {:?} is empty",
+            sample_range
+        )));
+    }
+
+    let stream_packets = prepare_ogg_vorbis_stream(stream)?;
+    let audio_packets = stream_packets.audio_packets;
+    let granule_positions = stream_packets.granule_positions;
+
+    // The first packet decoding samples of the range, and the last one needed to reach its end
+    let first = granule_positions.partition_point(|&granule| granule <= sample_range.start);
+    if first == audio_packets.len() {
+        return Err(VorbisFormatError(format!(
+            "The sample range {:?} starts after the end of the stream",
+            sample_range
+        )));
+    }
+    let last = granule_positions
+        .partition_point(|&granule| granule < sample_range.end)
+        .min(audio_packets.len() - 1);
+    let end_granule_position = granule_positions[last].min(sample_range.end);
+
+    let mut writer = OggStreamWriter::new(stream.stream_serial_number);
+    let map_error = |e: OggFormatError| VorbisFormatError(e.to_string());
+    let mut pages = write_ogg_vorbis_headers(&mut writer, stream_packets.header_packets)?;
+
+    for index in first.saturating_sub(1)..=last {
+        let granule_position = if index == last {
+            end_granule_position
+        } else {
+            granule_positions[index]
+        };
+        pages.extend(
+            writer
+                .write_packet(
+                    &audio_packets[index].data,
+                    granule_position.saturating_sub(sample_range.start),
+                    index == last,
+                )
+                .map_err(map_error)?,
+        );
+    }
+
+    Ok(pages)
+}
+
+/// Cuts a range of samples out of the first Vorbis stream of an Ogg file, as
+/// `cut_ogg_vorbis_stream` does.
+///
+/// Other logical streams, such as a Skeleton track or the following links of a chained file,
+/// are left out of the result, which is a standalone Ogg Vorbis file.
+///
+/// # Arguments
+///
+/// * `pages` - A slice of `OggPage` in physical stream order.
+/// * `sample_range` - The range of samples to keep.
+///
+/// # Returns
+///
+/// * `Result<Vec<OggPage>, VorbisFormatError>` - The pages of the new file, or an error if the
+///   file holds no Vorbis stream or it cannot be cut.
+pub fn cut_ogg_vorbis_file(
+    pages: &[OggPage],
+    sample_range: Range<u64>,
+) -> Result<Vec<OggPage>, VorbisFormatError> {
+    let stream = demux_ogg_pages(pages)
+        .into_iter()
+        .find(|stream| stream.codec == OggCodec::Vorbis)
+        .ok_or_else(|| VorbisFormatError("The file holds no Vorbis stream".to_string()))?;
+
+    cut_ogg_vorbis_stream(&stream, sample_range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{audio_granules, audio_packet, vorbis_stream};
+
+    #[test]
+    fn cut_keeps_the_range_of_the_first_link() {
+        let pages: Vec<OggPage> = vorbis_stream(7, "first", 400, 0)
+            .into_iter()
+            .chain(vorbis_stream(7, "second", 400, 0))
+            .collect();
+        let granules = audio_granules(400, 0);
+        let sample_range = granules[9] + 10..granules[300] - 5;
+
+        let cut = cut_ogg_vorbis_file(&pages, sample_range.clone()).unwrap();
+        let streams = demux_ogg_pages(&cut);
+        let [stream] = streams.as_slice() else {
+            panic!("expected a single stream, got {}", streams.len());
+        };
+        assert_eq!(stream.stream_serial_number, 7);
+        assert!(stream.is_bos_seen && stream.is_eos_seen);
+        assert!(stream.header_packets[1].data.ends_with(b"first\x01"));
+
+        // The packet before the range primes the decoder
+        let data: Vec<Vec<u8>> = stream.packets.iter().map(|p| p.data.clone()).collect();
+        let expected: Vec<Vec<u8>> = (9..=300).map(audio_packet).collect();
+        assert_eq!(data, expected);
+
+        // The first audio page ends before the samples decoded up to it, trimming the start
+        assert!(stream.pages.len() > 3);
+        let (first_index, first_packet) = stream
+            .packets
+            .iter()
+            .enumerate()
+            .find(|(_, packet)| packet.granule_position.is_some())
+            .unwrap();
+        let first_granule = first_packet.granule_position.unwrap();
+        assert_eq!(
+            first_granule,
+            granules[9 + first_index] - sample_range.start
+        );
+        let decoded_samples = granules[9 + first_index] - granules[9];
+        assert_eq!(
+            decoded_samples - first_granule,
+            sample_range.start - granules[9]
+        );
+
+        let last_page = stream.pages.last().unwrap();
+        assert!(last_page.is_boe);
+        assert_eq!(
+            last_page.absolute_granule_position,
+            sample_range.end - sample_range.start
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use crate::ogg_packet::OggPacket;
+use crate::ogg_vorbis_page::{ilog, IVorbisIdentificationHeader, IVorbisSetupHeader};
+
+/// Computes the number of samples decoded from the audio packets of a Vorbis stream.
+///
+/// Each audio packet selects a mode, whose block flag picks one of the two block sizes of the
+/// identification header. A packet completes a quarter of the previous block and a quarter of
+/// its own block, except for the first packet which only primes the decoder.
+#[derive(Debug, Clone)]
+pub struct OggVorbisGranuleCalculator {
+    /// The short block size.
+    pub blocksize0: usize,
+    /// The long block size.
+    pub blocksize1: usize,
+    mode_blockflags: Vec<bool>,
+    mode_bits: usize,
+    previous_block_size: Option<usize>,
+}
+
+impl OggVorbisGranuleCalculator {
+    /// Creates a new `OggVorbisGranuleCalculator` from the headers of a Vorbis stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `identification` - The identification header of the stream.
+    /// * `setup` - The setup header of the stream.
+    pub fn new(identification: &IVorbisIdentificationHeader, setup: &IVorbisSetupHeader) -> Self {
+        Self {
+            blocksize0: identification.blocksize0,
+            blocksize1: identification.blocksize1,
+            mode_blockflags: setup.modes.iter().map(|mode| mode.blockflag).collect(),
+            mode_bits: ilog(setup.modes.len().saturating_sub(1)),
+            previous_block_size: None,
+        }
+    }
+
+    /// Returns the block size of an audio packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The audio packet.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<usize>` - The block size, or `None` if the packet is empty, a header packet or
+    ///   refers to an unknown mode.
+    pub fn packet_block_size(&self, packet: &[u8]) -> Option<usize> {
+        let &first_byte = packet.first()?;
+        if first_byte & 1 != 0 {
+            return None;
+        }
+
+        // The mode number follows the packet type bit, and fits in the first byte
+        let mode = (first_byte as usize >> 1) & ((1 << self.mode_bits) - 1);
+        match self.mode_blockflags.get(mode)? {
+            true => Some(self.blocksize1),
+            false => Some(self.blocksize0),
+        }
+    }
+
+    /// Feeds the next audio packet of the stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The next audio packet, in stream order.
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - The number of samples completed by the packet, zero for packets without audio.
+    pub fn push_packet(&mut self, packet: &[u8]) -> u64 {
+        let Some(block_size) = self.packet_block_size(packet) else {
+            return 0;
+        };
+
+        let samples = match self.previous_block_size {
+            Some(previous_block_size) => previous_block_size / 4 + block_size / 4,
+            None => 0,
+        };
+        self.previous_block_size = Some(block_size);
+
+        samples as u64
+    }
+
+    /// Forgets the previous packet, as after a seek.
+    pub fn reset(&mut self) {
+        self.previous_block_size = None;
+    }
+}
+
+/// Calculates the granule position at the end of every audio packet of a Vorbis stream.
+///
+/// Durations come from the block sizes of the packets, while the granule positions carried by
+/// the original pages anchor the result, so a stream starting at a non-zero granule position or
+/// ending with a truncated last page keeps its timing.
+///
+/// # Arguments
+///
+/// * `calculator` - The calculator built from the headers of the stream.
+/// * `packets` - The audio packets of the stream, in stream order.
+///
+/// # Returns
+///
+/// A vector with the granule position at the end of each packet.
+pub fn calculate_ogg_vorbis_granule_positions(
+    calculator: &mut OggVorbisGranuleCalculator,
+    packets: &[OggPacket],
+) -> Vec<u64> {
+    let mut sample_counts = Vec::with_capacity(packets.len());
+    let mut sample_count = 0;
+    for packet in packets {
+        sample_count += calculator.push_packet(&packet.data);
+        sample_counts.push(sample_count);
+    }
+
+    let anchors: Vec<(usize, u64)> = packets
+        .iter()
+        .enumerate()
+        .filter_map(|(index, packet)| packet.granule_position.map(|granule| (index, granule)))
+        .collect();
+
+    let mut granule_positions = Vec::with_capacity(packets.len());
+    let mut next_anchor = 0;
+    for (index, &sample_count) in sample_counts.iter().enumerate() {
+        while next_anchor < anchors.len() && anchors[next_anchor].0 < index {
+            next_anchor += 1;
+        }
+        let previous = next_anchor.checked_sub(1).map(|anchor| anchors[anchor]);
+        let next = anchors.get(next_anchor).copied();
+
+        let granule_position = match (previous, next) {
+            (_, Some((anchor, granule))) if anchor == index => granule,
+            (Some((anchor, granule)), next) => {
+                let granule_position = granule + (sample_count - sample_counts[anchor]);
+                // A truncated last page ends before the decoded samples do
+                match next {
+                    Some((_, next_granule)) => granule_position.min(next_granule),
+                    None => granule_position,
+                }
+            }
+            (None, Some((anchor, granule))) => {
+                granule.saturating_sub(sample_counts[anchor] - sample_count)
+            }
+            (None, None) => sample_count,
+        };
+        granule_positions.push(granule_position);
+    }
+
+    granule_positions
+}
@@ -0,0 +1,218 @@
+use std::collections::HashSet;
+
+use crate::ogg_demuxer::{demux_ogg_pages, OggCodec, OggLogicalStream};
+use crate::ogg_packet::OggPacket;
+use crate::ogg_page::{OggFormatError, OggPage};
+use crate::ogg_stream_writer::OggStreamWriter;
+use crate::ogg_vorbis_granule::{
+    calculate_ogg_vorbis_granule_positions, OggVorbisGranuleCalculator,
+};
+use crate::ogg_vorbis_page::VorbisFormatError;
+use crate::ogg_vorbis_repaginate::{
+    prepare_ogg_vorbis_stream, write_ogg_vorbis_headers, OggVorbisStreamPackets,
+};
+
+/// Joins Vorbis streams one after another.
+///
+/// Consecutive streams with the same audio parameters and identical setup headers are merged
+/// into a single logical stream, keeping the comment header of the first one. Their audio
+/// packets are decoded one after another, so the granule positions are recalculated from the
+/// block sizes and only the start of the first stream and the end of the last one keep their
+/// trimming. Streams that cannot be merged start a new link of a chain instead, with a serial
+/// number not used by the other links.
+///
+/// # Arguments
+///
+/// * `streams` - The Vorbis streams, in playback order.
+///
+/// # Returns
+///
+/// * `Result<Vec<OggPage>, VorbisFormatError>` - The pages of the joined file, or an error if a
+///   stream is not a Vorbis stream with valid headers.
+pub fn join_ogg_vorbis_streams(
+    streams: &[OggLogicalStream],
+) -> Result<Vec<OggPage>, VorbisFormatError> {
+    if streams.is_empty() {
+        return Err(VorbisFormatError("No stream to join".to_string()));
+    }
+
+    let prepared_streams = streams
+        .iter()
+        .map(prepare_ogg_vorbis_stream)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut link_starts = vec![0];
+    for index in 1..prepared_streams.len() {
+        if !is_joinable(&prepared_streams[index - 1], &prepared_streams[index]) {
+            link_starts.push(index);
+        }
+    }
+    link_starts.push(prepared_streams.len());
+
+    let mut used_serial_numbers = HashSet::new();
+    let mut pages = Vec::new();
+    for link in link_starts.windows(2) {
+        let mut stream_serial_number = streams[link[0]].stream_serial_number;
+        while !used_serial_numbers.insert(stream_serial_number) {
+            stream_serial_number = stream_serial_number.wrapping_add(1);
+        }
+        pages.extend(write_joined_stream(
+            stream_serial_number,
+            &prepared_streams[link[0]..link[1]],
+        )?);
+    }
+
+    Ok(pages)
+}
+
+/// Joins the Vorbis streams of several Ogg files, as `join_ogg_vorbis_streams` does.
+///
+/// Every Vorbis stream of every file is joined in order, including the links of chained
+/// files. Other logical streams, such as Skeleton tracks, are left out.
+///
+/// # Arguments
+///
+/// * `files` - The pages of each file, in physical stream order.
+///
+/// # Returns
+///
+/// * `Result<Vec<OggPage>, VorbisFormatError>` - The pages of the joined file, or an error if the
+///   files hold no Vorbis stream or a stream cannot be joined.
+pub fn join_ogg_vorbis_files(files: &[&[OggPage]]) -> Result<Vec<OggPage>, VorbisFormatError> {
+    let streams: Vec<OggLogicalStream> = files
+        .iter()
+        .flat_map(|pages| demux_ogg_pages(pages))
+        .filter(|stream| stream.codec == OggCodec::Vorbis)
+        .collect();
+
+    join_ogg_vorbis_streams(&streams)
+}
+
+/// Checks if the audio packets of a stream can be decoded with the headers of another one.
+fn is_joinable(first: &OggVorbisStreamPackets, second: &OggVorbisStreamPackets) -> bool {
+    let first_identification = &first.identification_header;
+    let second_identification = &second.identification_header;
+
+    first_identification.audio_channels == second_identification.audio_channels
+        && first_identification.audio_sample_rate == second_identification.audio_sample_rate
+        && first_identification.blocksize0 == second_identification.blocksize0
+        && first_identification.blocksize1 == second_identification.blocksize1
+        && first.header_packets[2].data == second.header_packets[2].data
+}
+
+/// Writes joinable streams as a single logical stream, with the headers of the first one.
+fn write_joined_stream(
+    stream_serial_number: u32,
+    streams: &[OggVorbisStreamPackets],
+) -> Result<Vec<OggPage>, VorbisFormatError> {
+    let first = &streams[0];
+    let last = &streams[streams.len() - 1];
+
+    // Only the first granule position anchors the joined stream, the others restart at each
+    // joined stream and no longer apply
+    let mut is_anchored = false;
+    let audio_packets: Vec<OggPacket> = streams
+        .iter()
+        .flat_map(|stream| stream.audio_packets.iter().cloned())
+        .map(|mut packet| {
+            if is_anchored {
+                packet.granule_position = None;
+            }
+            is_anchored |= packet.granule_position.is_some();
+            packet
+        })
+        .collect();
+
+    let mut calculator =
+        OggVorbisGranuleCalculator::new(&first.identification_header, &first.setup_header);
+    let mut granule_positions =
+        calculate_ogg_vorbis_granule_positions(&mut calculator, &audio_packets);
+
+    // Keep the samples trimmed from the end of the last stream
+    if let ([.., previous_packet, last_packet], [.., previous_granule, last_granule]) = (
+        last.audio_packets.as_slice(),
+        last.granule_positions.as_slice(),
+    ) {
+        let mut calculator =
+            OggVorbisGranuleCalculator::new(&last.identification_header, &last.setup_header);
+        calculator.push_packet(&previous_packet.data);
+        let decoded_granule = previous_granule + calculator.push_packet(&last_packet.data);
+        let trimmed_samples = decoded_granule.saturating_sub(*last_granule);
+
+        if let [.., previous, last] = granule_positions.as_mut_slice() {
+            *last = last.saturating_sub(trimmed_samples).max(*previous);
+        }
+    }
+
+    let mut writer = OggStreamWriter::new(stream_serial_number);
+    let map_error = |e: OggFormatError| VorbisFormatError(e.to_string());
+    let mut pages = write_ogg_vorbis_headers(&mut writer, first.header_packets)?;
+
+    for (index, (packet, &granule_position)) in
+        audio_packets.iter().zip(&granule_positions).enumerate()
+    {
+        pages.extend(
+            writer
+                .write_packet(
+                    &packet.data,
+                    granule_position,
+                    index + 1 == audio_packets.len(),
+                )
+                .map_err(map_error)?,
+        );
+    }
+
+    if audio_packets.is_empty() {
+        pages.extend(writer.finish().map_err(map_error)?);
+    }
+
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ogg_vorbis_page::OggVorbisPage;
+    use crate::test_util::{audio_packet, vorbis_stream, IDENTIFICATION_PACKET, SETUP_PACKET};
+
+    #[test]
+    fn join_of_identical_setups_makes_one_stream() {
+        let chained: Vec<OggPage> = vorbis_stream(7, "first", 10, 0)
+            .into_iter()
+            .chain(vorbis_stream(7, "second", 6, 0))
+            .collect();
+        let other = vorbis_stream(9, "third", 8, 0);
+
+        let joined = join_ogg_vorbis_files(&[&chained, &other]).unwrap();
+        assert!(joined.iter().all(|page| page.stream_serial_number == 7));
+        let sequence_numbers: Vec<u32> = joined
+            .iter()
+            .map(|page| page.page_sequence_number)
+            .collect();
+        let expected_sequence_numbers: Vec<u32> = (0..joined.len() as u32).collect();
+        assert_eq!(sequence_numbers, expected_sequence_numbers);
+
+        let streams = demux_ogg_pages(&joined);
+        let [stream] = streams.as_slice() else {
+            panic!("expected a single stream, got {}", streams.len());
+        };
+        assert!(stream.is_bos_seen && stream.is_eos_seen);
+        assert!(stream.header_packets[1].data.ends_with(b"first\x01"));
+
+        let audio_packets: Vec<Vec<u8>> =
+            (0..10).chain(0..6).chain(0..8).map(audio_packet).collect();
+        let data: Vec<Vec<u8>> = stream.packets.iter().map(|p| p.data.clone()).collect();
+        assert_eq!(data, audio_packets);
+
+        // The packets are decoded one after another, across the joined streams
+        let identification =
+            OggVorbisPage::parse_identification_packet(&IDENTIFICATION_PACKET).unwrap();
+        let setup = OggVorbisPage::parse_setup_packet(&SETUP_PACKET, 2).unwrap();
+        let mut calculator = OggVorbisGranuleCalculator::new(&identification, &setup);
+        let samples: u64 = audio_packets
+            .iter()
+            .map(|packet| calculator.push_packet(packet))
+            .sum();
+        assert_eq!(joined.last().unwrap().absolute_granule_position, samples);
+    }
+}
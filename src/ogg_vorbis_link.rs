@@ -0,0 +1,242 @@
+use std::ops::Range;
+
+use crate::ogg_vorbis_granule::OggVorbisGranuleCalculator;
+use crate::read_ogg_vorbis_file::{OggVorbisPacket, OggVorbisPageResult};
+
+/// Represents one link of a chained Ogg Vorbis file.
+///
+/// A link starts with a group of BOS pages followed by fresh identification, comment and setup
+/// headers, and ends right before the next group of BOS pages.
+#[derive(Debug, Clone)]
+pub struct OggVorbisLink {
+    /// The range of page indices covered by the link.
+    pub page_range: Range<usize>,
+    /// The byte range covered by the link, counted over the pages of the file.
+    pub byte_range: Range<usize>,
+    /// The serial number of the Vorbis stream of the link.
+    pub stream_serial_number: u32,
+    /// The page and packet indices of the identification header, if found.
+    pub identification: Option<(usize, usize)>,
+    /// The page and packet indices of the comment header, if found.
+    pub comment: Option<(usize, usize)>,
+    /// The page and packet indices of the setup header, if found.
+    pub setup: Option<(usize, usize)>,
+    /// The range of samples covered by the link within the whole chain. The length of the range
+    /// is the last granule position of the link minus the granule position its audio starts at.
+    pub sample_range: Range<u64>,
+}
+
+/// Finds the links of a chained Ogg Vorbis file.
+///
+/// A file that is not chained yields a single link. Pages before the first BOS page are
+/// attributed to the first link.
+///
+/// # Arguments
+///
+/// * `ogg_vorbis_file` - A slice of `OggVorbisPageResult` representing the Ogg Vorbis file.
+///
+/// # Returns
+///
+/// A vector of `OggVorbisLink` in file order.
+pub fn find_ogg_vorbis_links(ogg_vorbis_file: &[OggVorbisPageResult]) -> Vec<OggVorbisLink> {
+    let mut link_starts = Vec::new();
+    for (page_index, page_result) in ogg_vorbis_file.iter().enumerate() {
+        let previous_is_bos = page_index > 0 && ogg_vorbis_file[page_index - 1].page.is_bos;
+        if page_index == 0 || (page_result.page.is_bos && !previous_is_bos) {
+            link_starts.push(page_index);
+        }
+    }
+
+    let mut links = Vec::new();
+    let mut byte_offset = 0;
+    let mut sample_offset = 0;
+
+    for (link_index, &link_start) in link_starts.iter().enumerate() {
+        let link_end = link_starts
+            .get(link_index + 1)
+            .copied()
+            .unwrap_or(ogg_vorbis_file.len());
+        let pages = &ogg_vorbis_file[link_start..link_end];
+
+        let find_packet = |predicate: fn(&OggVorbisPacket) -> bool| {
+            pages.iter().enumerate().find_map(|(page_index, page)| {
+                page.packets
+                    .iter()
+                    .position(predicate)
+                    .map(|packet_index| (link_start + page_index, packet_index))
+            })
+        };
+
+        let identification =
+            find_packet(|packet| matches!(packet, OggVorbisPacket::Identification(_)));
+        let comment = find_packet(|packet| matches!(packet, OggVorbisPacket::Comment(_)));
+        let setup = find_packet(|packet| matches!(packet, OggVorbisPacket::Setup(_)));
+
+        let stream_serial_number = match identification {
+            Some((page_index, _)) => ogg_vorbis_file[page_index].page.stream_serial_number,
+            None => pages[0].page.stream_serial_number,
+        };
+
+        let byte_length: usize = pages.iter().map(|page| page.page.page_size).sum();
+
+        let last_granule_position = pages
+            .iter()
+            .rev()
+            .filter(|page| page.page.stream_serial_number == stream_serial_number)
+            .map(|page| page.page.absolute_granule_position)
+            .find(|&granule_position| granule_position != u64::MAX)
+            .unwrap_or(0);
+        let start_granule_position = find_start_granule_position(
+            ogg_vorbis_file,
+            link_start..link_end,
+            stream_serial_number,
+            identification,
+            setup,
+        );
+        let sample_count = last_granule_position.saturating_sub(start_granule_position);
+
+        links.push(OggVorbisLink {
+            page_range: link_start..link_end,
+            byte_range: byte_offset..byte_offset + byte_length,
+            stream_serial_number,
+            identification,
+            comment,
+            setup,
+            sample_range: sample_offset..sample_offset + sample_count,
+        });
+
+        byte_offset += byte_length;
+        sample_offset += sample_count;
+    }
+
+    links
+}
+
+/// Finds the granule position the audio of a link starts at.
+///
+/// The granule position of the first page completing an audio packet, minus the samples decoded
+/// from the audio packets completed up to it. Links without parsed headers start at zero.
+fn find_start_granule_position(
+    ogg_vorbis_file: &[OggVorbisPageResult],
+    page_range: Range<usize>,
+    stream_serial_number: u32,
+    identification: Option<(usize, usize)>,
+    setup: Option<(usize, usize)>,
+) -> u64 {
+    let (Some(identification), Some(setup)) = (identification, setup) else {
+        return 0;
+    };
+    let (OggVorbisPacket::Identification(identification), OggVorbisPacket::Setup(setup)) = (
+        &ogg_vorbis_file[identification.0].packets[identification.1],
+        &ogg_vorbis_file[setup.0].packets[setup.1],
+    ) else {
+        return 0;
+    };
+
+    let mut calculator = OggVorbisGranuleCalculator::new(&identification.data, &setup.data);
+    let mut decoded_samples = 0;
+    for page_result in &ogg_vorbis_file[page_range] {
+        if page_result.page.stream_serial_number != stream_serial_number {
+            continue;
+        }
+
+        let mut is_audio_completed = false;
+        for packet in &page_result.completed_packets {
+            if calculator.packet_block_size(&packet.data).is_some() {
+                decoded_samples += calculator.push_packet(&packet.data);
+                is_audio_completed = true;
+            }
+        }
+
+        let granule_position = page_result.page.absolute_granule_position;
+        if is_audio_completed && granule_position != u64::MAX {
+            return granule_position.saturating_sub(decoded_samples);
+        }
+    }
+
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ogg_page::OggPage;
+    use crate::read_ogg_vorbis_file::OggVorbisPacketParser;
+    use crate::test_util::{audio_granules, vorbis_stream};
+    use crate::utils::update_ogg_vorbis_link_comments;
+    use std::collections::HashMap;
+
+    fn parse_pages(pages: Vec<OggPage>) -> Vec<OggVorbisPageResult> {
+        let mut parser = OggVorbisPacketParser::new(usize::MAX);
+        pages
+            .into_iter()
+            .map(|page| parser.parse_page(page).unwrap())
+            .collect()
+    }
+
+    fn title(file: &[OggVorbisPageResult], link: &OggVorbisLink) -> String {
+        let (page_index, packet_index) = link.comment.unwrap();
+        match &file[page_index].packets[packet_index] {
+            OggVorbisPacket::Comment(comment) => comment.data.comments["TITLE"][0].clone(),
+            _ => panic!("expected a comment header"),
+        }
+    }
+
+    #[test]
+    fn links_of_a_chain_cover_their_own_ranges() {
+        let first_link = vorbis_stream(7, "first", 30, 0);
+        let second_link = vorbis_stream(8, "second", 20, 10000);
+        let first_size: usize = first_link.iter().map(|page| page.page_size).sum();
+        let second_size: usize = second_link.iter().map(|page| page.page_size).sum();
+        let first_page_count = first_link.len();
+        let page_count = first_page_count + second_link.len();
+        let file = parse_pages(first_link.into_iter().chain(second_link).collect());
+
+        let links = find_ogg_vorbis_links(&file);
+        assert_eq!(links.len(), 2);
+
+        let first_samples = audio_granules(30, 0)[29];
+        assert_eq!(links[0].stream_serial_number, 7);
+        assert_eq!(links[0].page_range, 0..first_page_count);
+        assert_eq!(links[0].byte_range, 0..first_size);
+        assert_eq!(links[0].sample_range, 0..first_samples);
+        assert_eq!(links[0].identification, Some((0, 0)));
+
+        // The second link starts at granule position 10000, which is not part of its samples
+        let second_samples = audio_granules(20, 10000)[19] - 10000;
+        assert_eq!(links[1].stream_serial_number, 8);
+        assert_eq!(links[1].page_range, first_page_count..page_count);
+        assert_eq!(links[1].byte_range, first_size..first_size + second_size);
+        assert_eq!(
+            links[1].sample_range,
+            first_samples..first_samples + second_samples
+        );
+        assert_eq!(links[1].identification, Some((first_page_count, 0)));
+        assert_eq!(title(&file, &links[1]), "second");
+    }
+
+    #[test]
+    fn update_link_comments_edits_only_the_chosen_link() {
+        let pages: Vec<OggPage> = vorbis_stream(7, "first", 30, 0)
+            .into_iter()
+            .chain(vorbis_stream(7, "second", 20, 0))
+            .collect();
+        let file = parse_pages(pages.clone());
+        let links = find_ogg_vorbis_links(&file);
+
+        let mut new_comments = HashMap::new();
+        new_comments.insert("TITLE".to_string(), vec!["x".repeat(5000)]);
+        let updated =
+            update_ogg_vorbis_link_comments(file, &links[1], new_comments, usize::MAX).unwrap();
+
+        let updated_links = find_ogg_vorbis_links(&updated);
+        assert_eq!(updated_links.len(), 2);
+        assert_eq!(updated_links[0].page_range, links[0].page_range);
+        for page_index in links[0].page_range.clone() {
+            assert_eq!(updated[page_index].page.buffer, pages[page_index].buffer);
+        }
+        assert_eq!(title(&updated, &updated_links[0]), "first");
+        assert_eq!(title(&updated, &updated_links[1]), "x".repeat(5000));
+        assert_eq!(updated_links[1].sample_range, links[1].sample_range);
+    }
+}
@@ -10,6 +10,7 @@ use crate::ogg_page::OggPage;
 
 const VORBIS_HEAD_MAGIC_SIGNATURE: [u8; 6] = [0x76, 0x6f, 0x72, 0x62, 0x69, 0x73];
 const VORBIS_SETUP_CODEBOOK_MAGIC_SIGNATURE: [u8; 3] = [0x42, 0x43, 0x56];
+const IDENTIFICATION_HEADER_SIZE: usize = 30;
 
 lazy_static! {
     static ref ALLOWED_BLOCK_SIZES: HashSet<usize> = {
@@ -28,9 +29,8 @@ lazy_static! {
 
 /// Represents an error related to the Vorbis format.
 #[derive(Debug)]
-pub struct VorbisFormatError(String);
+pub struct VorbisFormatError(pub(crate) String);
 
-/// A reader for reading bits from a byte array.
 impl fmt::Display for VorbisFormatError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "VorbisFormatError: {}", self.0)
@@ -39,6 +39,10 @@ impl fmt::Display for VorbisFormatError {
 
 impl Error for VorbisFormatError {}
 
+/// A reader for reading bits from a byte array, least significant bit first.
+///
+/// Reading past the end of the data is the end-of-packet condition of the Vorbis specification:
+/// the read fails with a `VorbisFormatError` and the position is left unchanged.
 pub struct BitStreamReader {
     data: Vec<u8>,
     cursor: usize,
@@ -53,7 +57,30 @@ impl BitStreamReader {
         }
     }
 
-    /// Reads a single bit from the stream.
+    /// Returns the position of the next bit to read, in bits from the start of the data.
+    pub fn position(&self) -> usize {
+        self.cursor
+    }
+
+    /// Returns the number of bits left before the end of the data.
+    pub fn remaining_bits(&self) -> usize {
+        (self.data.len() * 8).saturating_sub(self.cursor)
+    }
+
+    /// Fails with an end-of-packet error if fewer than `num_bits` bits are left.
+    fn check_remaining_bits(&self, num_bits: usize) -> Result<(), VorbisFormatError> {
+        if num_bits > self.remaining_bits() {
+            return Err(VorbisFormatError(format!(
+                "End of packet: cannot read {} bits at bit {}, {} bits remaining",
+                num_bits,
+                self.cursor,
+                self.remaining_bits()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Reads a single bit from the stream, which must be within the data.
     fn read_bit(&mut self) -> u8 {
         let byte_index = self.cursor / 8;
         let bit_index = self.cursor % 8;
@@ -63,62 +90,211 @@ impl BitStreamReader {
     }
 
     /// Reads a specified number of bits from the stream as a number.
-    fn read_bits_as_number(&mut self, num_bits: usize) -> u32 {
+    fn read_bits_as_number(&mut self, num_bits: usize) -> Result<u32, VorbisFormatError> {
+        if num_bits > 32 {
+            return Err(VorbisFormatError(format!(
+                "Cannot read {} bits into a 32-bit number",
+                num_bits
+            )));
+        }
+        self.check_remaining_bits(num_bits)?;
+
         let mut result = 0;
         for i in 0..num_bits {
             let bit = self.read_bit();
             result |= (bit as u32) << i;
         }
+        Ok(result)
+    }
+
+    /// Reads an unsigned integer of `x` bits without moving the position.
+    pub fn peek(&mut self, x: usize) -> Result<u32, VorbisFormatError> {
+        let cursor = self.cursor;
+        let result = self.read_bits_as_number(x);
+        self.cursor = cursor;
         result
     }
 
+    /// Moves the position forward by `num_bits` bits.
+    pub fn skip(&mut self, num_bits: usize) -> Result<(), VorbisFormatError> {
+        self.check_remaining_bits(num_bits)?;
+        self.cursor += num_bits;
+        Ok(())
+    }
+
     /// Reads a boolean value from the stream.
-    pub fn read_bool(&mut self) -> bool {
-        self.read_bits_as_number(1) == 1
+    pub fn read_bool(&mut self) -> Result<bool, VorbisFormatError> {
+        Ok(self.read_bits_as_number(1)? == 1)
     }
 
     /// Reads an unsigned integer of `x` bits from the stream.
-    pub fn read_uint_n(&mut self, x: usize) -> u32 {
+    pub fn read_uint_n(&mut self, x: usize) -> Result<u32, VorbisFormatError> {
         self.read_bits_as_number(x)
     }
 
-    pub fn read_uint2(&mut self) -> u8 {
-        self.read_bits_as_number(2) as u8
+    pub fn read_uint2(&mut self) -> Result<u8, VorbisFormatError> {
+        Ok(self.read_bits_as_number(2)? as u8)
     }
 
-    pub fn read_uint3(&mut self) -> u8 {
-        self.read_bits_as_number(3) as u8
+    pub fn read_uint3(&mut self) -> Result<u8, VorbisFormatError> {
+        Ok(self.read_bits_as_number(3)? as u8)
     }
 
-    pub fn read_uint4(&mut self) -> u8 {
-        self.read_bits_as_number(4) as u8
+    pub fn read_uint4(&mut self) -> Result<u8, VorbisFormatError> {
+        Ok(self.read_bits_as_number(4)? as u8)
     }
 
-    pub fn read_uint5(&mut self) -> u8 {
-        self.read_bits_as_number(5) as u8
+    pub fn read_uint5(&mut self) -> Result<u8, VorbisFormatError> {
+        Ok(self.read_bits_as_number(5)? as u8)
     }
 
-    pub fn read_uint6(&mut self) -> u8 {
-        self.read_bits_as_number(6) as u8
+    pub fn read_uint6(&mut self) -> Result<u8, VorbisFormatError> {
+        Ok(self.read_bits_as_number(6)? as u8)
     }
 
-    pub fn read_uint8(&mut self) -> u8 {
-        self.read_bits_as_number(8) as u8
+    pub fn read_uint8(&mut self) -> Result<u8, VorbisFormatError> {
+        Ok(self.read_bits_as_number(8)? as u8)
     }
 
-    pub fn read_uint16(&mut self) -> u16 {
-        self.read_bits_as_number(16) as u16
+    pub fn read_uint16(&mut self) -> Result<u16, VorbisFormatError> {
+        Ok(self.read_bits_as_number(16)? as u16)
     }
 
-    pub fn read_uint24(&mut self) -> u32 {
+    pub fn read_uint24(&mut self) -> Result<u32, VorbisFormatError> {
         self.read_bits_as_number(24)
     }
 
-    pub fn read_uint32(&mut self) -> u32 {
+    pub fn read_uint32(&mut self) -> Result<u32, VorbisFormatError> {
         self.read_bits_as_number(32)
     }
 }
 
+/// A writer for writing bits to a byte array, least significant bit first, as `BitStreamReader`
+/// reads them.
+#[derive(Debug, Default)]
+pub struct BitStreamWriter {
+    data: Vec<u8>,
+    cursor: usize,
+}
+
+impl BitStreamWriter {
+    /// Creates a new empty `BitStreamWriter`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes a single bit to the stream.
+    fn write_bit(&mut self, bit: u8) {
+        if self.cursor % 8 == 0 {
+            self.data.push(0);
+        }
+        let byte_index = self.cursor / 8;
+        let bit_index = self.cursor % 8;
+        self.data[byte_index] |= (bit & 1) << bit_index;
+        self.cursor += 1;
+    }
+
+    /// Writes the lowest `num_bits` bits of a number to the stream.
+    fn write_bits_from_number(&mut self, value: u32, num_bits: usize) {
+        for i in 0..num_bits {
+            self.write_bit(value.checked_shr(i as u32).unwrap_or(0) as u8 & 1);
+        }
+    }
+
+    /// Writes a boolean value to the stream.
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_bits_from_number(value as u32, 1);
+    }
+
+    /// Writes an unsigned integer of `x` bits to the stream. Higher bits of `value` are ignored.
+    pub fn write_uint_n(&mut self, value: u32, x: usize) {
+        self.write_bits_from_number(value, x);
+    }
+
+    pub fn write_uint2(&mut self, value: u8) {
+        self.write_bits_from_number(value as u32, 2);
+    }
+
+    pub fn write_uint3(&mut self, value: u8) {
+        self.write_bits_from_number(value as u32, 3);
+    }
+
+    pub fn write_uint4(&mut self, value: u8) {
+        self.write_bits_from_number(value as u32, 4);
+    }
+
+    pub fn write_uint5(&mut self, value: u8) {
+        self.write_bits_from_number(value as u32, 5);
+    }
+
+    pub fn write_uint6(&mut self, value: u8) {
+        self.write_bits_from_number(value as u32, 6);
+    }
+
+    pub fn write_uint8(&mut self, value: u8) {
+        self.write_bits_from_number(value as u32, 8);
+    }
+
+    pub fn write_uint16(&mut self, value: u16) {
+        self.write_bits_from_number(value as u32, 16);
+    }
+
+    pub fn write_uint24(&mut self, value: u32) {
+        self.write_bits_from_number(value, 24);
+    }
+
+    pub fn write_uint32(&mut self, value: u32) {
+        self.write_bits_from_number(value, 32);
+    }
+
+    /// Pads the stream with zero bits up to the next byte boundary.
+    pub fn align_to_byte(&mut self) {
+        self.cursor = self.data.len() * 8;
+    }
+
+    /// Returns the number of bits written so far, including the alignment padding.
+    pub fn bit_len(&self) -> usize {
+        self.cursor
+    }
+
+    /// Returns the written bytes, the last one padded with zero bits.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Packs a 32-bit float value into a 32-bit integer, the inverse of `float32_unpack`.
+///
+/// The mantissa is normalized to 21 bits as encoders do, so values with more significant bits are
+/// rounded. Infinities are packed as the largest value of their sign and NaN as 0.
+pub fn float32_pack(x: f32) -> u32 {
+    if x == 0.0 || x.is_nan() {
+        return 0;
+    }
+
+    let sign = if x.is_sign_negative() { 0x80000000 } else { 0 };
+    if x.is_infinite() {
+        return sign | 0x7fe00000 | 0x1fffff;
+    }
+
+    // Find the exponent of the highest bit of the value
+    let value = (x as f64).abs();
+    let mut exponent = value.log2().floor() as i32;
+    if 2.0f64.powi(exponent) > value {
+        exponent -= 1;
+    } else if 2.0f64.powi(exponent + 1) <= value {
+        exponent += 1;
+    }
+
+    let mut mantissa = (value * 2.0f64.powi(20 - exponent)).round() as u32;
+    if mantissa == 1 << 21 {
+        mantissa >>= 1;
+        exponent += 1;
+    }
+
+    sign | (((exponent + 768) as u32) << 21) | mantissa
+}
+
 /// Unpacks a 32-bit float value from a given 32-bit integer.
 pub fn float32_unpack(x: u32) -> f32 {
     let mantissa = x & 0x1fffff;
@@ -138,7 +314,7 @@ pub fn lookup1_values(entries: u32, dimensions: u32) -> u32 {
     let mut high = entries;
 
     while low < high {
-        let mid = (low + high + 1) / 2;
+        let mid = (low + high).div_ceil(2);
         let mid_pow = (mid as f64).powi(dimensions as i32);
         if mid_pow <= entries as f64 {
             low = mid;
@@ -173,19 +349,28 @@ impl OggVorbisPage {
             .get_page_segment(segment_index)
             .map_err(|e| VorbisFormatError(e.to_string()))?;
 
-        if !self.is_header_packet(segment_index) {
-            return Err(VorbisFormatError("Invalid magic signature".to_string()));
-        }
+        Self::parse_identification_packet(&array)
+    }
 
-        if !self.is_identification_packet(segment_index) {
+    /// Parses an identification header from a complete packet.
+    pub fn parse_identification_packet(
+        packet: &[u8],
+    ) -> Result<IVorbisIdentificationHeader, VorbisFormatError> {
+        Self::check_header_packet(
+            packet,
+            VorbisHeaderType::Identification,
+            "an identification packet",
+        )?;
+
+        if packet.len() < IDENTIFICATION_HEADER_SIZE {
             return Err(VorbisFormatError(
-                "The packet is not an identification packet".to_string(),
+                "The identification header is truncated".to_string(),
             ));
         }
 
-        let mut reader = BitStreamReader::new(array, 7 * 8);
+        let mut reader = BitStreamReader::new(packet.to_vec(), 7 * 8);
 
-        let vorbis_version = reader.read_uint32();
+        let vorbis_version = reader.read_uint32()?;
         if vorbis_version != 0 {
             return Err(VorbisFormatError(format!(
                 "Unsupported Vorbis version: {}",
@@ -193,17 +378,38 @@ impl OggVorbisPage {
             )));
         }
 
-        let audio_channels = reader.read_uint8();
+        let audio_channels = reader.read_uint8()?;
+
+        let audio_sample_rate = reader.read_uint32()?;
+
+        let bitrate_maximum = reader.read_uint32()?;
+        let bitrate_nominal = reader.read_uint32()?;
+        let bitrate_minimum = reader.read_uint32()?;
 
-        let audio_sample_rate = reader.read_uint32();
+        let blocksize0 = 1 << reader.read_uint4()?;
+        let blocksize1 = 1 << reader.read_uint4()?;
+        Self::check_block_sizes(blocksize0, blocksize1)?;
 
-        let bitrate_maximum = reader.read_uint32();
-        let bitrate_nominal = reader.read_uint32();
-        let bitrate_minimum = reader.read_uint32();
+        let framing_flag = reader.read_bool()?;
+        if !framing_flag {
+            return Err(VorbisFormatError("Framing bit must be nonzero".to_string()));
+        }
 
-        let blocksize0 = 1 << reader.read_uint4();
-        let blocksize1 = 1 << reader.read_uint4();
+        Ok(IVorbisIdentificationHeader {
+            vorbis_version,
+            audio_channels,
+            audio_sample_rate,
+            bitrate_maximum,
+            bitrate_nominal,
+            bitrate_minimum,
+            blocksize0,
+            blocksize1,
+            framing_flag,
+        })
+    }
 
+    /// Checks that the block sizes of an identification header are allowed by the specification.
+    fn check_block_sizes(blocksize0: usize, blocksize1: usize) -> Result<(), VorbisFormatError> {
         if !ALLOWED_BLOCK_SIZES.contains(&blocksize0) {
             return Err(VorbisFormatError(format!(
                 "Invalid blocksize0 values: {}",
@@ -224,27 +430,50 @@ impl OggVorbisPage {
             ));
         }
 
-        let framing_flag = reader.read_bool();
-        if !framing_flag {
-            return Err(VorbisFormatError("Framing bit must be nonzero".to_string()));
+        Ok(())
+    }
+
+    /// Checks that a packet starts with the given header type and the Vorbis magic signature.
+    fn check_header_packet(
+        packet: &[u8],
+        header_type: VorbisHeaderType,
+        name: &str,
+    ) -> Result<(), VorbisFormatError> {
+        if packet.get(1..1 + VORBIS_HEAD_MAGIC_SIGNATURE.len())
+            != Some(&VORBIS_HEAD_MAGIC_SIGNATURE[..])
+        {
+            return Err(VorbisFormatError("Invalid magic signature".to_string()));
         }
 
-        Ok(IVorbisIdentificationHeader {
-            vorbis_version,
-            audio_channels,
-            audio_sample_rate,
-            bitrate_maximum,
-            bitrate_nominal,
-            bitrate_minimum,
-            blocksize0,
-            blocksize1,
-            framing_flag,
-        })
+        if packet[0] != header_type as u8 {
+            return Err(VorbisFormatError(format!("The packet is not {}", name)));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the header type of a complete packet, or `None` if it is not a Vorbis header packet.
+    pub fn header_packet_type(packet: &[u8]) -> Option<VorbisHeaderType> {
+        if packet.get(1..1 + VORBIS_HEAD_MAGIC_SIGNATURE.len())
+            != Some(&VORBIS_HEAD_MAGIC_SIGNATURE[..])
+        {
+            return None;
+        }
+
+        match packet[0] {
+            1 => Some(VorbisHeaderType::Identification),
+            3 => Some(VorbisHeaderType::Comment),
+            5 => Some(VorbisHeaderType::Setup),
+            _ => None,
+        }
     }
 
     /// Checks if the specified segment is a header packet.
     pub fn is_header_packet(&self, segment_index: usize) -> bool {
         let array = self.ogg_page.get_page_segment(segment_index).unwrap();
+        if array.len() < 1 + VORBIS_HEAD_MAGIC_SIGNATURE.len() {
+            return false;
+        }
         for i in 0..VORBIS_HEAD_MAGIC_SIGNATURE.len() {
             if array[i + 1] != VORBIS_HEAD_MAGIC_SIGNATURE[i] {
                 return false;
@@ -256,19 +485,19 @@ impl OggVorbisPage {
     /// Checks if the specified segment is an identification packet.
     pub fn is_identification_packet(&self, segment_index: usize) -> bool {
         let array = self.ogg_page.get_page_segment(segment_index).unwrap();
-        array[0] == VorbisHeaderType::Identification as u8
+        array.first() == Some(&(VorbisHeaderType::Identification as u8))
     }
 
     /// Checks if the specified segment is a comment packet.
     pub fn is_comment_packet(&self, segment_index: usize) -> bool {
         let array = self.ogg_page.get_page_segment(segment_index).unwrap();
-        array[0] == VorbisHeaderType::Comment as u8
+        array.first() == Some(&(VorbisHeaderType::Comment as u8))
     }
 
     /// Checks if the specified segment is a setup packet.
     pub fn is_setup_packet(&self, segment_index: usize) -> bool {
         let array = self.ogg_page.get_page_segment(segment_index).unwrap();
-        array[0] == VorbisHeaderType::Setup as u8
+        array.first() == Some(&(VorbisHeaderType::Setup as u8))
     }
 
     /// Retrieves the comments from the specified segment index.
@@ -281,37 +510,33 @@ impl OggVorbisPage {
             .get_page_segment(segment_index)
             .map_err(|e| VorbisFormatError(e.to_string()))?;
 
-        if !self.is_header_packet(segment_index) {
-            return Err(VorbisFormatError("Invalid magic signature".to_string()));
-        }
+        Self::parse_comment_packet(&array)
+    }
 
-        if !self.is_comment_packet(segment_index) {
-            return Err(VorbisFormatError(
-                "The packet is not a comment packet".to_string(),
-            ));
-        }
+    /// Parses a comment header from a complete packet.
+    pub fn parse_comment_packet(packet: &[u8]) -> Result<IVorbisCommentHeader, VorbisFormatError> {
+        Self::check_header_packet(packet, VorbisHeaderType::Comment, "a comment packet")?;
 
-        let mut reader = BitStreamReader::new(array.clone(), 7 * 8);
+        let mut reader = BitStreamReader::new(packet.to_vec(), 7 * 8);
 
-        let vendor_length = reader.read_uint32();
-        let vendor_array =
-            &array[(reader.cursor / 8)..((reader.cursor / 8) + vendor_length as usize)];
+        let vendor_length = reader.read_uint32()?;
+        let vendor_array = Self::get_comment_field(packet, reader.position() / 8, vendor_length)?;
         let vendor = str::from_utf8(vendor_array)
             .map_err(|_| VorbisFormatError("Invalid UTF-8 sequence".to_string()))?
             .to_string();
-        reader.cursor += vendor_length as usize * 8;
+        reader.skip(vendor_length as usize * 8)?;
 
-        let user_comment_list_length = reader.read_uint32();
+        let user_comment_list_length = reader.read_uint32()?;
 
         let mut comments = std::collections::HashMap::new();
         for _ in 0..user_comment_list_length {
-            let comment_length = reader.read_uint32();
+            let comment_length = reader.read_uint32()?;
             let comment_array =
-                &array[(reader.cursor / 8)..((reader.cursor / 8) + comment_length as usize)];
+                Self::get_comment_field(packet, reader.position() / 8, comment_length)?;
             let comment = str::from_utf8(comment_array)
                 .map_err(|_| VorbisFormatError("Invalid UTF-8 sequence".to_string()))?
                 .to_string();
-            reader.cursor += comment_length as usize * 8;
+            reader.skip(comment_length as usize * 8)?;
 
             let parts: Vec<&str> = comment.splitn(2, '=').collect();
             if parts.len() == 2 {
@@ -324,7 +549,7 @@ impl OggVorbisPage {
             }
         }
 
-        let framing_bit = reader.read_bool();
+        let framing_bit = reader.read_bool()?;
         if !framing_bit {
             return Err(VorbisFormatError("Framing bit must be nonzero".to_string()));
         }
@@ -332,35 +557,49 @@ impl OggVorbisPage {
         Ok(IVorbisCommentHeader { vendor, comments })
     }
 
+    /// Gets a length-prefixed field of a comment header, failing if the packet is truncated.
+    fn get_comment_field(
+        packet: &[u8],
+        position: usize,
+        length: u32,
+    ) -> Result<&[u8], VorbisFormatError> {
+        packet
+            .get(position..position + length as usize)
+            .ok_or_else(|| VorbisFormatError("The comment header is truncated".to_string()))
+    }
+
     /// Parses a setup codebook from the given `BitStreamReader`.
     fn parse_setup_codebook(
         reader: &mut BitStreamReader,
     ) -> Result<IVorbisSetupCodebook, VorbisFormatError> {
         for &byte in &VORBIS_SETUP_CODEBOOK_MAGIC_SIGNATURE {
-            let read_byte = reader.read_uint8();
+            let read_byte = reader.read_uint8()?;
             if read_byte != byte {
                 return Err(VorbisFormatError(format!(
                     "Invalid codebook magic string, expected {:#x}, got {:#x} in position {}",
                     byte,
                     read_byte,
-                    reader.cursor - 8
+                    reader.position() - 8
                 )));
             }
         }
 
-        let dimensions = reader.read_uint16();
-        let entries = reader.read_uint24();
+        let dimensions = reader.read_uint16()?;
+        let entries = reader.read_uint24()?;
 
-        let ordered = reader.read_bool();
+        let ordered = reader.read_bool()?;
+        let mut sparse = false;
 
         let mut codeword_lengths = vec![0; entries as usize];
+        let mut initial_codeword_length = None;
 
         if ordered {
             let mut current_entry = 0;
-            let mut current_length = reader.read_uint5() + 1;
+            let mut current_length = reader.read_uint5()? + 1;
+            initial_codeword_length = Some(current_length);
 
             while current_entry < entries {
-                let number = reader.read_uint_n(ilog(entries - current_entry) as usize);
+                let number = reader.read_uint_n(ilog(entries - current_entry) as usize)?;
                 if current_entry + number > entries {
                     return Err(VorbisFormatError(
                         "Invalid codebook: too many codewords".to_string(),
@@ -370,28 +609,34 @@ impl OggVorbisPage {
                     codeword_lengths[current_entry as usize] = current_length;
                     current_entry += 1;
                 }
-                current_length += 1;
+                current_length = current_length.checked_add(1).ok_or_else(|| {
+                    VorbisFormatError("Invalid codebook: codeword length overflow".to_string())
+                })?;
             }
         } else {
-            let sparse = reader.read_bool();
+            sparse = reader.read_bool()?;
             for i in 0..entries {
                 if sparse {
-                    let flag = reader.read_bool();
+                    let flag = reader.read_bool()?;
                     if flag {
-                        codeword_lengths[i as usize] = reader.read_uint5() + 1;
+                        codeword_lengths[i as usize] = reader.read_uint5()? + 1;
                     } else {
                         codeword_lengths[i as usize] = 0;
                     }
                 } else {
-                    codeword_lengths[i as usize] = reader.read_uint5() + 1;
+                    codeword_lengths[i as usize] = reader.read_uint5()? + 1;
                 }
             }
         }
 
-        let lookup_type: VorbisSetupCodebookLookupType = reader.read_uint4().into();
+        let lookup_type = reader.read_uint4()?;
+        if lookup_type > VorbisSetupCodebookLookupType::Explicitly as u8 {
+            return Err(VorbisFormatError("Unsupported lookup type".to_string()));
+        }
+        let lookup_type: VorbisSetupCodebookLookupType = lookup_type.into();
 
-        let mut minimum_value = None;
-        let mut delta_value = None;
+        let mut packed_minimum_value = None;
+        let mut packed_delta_value = None;
         let mut value_bits = None;
         let mut sequence_p = None;
         let mut multiplicands = None;
@@ -399,33 +644,38 @@ impl OggVorbisPage {
         if lookup_type == VorbisSetupCodebookLookupType::Implicitly
             || lookup_type == VorbisSetupCodebookLookupType::Explicitly
         {
-            minimum_value = Some(float32_unpack(reader.read_uint32()));
-            delta_value = Some(float32_unpack(reader.read_uint32()));
-            value_bits = Some(reader.read_uint4() + 1);
-            sequence_p = Some(reader.read_bool());
+            packed_minimum_value = Some(reader.read_uint32()?);
+            packed_delta_value = Some(reader.read_uint32()?);
+            value_bits = Some(reader.read_uint4()? + 1);
+            sequence_p = Some(reader.read_bool()?);
 
             let lookup_values = if lookup_type == VorbisSetupCodebookLookupType::Implicitly {
                 lookup1_values(entries, dimensions as u32)
             } else {
-                entries * (dimensions as u32)
+                entries.checked_mul(dimensions as u32).ok_or_else(|| {
+                    VorbisFormatError("Invalid codebook: too many lookup values".to_string())
+                })?
             };
 
             multiplicands = Some(
                 (0..lookup_values)
                     .map(|_| reader.read_uint_n(value_bits.unwrap() as usize))
-                    .collect(),
+                    .collect::<Result<_, _>>()?,
             );
-        } else if lookup_type > VorbisSetupCodebookLookupType::Explicitly {
-            return Err(VorbisFormatError("Unsupported lookup type".to_string()));
         }
 
         Ok(IVorbisSetupCodebook {
             dimensions,
             entries,
+            ordered,
+            sparse,
+            initial_codeword_length,
             codeword_lengths,
             lookup_type,
-            minimum_value,
-            delta_value,
+            minimum_value: packed_minimum_value.map(float32_unpack),
+            delta_value: packed_delta_value.map(float32_unpack),
+            packed_minimum_value,
+            packed_delta_value,
             value_bits,
             sequence_p,
             multiplicands,
@@ -435,19 +685,19 @@ impl OggVorbisPage {
     /// Parses a floor type 0 from the given `BitStreamReader`.
     fn parse_floor_type0(
         reader: &mut BitStreamReader,
-        codebook_count: u8,
+        codebook_count: u16,
     ) -> Result<IVorbisFloorType0, VorbisFormatError> {
-        let order = reader.read_uint8();
-        let rate = reader.read_uint16();
-        let bark_map_size = reader.read_uint16();
-        let amplitude_bits = reader.read_uint6();
-        let amplitude_offset = reader.read_uint8();
-        let number_of_books = reader.read_uint4() + 1;
+        let order = reader.read_uint8()?;
+        let rate = reader.read_uint16()?;
+        let bark_map_size = reader.read_uint16()?;
+        let amplitude_bits = reader.read_uint6()?;
+        let amplitude_offset = reader.read_uint8()?;
+        let number_of_books = reader.read_uint4()? + 1;
 
         let mut book_list = Vec::new();
         for _ in 0..number_of_books {
-            let book = reader.read_uint8();
-            if book > codebook_count {
+            let book = reader.read_uint8()?;
+            if book as u16 > codebook_count {
                 return Err(VorbisFormatError(
                     "Invalid book number in floor0_book_list".to_string(),
                 ));
@@ -470,33 +720,37 @@ impl OggVorbisPage {
     fn parse_floor_type1(
         reader: &mut BitStreamReader,
     ) -> Result<IVorbisFloorType1, VorbisFormatError> {
-        let partitions = reader.read_uint5();
+        let partitions = reader.read_uint5()?;
 
         let mut partition_class_list = vec![0; partitions as usize];
         for i in 0..partitions {
-            partition_class_list[i as usize] = reader.read_uint4();
+            partition_class_list[i as usize] = reader.read_uint4()?;
         }
 
-        let maximum_class = *partition_class_list.iter().max().unwrap();
-
-        let mut class_dimensions = vec![0; (maximum_class + 1) as usize];
-        let mut class_subclasses = vec![0; (maximum_class + 1) as usize];
-        let mut class_masterbooks = vec![-1; (maximum_class + 1) as usize];
-        let mut subclass_books = vec![vec![-1; 8]; (maximum_class + 1) as usize];
-
-        for i in 0..=maximum_class {
-            class_dimensions[i as usize] = reader.read_uint3() + 1;
-            class_subclasses[i as usize] = reader.read_uint2();
-            if class_subclasses[i as usize] > 0 {
-                class_masterbooks[i as usize] = reader.read_uint8() as i32;
+        // A floor without partitions has no class either
+        let class_count = partition_class_list
+            .iter()
+            .max()
+            .map_or(0, |&maximum_class| maximum_class as usize + 1);
+
+        let mut class_dimensions = vec![0; class_count];
+        let mut class_subclasses = vec![0; class_count];
+        let mut class_masterbooks = vec![-1; class_count];
+        let mut subclass_books = vec![vec![-1; 8]; class_count];
+
+        for i in 0..class_count {
+            class_dimensions[i] = reader.read_uint3()? + 1;
+            class_subclasses[i] = reader.read_uint2()?;
+            if class_subclasses[i] > 0 {
+                class_masterbooks[i] = reader.read_uint8()? as i32;
             }
-            for j in 0..(1 << class_subclasses[i as usize]) {
-                subclass_books[i as usize][j as usize] = reader.read_uint8() as i32 - 1;
+            for j in 0..(1 << class_subclasses[i]) {
+                subclass_books[i][j as usize] = reader.read_uint8()? as i32 - 1;
             }
         }
 
-        let multiplier = reader.read_uint2() + 1;
-        let rangebits = reader.read_uint4();
+        let multiplier = reader.read_uint2()? + 1;
+        let rangebits = reader.read_uint4()?;
 
         let mut x_list = vec![0, 1 << rangebits];
         let mut values = 2;
@@ -504,7 +758,7 @@ impl OggVorbisPage {
         for i in 0..partitions {
             let current_class_number = partition_class_list[i as usize];
             for _ in 0..class_dimensions[current_class_number as usize] {
-                x_list.push(reader.read_uint_n(rangebits as usize) as i32);
+                x_list.push(reader.read_uint_n(rangebits as usize)? as i32);
                 values += 1;
             }
         }
@@ -539,9 +793,9 @@ impl OggVorbisPage {
     /// Parses a residue from the given `BitStreamReader`.
     fn parse_residue(
         reader: &mut BitStreamReader,
-        codebook_count: u8,
+        codebook_count: u16,
     ) -> Result<IVorbisResidue, VorbisFormatError> {
-        let residue_type = reader.read_uint16();
+        let residue_type = reader.read_uint16()?;
         if residue_type > 2 {
             return Err(VorbisFormatError(format!(
                 "Invalid residue type {}",
@@ -549,30 +803,32 @@ impl OggVorbisPage {
             )));
         }
 
-        let begin = reader.read_uint24();
-        let end = reader.read_uint24();
-        let partition_size = reader.read_uint24() + 1;
-        let classifications = reader.read_uint6() + 1;
-        let classbook = reader.read_uint8();
+        let begin = reader.read_uint24()?;
+        let end = reader.read_uint24()?;
+        let partition_size = reader.read_uint24()? + 1;
+        let classifications = reader.read_uint6()? + 1;
+        let classbook = reader.read_uint8()?;
 
-        if classbook >= codebook_count {
+        if classbook as u16 >= codebook_count {
             return Err(VorbisFormatError("Invalid classbook number".to_string()));
         }
 
         let mut cascade = vec![0; classifications as usize];
+        let mut cascade_bitflags = vec![false; classifications as usize];
         for i in 0..classifications {
-            let low_bits = reader.read_uint3();
-            let bitflag = reader.read_bool();
-            let high_bits = if bitflag { reader.read_uint5() } else { 0 };
+            let low_bits = reader.read_uint3()?;
+            let bitflag = reader.read_bool()?;
+            let high_bits = if bitflag { reader.read_uint5()? } else { 0 };
             cascade[i as usize] = (high_bits << 3) + low_bits;
+            cascade_bitflags[i as usize] = bitflag;
         }
 
         let mut books = vec![vec![-1; 8]; classifications as usize];
         for i in 0..classifications {
             for j in 0..8 {
                 if (cascade[i as usize] & (1 << j)) != 0 {
-                    let book = reader.read_uint8();
-                    if book >= codebook_count {
+                    let book = reader.read_uint8()?;
+                    if book as u16 >= codebook_count {
                         return Err(VorbisFormatError(
                             "Invalid book number in residue_books".to_string(),
                         ));
@@ -590,6 +846,7 @@ impl OggVorbisPage {
             classifications,
             classbook,
             cascade,
+            cascade_bitflags,
             books,
         })
     }
@@ -602,32 +859,32 @@ impl OggVorbisPage {
         residue_count: u8,
     ) -> Result<IVorbisMapping, VorbisFormatError> {
         // Step 2a: Read the mapping type (16 bits)
-        let mapping_type = reader.read_uint16();
+        let mapping_type = reader.read_uint16()?;
         if mapping_type != 0 {
             return Err(VorbisFormatError("Unsupported mapping type".to_string()));
         }
 
         // Step 2c-i: Read 1 bit as a boolean flag for submaps
-        let submaps_flag = reader.read_bool();
+        let submaps_flag = reader.read_bool()?;
         let submaps = if submaps_flag {
-            reader.read_uint4() + 1
+            reader.read_uint4()? + 1
         } else {
             1
         };
 
         // Step 2c-ii: Read 1 bit as a boolean flag for coupling steps
-        let coupling_flag = reader.read_bool();
+        let coupling_flag = reader.read_bool()?;
         let mut coupling_steps = 0;
         let mut magnitude = Vec::new();
         let mut angle = Vec::new();
 
         if coupling_flag {
-            coupling_steps = reader.read_uint8() + 1;
-            let coupling_bits = ilog(audio_channels - 1);
+            coupling_steps = reader.read_uint8()? + 1;
+            let coupling_bits = ilog(audio_channels.saturating_sub(1));
 
             for _ in 0..coupling_steps {
-                let magnitude_val = reader.read_uint_n(coupling_bits as usize) as u8;
-                let angle_val = reader.read_uint_n(coupling_bits as usize) as u8;
+                let magnitude_val = reader.read_uint_n(coupling_bits as usize)? as u8;
+                let angle_val = reader.read_uint_n(coupling_bits as usize)? as u8;
                 if magnitude_val == angle_val
                     || magnitude_val >= audio_channels
                     || angle_val >= audio_channels
@@ -642,7 +899,7 @@ impl OggVorbisPage {
         }
 
         // Step 2c-iii: Read 2 bits reserved field; if nonzero, the stream is undecodable
-        let reserved = reader.read_uint2();
+        let reserved = reader.read_uint2()?;
         if reserved != 0 {
             return Err(VorbisFormatError(
                 "Invalid reserved field in mapping".to_string(),
@@ -653,7 +910,7 @@ impl OggVorbisPage {
         let mut mux = Vec::new();
         if submaps > 1 {
             for _ in 0..audio_channels {
-                let mux_value = reader.read_uint4();
+                let mux_value = reader.read_uint4()?;
                 if mux_value >= submaps {
                     return Err(VorbisFormatError("Invalid multiplex value".to_string()));
                 }
@@ -662,16 +919,17 @@ impl OggVorbisPage {
         }
 
         // Step 2c-v: Read floor and residue numbers for each submap
+        let mut submap_times = Vec::new();
         let mut submap_floors = Vec::new();
         let mut submap_residues = Vec::new();
         for _ in 0..submaps {
-            reader.read_uint8(); // Discard 8 bits (unused time configuration placeholder)
-            let floor_number = reader.read_uint8();
+            submap_times.push(reader.read_uint8()?); // Unused time configuration placeholder
+            let floor_number = reader.read_uint8()?;
             if floor_number >= floor_count {
                 return Err(VorbisFormatError("Invalid floor number".to_string()));
             }
             submap_floors.push(floor_number);
-            let residue_number = reader.read_uint8();
+            let residue_number = reader.read_uint8()?;
             if residue_number >= residue_count {
                 return Err(VorbisFormatError("Invalid residue number".to_string()));
             }
@@ -679,11 +937,13 @@ impl OggVorbisPage {
         }
 
         Ok(IVorbisMapping {
+            submaps_flag,
             submaps,
             coupling_steps,
             magnitude,
             angle,
             mux,
+            submap_times,
             submap_floors,
             submap_residues,
         })
@@ -691,10 +951,10 @@ impl OggVorbisPage {
 
     /// Parses a mode from the given `BitStreamReader`.
     fn parse_mode(reader: &mut BitStreamReader) -> Result<IVorbisMode, VorbisFormatError> {
-        let blockflag = reader.read_bool();
-        let windowtype = reader.read_uint16();
-        let transformtype = reader.read_uint16();
-        let mapping = reader.read_uint8();
+        let blockflag = reader.read_bool()?;
+        let windowtype = reader.read_uint16()?;
+        let transformtype = reader.read_uint16()?;
+        let mapping = reader.read_uint8()?;
 
         if windowtype != 0 || transformtype != 0 {
             return Err(VorbisFormatError(
@@ -721,36 +981,36 @@ impl OggVorbisPage {
             .get_page_segment(segment_index)
             .map_err(|e| VorbisFormatError(e.to_string()))?;
 
-        if !self.is_header_packet(segment_index) {
-            return Err(VorbisFormatError("Invalid magic signature".to_string()));
-        }
+        Self::parse_setup_packet(&array, audio_channels)
+    }
 
-        if !self.is_setup_packet(segment_index) {
-            return Err(VorbisFormatError(
-                "The packet is not a setup packet".to_string(),
-            ));
-        }
+    /// Parses a setup header from a complete packet, which may span several pages.
+    pub fn parse_setup_packet(
+        packet: &[u8],
+        audio_channels: u8,
+    ) -> Result<IVorbisSetupHeader, VorbisFormatError> {
+        Self::check_header_packet(packet, VorbisHeaderType::Setup, "a setup packet")?;
 
-        let mut reader = BitStreamReader::new(array, 7 * 8);
+        let mut reader = BitStreamReader::new(packet.to_vec(), 7 * 8);
 
         let mut codebooks = Vec::new();
-        let codebook_count = reader.read_uint8() + 1;
+        let codebook_count = reader.read_uint8()? as u16 + 1;
         for _ in 0..codebook_count {
             codebooks.push(Self::parse_setup_codebook(&mut reader)?);
         }
 
-        let time_count = reader.read_uint6() + 1;
+        let time_count = reader.read_uint6()? + 1;
         for _ in 0..time_count {
-            let time_type = reader.read_uint16();
+            let time_type = reader.read_uint16()?;
             if time_type != 0 {
                 return Err(VorbisFormatError("Invalid time type".to_string()));
             }
         }
 
-        let floor_count = reader.read_uint6() + 1;
+        let floor_count = reader.read_uint6()? + 1;
         let mut floors = Vec::new();
         for _ in 0..floor_count {
-            let floor_type = reader.read_uint16();
+            let floor_type = reader.read_uint16()?;
             if floor_type == 0 {
                 floors.push(IVorbisFloor::Type0(Self::parse_floor_type0(
                     &mut reader,
@@ -763,13 +1023,13 @@ impl OggVorbisPage {
             }
         }
 
-        let residue_count = reader.read_uint6() + 1;
+        let residue_count = reader.read_uint6()? + 1;
         let mut residues = Vec::new();
         for _ in 0..residue_count {
             residues.push(Self::parse_residue(&mut reader, codebook_count)?);
         }
 
-        let mapping_count = reader.read_uint6() + 1;
+        let mapping_count = reader.read_uint6()? + 1;
         let mut mappings = Vec::new();
         for _ in 0..mapping_count {
             mappings.push(Self::parse_mapping(
@@ -780,19 +1040,20 @@ impl OggVorbisPage {
             )?);
         }
 
-        let mode_count = reader.read_uint6() + 1;
+        let mode_count = reader.read_uint6()? + 1;
         let mut modes = Vec::new();
         for _ in 0..mode_count {
             modes.push(Self::parse_mode(&mut reader)?);
         }
 
-        let framing_bit = reader.read_bool();
+        let framing_bit = reader.read_bool()?;
         if !framing_bit {
             return Err(VorbisFormatError("Framing bit must be nonzero".to_string()));
         }
 
         Ok(IVorbisSetupHeader {
             codebooks,
+            time_count,
             floors,
             residues,
             mappings,
@@ -819,6 +1080,62 @@ impl OggVorbisPage {
         Ok(new_page)
     }
 
+    /// Builds an identification header packet, the inverse of `parse_identification_packet`.
+    ///
+    /// The framing bit is always set, as a packet without it is not a valid header.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The identification header.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<u8>, VorbisFormatError>` - The 30 byte packet, or an error if the header holds
+    ///   values that cannot be stored or are not allowed by the specification.
+    pub fn build_identification(
+        header: &IVorbisIdentificationHeader,
+    ) -> Result<Vec<u8>, VorbisFormatError> {
+        if header.vorbis_version != 0 {
+            return Err(VorbisFormatError(format!(
+                "Unsupported Vorbis version: {}",
+                header.vorbis_version
+            )));
+        }
+
+        if header.audio_channels == 0 {
+            return Err(VorbisFormatError(
+                "The channel count must be nonzero".to_string(),
+            ));
+        }
+
+        if header.audio_sample_rate == 0 {
+            return Err(VorbisFormatError(
+                "The sample rate must be nonzero".to_string(),
+            ));
+        }
+
+        Self::check_block_sizes(header.blocksize0, header.blocksize1)?;
+
+        let mut result = Vec::with_capacity(IDENTIFICATION_HEADER_SIZE);
+        result.push(VorbisHeaderType::Identification as u8);
+        result.extend_from_slice(&VORBIS_HEAD_MAGIC_SIGNATURE);
+        result.extend_from_slice(&header.vorbis_version.to_le_bytes());
+        result.push(header.audio_channels);
+        result.extend_from_slice(&header.audio_sample_rate.to_le_bytes());
+        result.extend_from_slice(&header.bitrate_maximum.to_le_bytes());
+        result.extend_from_slice(&header.bitrate_nominal.to_le_bytes());
+        result.extend_from_slice(&header.bitrate_minimum.to_le_bytes());
+
+        // Both block sizes are stored as their exponent, blocksize0 in the low nibble
+        let blocksize0_exponent = header.blocksize0.trailing_zeros() as u8;
+        let blocksize1_exponent = header.blocksize1.trailing_zeros() as u8;
+        result.push(blocksize0_exponent | (blocksize1_exponent << 4));
+
+        result.push(1); // Framing bit
+
+        Ok(result)
+    }
+
     pub fn build_comments(header: IVorbisCommentHeader) -> Vec<u8> {
         let mut result = Vec::new();
         result.push(3); // Comment packet type
@@ -842,6 +1159,494 @@ impl OggVorbisPage {
 
         result
     }
+
+    /// Builds a setup header packet, the inverse of `parse_setup_packet`.
+    ///
+    /// A packet parsed by `parse_setup_packet` is written back bit for bit, up to the byte holding
+    /// the framing bit; anything after it is left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The setup header.
+    /// * `audio_channels` - The number of audio channels, from the identification header.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<u8>, VorbisFormatError>` - The packet, or an error if the header cannot be
+    ///   stored.
+    pub fn build_setup(
+        header: &IVorbisSetupHeader,
+        audio_channels: u8,
+    ) -> Result<Vec<u8>, VorbisFormatError> {
+        let mut writer = BitStreamWriter::new();
+        writer.write_uint8(VorbisHeaderType::Setup as u8);
+        for &byte in &VORBIS_HEAD_MAGIC_SIGNATURE {
+            writer.write_uint8(byte);
+        }
+
+        Self::write_setup_count(&mut writer, "codebooks", header.codebooks.len(), 8)?;
+        for codebook in &header.codebooks {
+            Self::build_setup_codebook(&mut writer, codebook)?;
+        }
+
+        Self::write_setup_count(&mut writer, "times", header.time_count as usize, 6)?;
+        for _ in 0..header.time_count {
+            writer.write_uint16(0);
+        }
+
+        Self::write_setup_count(&mut writer, "floors", header.floors.len(), 6)?;
+        for floor in &header.floors {
+            match floor {
+                IVorbisFloor::Type0(floor) => {
+                    writer.write_uint16(0);
+                    Self::build_floor_type0(&mut writer, floor)?;
+                }
+                IVorbisFloor::Type1(floor) => {
+                    writer.write_uint16(1);
+                    Self::build_floor_type1(&mut writer, floor)?;
+                }
+            }
+        }
+
+        Self::write_setup_count(&mut writer, "residues", header.residues.len(), 6)?;
+        for residue in &header.residues {
+            Self::build_residue(&mut writer, residue)?;
+        }
+
+        Self::write_setup_count(&mut writer, "mappings", header.mappings.len(), 6)?;
+        for mapping in &header.mappings {
+            Self::build_mapping(&mut writer, mapping, audio_channels)?;
+        }
+
+        Self::write_setup_count(&mut writer, "modes", header.modes.len(), 6)?;
+        for mode in &header.modes {
+            Self::build_mode(&mut writer, mode);
+        }
+
+        writer.write_bool(header.framing_bit);
+
+        Ok(writer.into_bytes())
+    }
+
+    /// Writes a count stored minus one, failing if it does not fit in the given number of bits.
+    fn write_setup_count(
+        writer: &mut BitStreamWriter,
+        name: &str,
+        count: usize,
+        num_bits: usize,
+    ) -> Result<(), VorbisFormatError> {
+        if count == 0 || count > 1 << num_bits {
+            return Err(VorbisFormatError(format!(
+                "Invalid number of {}: {}",
+                name, count
+            )));
+        }
+        writer.write_uint_n(count as u32 - 1, num_bits);
+        Ok(())
+    }
+
+    /// Writes a field, failing if it is negative or does not fit in the given number of bits.
+    fn write_setup_field(
+        writer: &mut BitStreamWriter,
+        name: &str,
+        value: i64,
+        num_bits: usize,
+    ) -> Result<(), VorbisFormatError> {
+        if value < 0 || value >> num_bits != 0 {
+            return Err(VorbisFormatError(format!(
+                "Invalid {}: {} does not fit in {} bits",
+                name, value, num_bits
+            )));
+        }
+        writer.write_uint_n(value as u32, num_bits);
+        Ok(())
+    }
+
+    /// Writes a field stored minus one, failing if it is zero or does not fit in the given
+    /// number of bits.
+    fn write_setup_length(
+        writer: &mut BitStreamWriter,
+        name: &str,
+        value: i64,
+        num_bits: usize,
+    ) -> Result<(), VorbisFormatError> {
+        if value == 0 {
+            return Err(VorbisFormatError(format!("Invalid {}: 0", name)));
+        }
+        Self::write_setup_field(writer, name, value - 1, num_bits)
+    }
+
+    /// Writes a setup codebook to the given `BitStreamWriter`.
+    fn build_setup_codebook(
+        writer: &mut BitStreamWriter,
+        codebook: &IVorbisSetupCodebook,
+    ) -> Result<(), VorbisFormatError> {
+        let entries = codebook.entries;
+        if codebook.codeword_lengths.len() != entries as usize {
+            return Err(VorbisFormatError(
+                "Invalid codebook: the codeword lengths do not match the entries".to_string(),
+            ));
+        }
+
+        for &byte in &VORBIS_SETUP_CODEBOOK_MAGIC_SIGNATURE {
+            writer.write_uint8(byte);
+        }
+        writer.write_uint16(codebook.dimensions);
+        Self::write_setup_field(writer, "codebook entries", entries.into(), 24)?;
+        writer.write_bool(codebook.ordered);
+
+        if codebook.ordered {
+            let lengths = &codebook.codeword_lengths;
+            if lengths.contains(&0) || lengths.windows(2).any(|pair| pair[0] > pair[1]) {
+                return Err(VorbisFormatError(
+                    "Invalid codebook: ordered codeword lengths must be nonzero and increasing"
+                        .to_string(),
+                ));
+            }
+
+            let first_length = lengths.first().map_or(1, |&length| length);
+            let initial_length = codebook.initial_codeword_length.unwrap_or(first_length);
+            if initial_length > first_length {
+                return Err(VorbisFormatError(
+                    "Invalid codebook: the initial codeword length exceeds the first one"
+                        .to_string(),
+                ));
+            }
+
+            let mut current_entry = 0;
+            let mut current_length = initial_length as u32;
+            Self::write_setup_length(writer, "codeword length", current_length.into(), 5)?;
+
+            while current_entry < entries {
+                let number = lengths[current_entry as usize..]
+                    .iter()
+                    .take_while(|&&length| length as u32 == current_length)
+                    .count() as u32;
+                writer.write_uint_n(number, ilog(entries - current_entry) as usize);
+                current_entry += number;
+                current_length += 1;
+            }
+        } else {
+            writer.write_bool(codebook.sparse);
+            for &length in &codebook.codeword_lengths {
+                if codebook.sparse {
+                    writer.write_bool(length != 0);
+                    if length == 0 {
+                        continue;
+                    }
+                } else if length == 0 {
+                    return Err(VorbisFormatError(
+                        "Invalid codebook: unused entries require a sparse codebook".to_string(),
+                    ));
+                }
+                Self::write_setup_length(writer, "codeword length", length.into(), 5)?;
+            }
+        }
+
+        writer.write_uint4(codebook.lookup_type as u8);
+
+        if codebook.lookup_type != VorbisSetupCodebookLookupType::None {
+            let (
+                Some(packed_minimum_value),
+                Some(packed_delta_value),
+                Some(value_bits),
+                Some(sequence_p),
+                Some(multiplicands),
+            ) = (
+                codebook.packed_minimum_value,
+                codebook.packed_delta_value,
+                codebook.value_bits,
+                codebook.sequence_p,
+                &codebook.multiplicands,
+            )
+            else {
+                return Err(VorbisFormatError(
+                    "Invalid codebook: the lookup table is incomplete".to_string(),
+                ));
+            };
+
+            let lookup_values = match codebook.lookup_type {
+                VorbisSetupCodebookLookupType::Implicitly => {
+                    lookup1_values(entries, codebook.dimensions as u32)
+                }
+                _ => entries
+                    .checked_mul(codebook.dimensions as u32)
+                    .ok_or_else(|| {
+                        VorbisFormatError("Invalid codebook: too many lookup values".to_string())
+                    })?,
+            };
+            if multiplicands.len() != lookup_values as usize {
+                return Err(VorbisFormatError(format!(
+                    "Invalid codebook: expected {} multiplicands, got {}",
+                    lookup_values,
+                    multiplicands.len()
+                )));
+            }
+
+            writer.write_uint32(packed_minimum_value);
+            writer.write_uint32(packed_delta_value);
+            Self::write_setup_length(writer, "value bits", value_bits.into(), 4)?;
+            writer.write_bool(sequence_p);
+            for &multiplicand in multiplicands {
+                Self::write_setup_field(
+                    writer,
+                    "multiplicand",
+                    multiplicand.into(),
+                    value_bits as usize,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes a floor type 0 to the given `BitStreamWriter`.
+    fn build_floor_type0(
+        writer: &mut BitStreamWriter,
+        floor: &IVorbisFloorType0,
+    ) -> Result<(), VorbisFormatError> {
+        if floor.book_list.is_empty() || floor.book_list.len() > 16 {
+            return Err(VorbisFormatError(format!(
+                "Invalid number of floor0 books: {}",
+                floor.book_list.len()
+            )));
+        }
+
+        writer.write_uint8(floor.order);
+        writer.write_uint16(floor.rate);
+        writer.write_uint16(floor.bark_map_size);
+        Self::write_setup_field(
+            writer,
+            "floor0 amplitude bits",
+            floor.amplitude_bits.into(),
+            6,
+        )?;
+        writer.write_uint8(floor.amplitude_offset);
+        writer.write_uint4(floor.book_list.len() as u8 - 1);
+        for &book in &floor.book_list {
+            writer.write_uint8(book);
+        }
+
+        Ok(())
+    }
+
+    /// Writes a floor type 1 to the given `BitStreamWriter`.
+    fn build_floor_type1(
+        writer: &mut BitStreamWriter,
+        floor: &IVorbisFloorType1,
+    ) -> Result<(), VorbisFormatError> {
+        // The class count is implied by the highest partition class
+        let class_count = floor.class_dimensions.len();
+        let implied_class_count = floor
+            .partition_class_list
+            .iter()
+            .max()
+            .map_or(0, |&maximum_class| maximum_class as usize + 1);
+        if class_count != implied_class_count
+            || floor.class_subclasses.len() != class_count
+            || floor.class_masterbooks.len() != class_count
+            || floor.subclass_books.len() != class_count
+        {
+            return Err(VorbisFormatError(
+                "Invalid floor1: the partition classes do not match the class lists".to_string(),
+            ));
+        }
+
+        Self::write_setup_field(
+            writer,
+            "floor1 partitions",
+            floor.partition_class_list.len() as i64,
+            5,
+        )?;
+        for &class in &floor.partition_class_list {
+            Self::write_setup_field(writer, "floor1 partition class", class.into(), 4)?;
+        }
+
+        for class in 0..class_count {
+            let class_dimensions = floor.class_dimensions[class];
+            Self::write_setup_length(
+                writer,
+                "floor1 class dimensions",
+                class_dimensions.into(),
+                3,
+            )?;
+            let class_subclasses = floor.class_subclasses[class];
+            Self::write_setup_field(
+                writer,
+                "floor1 class subclasses",
+                class_subclasses.into(),
+                2,
+            )?;
+            if class_subclasses > 0 {
+                let masterbook = floor.class_masterbooks[class];
+                Self::write_setup_field(writer, "floor1 class masterbook", masterbook.into(), 8)?;
+            }
+
+            let subclass_books = &floor.subclass_books[class];
+            if subclass_books.len() < 1 << class_subclasses {
+                return Err(VorbisFormatError(format!(
+                    "Invalid floor1: class {} has fewer than {} subclass books",
+                    class,
+                    1 << class_subclasses
+                )));
+            }
+            for &book in &subclass_books[..1 << class_subclasses] {
+                Self::write_setup_field(writer, "floor1 subclass book", i64::from(book) + 1, 8)?;
+            }
+        }
+
+        Self::write_setup_length(writer, "floor1 multiplier", floor.multiplier.into(), 2)?;
+        Self::write_setup_field(writer, "floor1 range bits", floor.rangebits.into(), 4)?;
+
+        // The first two X values are implied by the range bits
+        let x_list_length: usize = floor
+            .partition_class_list
+            .iter()
+            .map(|&class| floor.class_dimensions[class as usize] as usize)
+            .sum();
+        if floor.x_list.len() != x_list_length + 2 {
+            return Err(VorbisFormatError(
+                "Invalid floor1: the X list does not match the partition classes".to_string(),
+            ));
+        }
+        for &x in &floor.x_list[2..] {
+            Self::write_setup_field(writer, "floor1 X value", x.into(), floor.rangebits as usize)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a residue to the given `BitStreamWriter`.
+    fn build_residue(
+        writer: &mut BitStreamWriter,
+        residue: &IVorbisResidue,
+    ) -> Result<(), VorbisFormatError> {
+        let classifications = residue.cascade.len();
+        if classifications == 0
+            || classifications > 64
+            || residue.cascade_bitflags.len() != classifications
+            || residue.books.len() != classifications
+        {
+            return Err(VorbisFormatError(
+                "Invalid residue: the cascades do not match the classifications".to_string(),
+            ));
+        }
+
+        writer.write_uint16(residue.residue_type);
+        Self::write_setup_field(writer, "residue begin", residue.begin.into(), 24)?;
+        Self::write_setup_field(writer, "residue end", residue.end.into(), 24)?;
+        Self::write_setup_length(
+            writer,
+            "residue partition size",
+            residue.partition_size.into(),
+            24,
+        )?;
+        writer.write_uint6(classifications as u8 - 1);
+        writer.write_uint8(residue.classbook);
+
+        for (&cascade, &bitflag) in residue.cascade.iter().zip(&residue.cascade_bitflags) {
+            if !bitflag && cascade >> 3 != 0 {
+                return Err(VorbisFormatError(
+                    "Invalid residue: the high bits of a cascade require its bitflag".to_string(),
+                ));
+            }
+            writer.write_uint3(cascade & 0x7);
+            writer.write_bool(bitflag);
+            if bitflag {
+                writer.write_uint5(cascade >> 3);
+            }
+        }
+
+        for (&cascade, books) in residue.cascade.iter().zip(&residue.books) {
+            for j in (0..8).filter(|j| (cascade & (1 << j)) != 0) {
+                let book = books.get(j).copied().ok_or_else(|| {
+                    VorbisFormatError(
+                        "Invalid residue: a cascade bit has no matching book".to_string(),
+                    )
+                })?;
+                Self::write_setup_field(writer, "residue book", book.into(), 8)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes a mapping to the given `BitStreamWriter`.
+    fn build_mapping(
+        writer: &mut BitStreamWriter,
+        mapping: &IVorbisMapping,
+        audio_channels: u8,
+    ) -> Result<(), VorbisFormatError> {
+        let submaps = mapping.submaps as usize;
+        if submaps == 0
+            || submaps > 16
+            || (submaps > 1 && !mapping.submaps_flag)
+            || (submaps > 1 && mapping.mux.len() != audio_channels as usize)
+            || mapping.submap_times.len() != submaps
+            || mapping.submap_floors.len() != submaps
+            || mapping.submap_residues.len() != submaps
+        {
+            return Err(VorbisFormatError(
+                "Invalid mapping: the submap lists do not match the submaps".to_string(),
+            ));
+        }
+
+        let coupling_steps = mapping.coupling_steps as usize;
+        if mapping.magnitude.len() != coupling_steps || mapping.angle.len() != coupling_steps {
+            return Err(VorbisFormatError(
+                "Invalid mapping: the coupling channels do not match the coupling steps"
+                    .to_string(),
+            ));
+        }
+
+        // Mapping type, only type 0 is defined
+        writer.write_uint16(0);
+
+        writer.write_bool(mapping.submaps_flag);
+        if mapping.submaps_flag {
+            writer.write_uint4(submaps as u8 - 1);
+        }
+
+        writer.write_bool(coupling_steps > 0);
+        if coupling_steps > 0 {
+            writer.write_uint8(coupling_steps as u8 - 1);
+            let coupling_bits = ilog(audio_channels.saturating_sub(1)) as usize;
+            for (&magnitude, &angle) in mapping.magnitude.iter().zip(&mapping.angle) {
+                Self::write_setup_field(
+                    writer,
+                    "mapping magnitude",
+                    magnitude.into(),
+                    coupling_bits,
+                )?;
+                Self::write_setup_field(writer, "mapping angle", angle.into(), coupling_bits)?;
+            }
+        }
+
+        // Reserved field
+        writer.write_uint2(0);
+
+        if submaps > 1 {
+            for &mux in &mapping.mux {
+                Self::write_setup_field(writer, "mapping mux", mux.into(), 4)?;
+            }
+        }
+
+        for submap in 0..submaps {
+            writer.write_uint8(mapping.submap_times[submap]);
+            writer.write_uint8(mapping.submap_floors[submap]);
+            writer.write_uint8(mapping.submap_residues[submap]);
+        }
+
+        Ok(())
+    }
+
+    /// Writes a mode to the given `BitStreamWriter`.
+    fn build_mode(writer: &mut BitStreamWriter, mode: &IVorbisMode) {
+        writer.write_bool(mode.blockflag);
+        writer.write_uint16(mode.windowtype);
+        writer.write_uint16(mode.transformtype);
+        writer.write_uint8(mode.mapping);
+    }
 }
 
 /// Calculates the integer logarithm base 2 of a number.
@@ -852,7 +1657,7 @@ pub fn ilog<T: PrimInt>(x: T) -> T {
 }
 
 /// Represents the different types of Vorbis headers.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VorbisHeaderType {
     Identification = 1,
     Comment = 3,
@@ -884,6 +1689,8 @@ pub struct IVorbisCommentHeader {
 #[derive(Debug)]
 pub struct IVorbisSetupHeader {
     pub codebooks: Vec<IVorbisSetupCodebook>,
+    /// The number of time domain transform placeholders, which are all of type 0.
+    pub time_count: u8,
     pub floors: Vec<IVorbisFloor>,
     pub residues: Vec<IVorbisResidue>,
     pub mappings: Vec<IVorbisMapping>,
@@ -896,17 +1703,28 @@ pub struct IVorbisSetupHeader {
 pub struct IVorbisSetupCodebook {
     pub dimensions: u16,
     pub entries: u32,
+    /// Whether the codeword lengths are stored as runs of increasing lengths.
+    pub ordered: bool,
+    /// Whether unordered codeword lengths flag unused entries, which have a length of 0.
+    pub sparse: bool,
+    /// The length the runs of an ordered codebook start at, below the length of the first entry
+    /// if the first runs are empty. `None` for unordered codebooks, or to start at the first entry.
+    pub initial_codeword_length: Option<u8>,
     pub codeword_lengths: Vec<u8>,
     pub lookup_type: VorbisSetupCodebookLookupType,
     pub minimum_value: Option<f32>,
     pub delta_value: Option<f32>,
+    /// The minimum value as stored in the packet, before `float32_unpack`.
+    pub packed_minimum_value: Option<u32>,
+    /// The delta value as stored in the packet, before `float32_unpack`.
+    pub packed_delta_value: Option<u32>,
     pub value_bits: Option<u8>,
     pub sequence_p: Option<bool>,
     pub multiplicands: Option<Vec<u32>>,
 }
 
 /// Represents the lookup types for Vorbis setup codebooks.
-#[derive(Debug, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub enum VorbisSetupCodebookLookupType {
     None = 0,
     Implicitly = 1,
@@ -967,17 +1785,23 @@ pub struct IVorbisResidue {
     pub classifications: u8,
     pub classbook: u8,
     pub cascade: Vec<u8>,
+    /// Whether the high bits of each cascade are stored, even if they are 0.
+    pub cascade_bitflags: Vec<bool>,
     pub books: Vec<Vec<i32>>,
 }
 
 /// Represents a Vorbis mapping.
 #[derive(Debug)]
 pub struct IVorbisMapping {
+    /// Whether the submap count is stored, even if it is 1.
+    pub submaps_flag: bool,
     pub submaps: u8,
     pub coupling_steps: u8,
     pub magnitude: Vec<u8>,
     pub angle: Vec<u8>,
     pub mux: Vec<u8>,
+    /// The unused time configuration placeholder of each submap.
+    pub submap_times: Vec<u8>,
     pub submap_floors: Vec<u8>,
     pub submap_residues: Vec<u8>,
 }
@@ -1004,3 +1828,232 @@ impl DerefMut for OggVorbisPage {
         &mut self.ogg_page
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{IDENTIFICATION_PACKET, SETUP_PACKET};
+    use std::collections::HashMap;
+
+    #[test]
+    fn float32_pack_round_trips_values_with_a_21_bit_mantissa() {
+        for value in [
+            0.0,
+            1.0,
+            -1.0,
+            0.5,
+            -0.375,
+            0.0029296875,
+            -1048575.5,
+            1234567.0,
+            -3298534883328.0,
+            2097151.0 * 1024.0,
+        ] {
+            assert_eq!(float32_unpack(float32_pack(value)), value);
+        }
+    }
+
+    #[test]
+    fn bit_stream_writer_output_reads_back_unchanged() {
+        let mut writer = BitStreamWriter::new();
+        writer.write_bool(true);
+        writer.write_uint3(5);
+        writer.write_uint_n(0x1abcd, 17);
+        writer.align_to_byte();
+        writer.write_uint8(0xa5);
+        writer.write_uint5(19);
+        writer.write_uint32(0xdeadbeef);
+        writer.write_uint_n(0, 0);
+        writer.write_uint24(0x123456);
+        assert_eq!(writer.bit_len(), 24 + 8 + 5 + 32 + 24);
+
+        let bytes = writer.into_bytes();
+        assert_eq!(bytes.len(), 12);
+        let mut reader = BitStreamReader::new(bytes, 0);
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(reader.read_uint3().unwrap(), 5);
+        assert_eq!(reader.read_uint_n(17).unwrap(), 0x1abcd);
+        // The alignment padding is zero
+        assert_eq!(reader.read_uint_n(3).unwrap(), 0);
+        assert_eq!(reader.position(), 24);
+        assert_eq!(reader.read_uint8().unwrap(), 0xa5);
+        assert_eq!(reader.read_uint5().unwrap(), 19);
+        assert_eq!(reader.read_uint32().unwrap(), 0xdeadbeef);
+        assert_eq!(reader.read_uint24().unwrap(), 0x123456);
+        assert_eq!(reader.remaining_bits(), 3);
+    }
+
+    #[test]
+    fn bit_stream_reader_fails_at_the_end_of_the_packet() {
+        let mut reader = BitStreamReader::new(vec![0b1010_1100, 0b0000_0101], 0);
+        assert_eq!(reader.read_uint_n(13).unwrap(), 0x5ac);
+        assert_eq!(reader.remaining_bits(), 3);
+
+        assert_eq!(reader.peek(3).unwrap(), 0);
+        assert!(reader.peek(4).is_err());
+        assert!(reader.skip(4).is_err());
+        assert!(reader.read_uint8().is_err());
+        assert_eq!(reader.position(), 13);
+
+        reader.skip(3).unwrap();
+        assert_eq!(reader.remaining_bits(), 0);
+        assert_eq!(reader.peek(0).unwrap(), 0);
+        assert!(reader.read_bool().is_err());
+        assert!(reader.read_uint32().is_err());
+        assert_eq!(reader.position(), 16);
+    }
+
+    #[test]
+    fn truncated_header_packets_are_rejected() {
+        let mut comments = HashMap::new();
+        comments.insert("TITLE".to_string(), vec!["Title".to_string()]);
+        let comment_packet = OggVorbisPage::build_comments(IVorbisCommentHeader {
+            vendor: "vendor".to_string(),
+            comments,
+        });
+        assert!(OggVorbisPage::parse_comment_packet(&comment_packet).is_ok());
+
+        for length in 0..IDENTIFICATION_PACKET.len() {
+            let packet = &IDENTIFICATION_PACKET[..length];
+            assert!(OggVorbisPage::parse_identification_packet(packet).is_err());
+        }
+        for length in 0..comment_packet.len() {
+            let packet = &comment_packet[..length];
+            assert!(OggVorbisPage::parse_comment_packet(packet).is_err());
+        }
+        for length in 0..SETUP_PACKET.len() {
+            let packet = &SETUP_PACKET[..length];
+            assert!(OggVorbisPage::parse_setup_packet(packet, 2).is_err());
+        }
+    }
+
+    #[test]
+    fn build_identification_round_trips_an_identification_packet() {
+        let header = OggVorbisPage::parse_identification_packet(&IDENTIFICATION_PACKET).unwrap();
+        assert_eq!(header.audio_channels, 2);
+        assert_eq!(header.audio_sample_rate, 44100);
+        assert_eq!(header.bitrate_nominal, 128000);
+        assert_eq!((header.blocksize0, header.blocksize1), (256, 2048));
+
+        assert_eq!(
+            OggVorbisPage::build_identification(&header).unwrap(),
+            IDENTIFICATION_PACKET
+        );
+    }
+
+    #[test]
+    fn build_identification_rejects_invalid_block_sizes() {
+        for (blocksize0, blocksize1) in [(32, 2048), (256, 16384), (300, 2048), (2048, 256)] {
+            let mut header =
+                OggVorbisPage::parse_identification_packet(&IDENTIFICATION_PACKET).unwrap();
+            header.blocksize0 = blocksize0;
+            header.blocksize1 = blocksize1;
+            assert!(OggVorbisPage::build_identification(&header).is_err());
+        }
+
+        // Block sizes of 2048 and 256, swapped
+        let mut packet = IDENTIFICATION_PACKET;
+        packet[28] = 0x8b;
+        assert!(OggVorbisPage::parse_identification_packet(&packet).is_err());
+    }
+
+    #[test]
+    fn build_setup_round_trips_a_setup_packet() {
+        let header = OggVorbisPage::parse_setup_packet(&SETUP_PACKET, 2).unwrap();
+
+        let codebook_kinds: Vec<_> = header
+            .codebooks
+            .iter()
+            .map(|codebook| (codebook.ordered, codebook.sparse, codebook.lookup_type))
+            .collect();
+        assert_eq!(
+            codebook_kinds,
+            [
+                (true, false, VorbisSetupCodebookLookupType::Implicitly),
+                (false, true, VorbisSetupCodebookLookupType::Explicitly),
+                (false, false, VorbisSetupCodebookLookupType::None),
+            ]
+        );
+        assert_eq!(header.codebooks[0].minimum_value, Some(-1.0));
+        assert_eq!(header.codebooks[0].delta_value, Some(0.5));
+        assert_eq!(header.residues[0].cascade, [5, 1, 8]);
+        assert_eq!(header.residues[0].cascade_bitflags, [true, false, true]);
+        assert_eq!(header.mappings[1].submaps, 2);
+
+        assert_eq!(
+            OggVorbisPage::build_setup(&header, 2).unwrap(),
+            SETUP_PACKET
+        );
+    }
+
+    #[test]
+    fn build_setup_keeps_empty_runs_of_ordered_codebooks() {
+        let mut header = OggVorbisPage::parse_setup_packet(&SETUP_PACKET, 2).unwrap();
+        let first_length = header.codebooks[0].codeword_lengths[0];
+        assert_eq!(
+            header.codebooks[0].initial_codeword_length,
+            Some(first_length)
+        );
+        assert_eq!(header.codebooks[1].initial_codeword_length, None);
+
+        // Lengthen every codeword, so the run of the original first length becomes empty
+        for length in header.codebooks[0].codeword_lengths.iter_mut() {
+            *length += 1;
+        }
+        let packet = OggVorbisPage::build_setup(&header, 2).unwrap();
+        assert_ne!(packet, SETUP_PACKET);
+
+        let parsed = OggVorbisPage::parse_setup_packet(&packet, 2).unwrap();
+        assert_eq!(
+            parsed.codebooks[0].initial_codeword_length,
+            Some(first_length)
+        );
+        assert_eq!(
+            parsed.codebooks[0].codeword_lengths,
+            header.codebooks[0].codeword_lengths
+        );
+        assert_eq!(OggVorbisPage::build_setup(&parsed, 2).unwrap(), packet);
+
+        // Without an initial length, the runs start at the first entry's length
+        header.codebooks[0].initial_codeword_length = None;
+        let shortest = OggVorbisPage::build_setup(&header, 2).unwrap();
+        let reparsed = OggVorbisPage::parse_setup_packet(&shortest, 2).unwrap();
+        assert_eq!(
+            reparsed.codebooks[0].initial_codeword_length,
+            Some(first_length + 1)
+        );
+        header.codebooks[0].initial_codeword_length = Some(first_length + 2);
+        assert!(OggVorbisPage::build_setup(&header, 2).is_err());
+    }
+
+    #[test]
+    fn build_setup_rejects_fields_wider_than_their_bits() {
+        let build = |change: fn(&mut IVorbisSetupHeader)| {
+            let mut header = OggVorbisPage::parse_setup_packet(&SETUP_PACKET, 2).unwrap();
+            change(&mut header);
+            OggVorbisPage::build_setup(&header, 2)
+        };
+        fn floor1(header: &mut IVorbisSetupHeader) -> &mut IVorbisFloorType1 {
+            match &mut header.floors[0] {
+                IVorbisFloor::Type1(floor) => floor,
+                IVorbisFloor::Type0(_) => unreachable!(),
+            }
+        }
+
+        assert!(build(|_| {}).is_ok());
+        assert!(build(|header| header.codebooks[2].codeword_lengths[0] = 33).is_err());
+        assert!(build(|header| header.codebooks[1].value_bits = Some(0)).is_err());
+        assert!(build(|header| header.codebooks[1].value_bits = Some(17)).is_err());
+        assert!(
+            build(|header| header.codebooks[1].multiplicands.as_mut().unwrap()[1] = 32).is_err()
+        );
+        assert!(build(|header| header.codebooks[0].entries = 1 << 24).is_err());
+        assert!(build(|header| floor1(header).class_dimensions[0] = 0).is_err());
+        assert!(build(|header| floor1(header).multiplier = 0).is_err());
+        assert!(build(|header| floor1(header).subclass_books[0].truncate(1)).is_err());
+        assert!(build(|header| floor1(header).x_list[2] = 128).is_err());
+        assert!(build(|header| header.residues[0].partition_size = 0).is_err());
+        assert!(build(|header| header.residues[0].books[0].truncate(2)).is_err());
+        assert!(build(|header| header.mappings[0].angle[0] = 2).is_err());
+    }
+}
@@ -0,0 +1,306 @@
+use crate::ogg_demuxer::{demux_ogg_pages, OggCodec, OggLogicalStream};
+use crate::ogg_packet::OggPacket;
+use crate::ogg_page::{OggFormatError, OggPage};
+use crate::ogg_stream_writer::OggStreamWriter;
+use crate::ogg_vorbis_granule::{
+    calculate_ogg_vorbis_granule_positions, OggVorbisGranuleCalculator,
+};
+use crate::ogg_vorbis_page::{
+    IVorbisIdentificationHeader, IVorbisSetupHeader, OggVorbisPage, VorbisFormatError,
+};
+
+/// The smallest page size that still fits a lacing value of 255 bytes.
+const MIN_PAGE_SIZE: usize = 27 + 1 + 255;
+
+/// Options controlling how `repaginate_ogg_vorbis_stream` rebuilds pages.
+#[derive(Debug, Clone, Default)]
+pub struct OggRepaginateOptions {
+    /// The size no page may exceed, including its header and segment table.
+    pub max_page_size: Option<usize>,
+    /// The maximum number of samples between the granule positions of consecutive pages. A
+    /// single packet longer than this still gets a page of its own.
+    pub max_granule_span: Option<u64>,
+}
+
+/// Rebuilds the pages of a Vorbis stream from its reassembled packets.
+///
+/// The identification header is put alone on the first page and the comment and setup headers
+/// on the following pages, so the first audio packet starts on a fresh page as the Vorbis
+/// specification requires. Granule positions are recalculated from the block sizes of the
+/// packets, and sequence numbers and checksums are rebuilt. Incomplete packets are dropped.
+///
+/// # Arguments
+///
+/// * `stream` - The Vorbis stream, as split by `OggDemuxer`.
+/// * `options` - The limits of the new pages.
+///
+/// # Returns
+///
+/// * `Result<Vec<OggPage>, VorbisFormatError>` - The new pages, or an error if the stream is not
+///   a Vorbis stream with valid headers.
+pub fn repaginate_ogg_vorbis_stream(
+    stream: &OggLogicalStream,
+    options: &OggRepaginateOptions,
+) -> Result<Vec<OggPage>, VorbisFormatError> {
+    if stream.codec != OggCodec::Vorbis {
+        return Err(VorbisFormatError(format!(
+            "Only Vorbis streams can be repaginated, got {:?}",
+            stream.codec
+        )));
+    }
+
+    if let Some(max_page_size) = options.max_page_size {
+        if max_page_size < MIN_PAGE_SIZE {
+            return Err(VorbisFormatError(format!(
+                "The maximum page size must be at least {} bytes, got {}",
+                MIN_PAGE_SIZE, max_page_size
+            )));
+        }
+    }
+
+    let stream_packets = prepare_ogg_vorbis_stream(stream)?;
+    let audio_packets = stream_packets.audio_packets;
+    let granule_positions = stream_packets.granule_positions;
+
+    let mut writer = OggStreamWriter::new(stream.stream_serial_number);
+    if let Some(max_page_size) = options.max_page_size {
+        writer.max_page_size = Some(max_page_size);
+        writer.max_page_body_size = max_page_size;
+    }
+
+    let map_error = |e: OggFormatError| VorbisFormatError(e.to_string());
+    let mut pages = write_ogg_vorbis_headers(&mut writer, stream_packets.header_packets)?;
+
+    // The first audio packet only primes the decoder, so its granule position is where the
+    // stream starts, which is not zero for streams cut out of a longer one
+    let mut page_start_granule = granule_positions.first().copied().unwrap_or(0);
+    for (index, (packet, &granule_position)) in
+        audio_packets.iter().zip(&granule_positions).enumerate()
+    {
+        let mut new_pages = Vec::new();
+
+        if let Some(max_granule_span) = options.max_granule_span {
+            if writer.pending_granule_position().is_some()
+                && granule_position.saturating_sub(page_start_granule) > max_granule_span
+            {
+                new_pages.extend(writer.flush().map_err(map_error)?);
+            }
+        }
+
+        let end_of_stream = stream.is_eos_seen && index + 1 == audio_packets.len();
+        new_pages.extend(
+            writer
+                .write_packet(&packet.data, granule_position, end_of_stream)
+                .map_err(map_error)?,
+        );
+
+        if let Some(granule_position) = new_pages
+            .iter()
+            .rev()
+            .map(|page| page.absolute_granule_position)
+            .find(|&granule_position| granule_position != u64::MAX)
+        {
+            page_start_granule = granule_position;
+        }
+        pages.extend(new_pages);
+    }
+
+    if stream.is_eos_seen && audio_packets.is_empty() {
+        pages.extend(writer.finish().map_err(map_error)?);
+    } else {
+        pages.extend(writer.flush().map_err(map_error)?);
+    }
+
+    Ok(pages)
+}
+
+/// The packets of a Vorbis stream, ready to be written into new pages.
+pub(crate) struct OggVorbisStreamPackets<'a> {
+    /// The identification, comment and setup header packets.
+    pub header_packets: [&'a OggPacket; 3],
+    pub identification_header: IVorbisIdentificationHeader,
+    pub setup_header: IVorbisSetupHeader,
+    /// The complete audio packets.
+    pub audio_packets: Vec<OggPacket>,
+    /// The granule position at the end of each audio packet.
+    pub granule_positions: Vec<u64>,
+}
+
+/// Checks the headers of a Vorbis stream and calculates the granule positions of its complete
+/// audio packets.
+pub(crate) fn prepare_ogg_vorbis_stream(
+    stream: &OggLogicalStream,
+) -> Result<OggVorbisStreamPackets<'_>, VorbisFormatError> {
+    if stream.codec != OggCodec::Vorbis {
+        return Err(VorbisFormatError(format!(
+            "Expected a Vorbis stream, got {:?}",
+            stream.codec
+        )));
+    }
+
+    let [identification, comment, setup] = match stream.header_packets.as_slice() {
+        [identification, comment, setup] => [identification, comment, setup],
+        _ => {
+            return Err(VorbisFormatError(
+                "The stream is missing header packets".to_string(),
+            ))
+        }
+    };
+
+    let identification_header = OggVorbisPage::parse_identification_packet(&identification.data)?;
+    let setup_header =
+        OggVorbisPage::parse_setup_packet(&setup.data, identification_header.audio_channels)?;
+
+    let audio_packets: Vec<OggPacket> = stream
+        .packets
+        .iter()
+        .filter(|packet| packet.is_complete)
+        .cloned()
+        .collect();
+    let mut calculator = OggVorbisGranuleCalculator::new(&identification_header, &setup_header);
+    let granule_positions = calculate_ogg_vorbis_granule_positions(&mut calculator, &audio_packets);
+
+    Ok(OggVorbisStreamPackets {
+        header_packets: [identification, comment, setup],
+        identification_header,
+        setup_header,
+        audio_packets,
+        granule_positions,
+    })
+}
+
+/// Writes the header packets of a Vorbis stream, the identification header alone on the first
+/// page and the comment and setup headers on the following pages.
+pub(crate) fn write_ogg_vorbis_headers(
+    writer: &mut OggStreamWriter,
+    [identification, comment, setup]: [&OggPacket; 3],
+) -> Result<Vec<OggPage>, VorbisFormatError> {
+    let map_error = |e: OggFormatError| VorbisFormatError(e.to_string());
+    let mut pages = Vec::new();
+
+    pages.extend(
+        writer
+            .write_packet(&identification.data, 0, false)
+            .map_err(map_error)?,
+    );
+    pages.extend(writer.flush().map_err(map_error)?);
+    for header in [comment, setup] {
+        pages.extend(
+            writer
+                .write_packet(&header.data, 0, false)
+                .map_err(map_error)?,
+        );
+    }
+    pages.extend(writer.flush().map_err(map_error)?);
+
+    Ok(pages)
+}
+
+/// Rebuilds the pages of every Vorbis stream of an Ogg file, as `repaginate_ogg_vorbis_stream` does.
+///
+/// The streams are written one after another, so every link of a chained file is repaginated on
+/// its own, even if the links reuse a serial number. Files holding other codecs are rejected.
+///
+/// # Arguments
+///
+/// * `pages` - A slice of `OggPage` in physical stream order.
+/// * `options` - The limits of the new pages.
+///
+/// # Returns
+///
+/// * `Result<Vec<OggPage>, VorbisFormatError>` - The new pages, or an error if a stream cannot be repaginated.
+pub fn repaginate_ogg_vorbis_file(
+    pages: &[OggPage],
+    options: &OggRepaginateOptions,
+) -> Result<Vec<OggPage>, VorbisFormatError> {
+    let mut result = Vec::new();
+    for stream in demux_ogg_pages(pages) {
+        result.extend(repaginate_ogg_vorbis_stream(&stream, options)?);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::vorbis_stream;
+
+    #[test]
+    fn repaginated_links_reassemble_to_the_same_packets() {
+        let pages: Vec<OggPage> = vorbis_stream(7, "first", 30, 0)
+            .into_iter()
+            .chain(vorbis_stream(7, "second", 20, 0))
+            .collect();
+        let options = OggRepaginateOptions {
+            max_page_size: Some(MIN_PAGE_SIZE),
+            max_granule_span: Some(4096),
+        };
+
+        let repaginated = repaginate_ogg_vorbis_file(&pages, &options).unwrap();
+        assert!(repaginated
+            .iter()
+            .all(|page| page.if_page_checksum_correct()));
+        assert!(repaginated
+            .iter()
+            .all(|page| page.page_size <= MIN_PAGE_SIZE));
+
+        let streams = demux_ogg_pages(&pages);
+        let repaginated_streams = demux_ogg_pages(&repaginated);
+        assert_eq!(repaginated_streams.len(), 2);
+
+        for (stream, repaginated_stream) in streams.iter().zip(&repaginated_streams) {
+            assert!(repaginated_stream.is_bos_seen && repaginated_stream.is_eos_seen);
+            for (packets, repaginated_packets) in [
+                (&stream.header_packets, &repaginated_stream.header_packets),
+                (&stream.packets, &repaginated_stream.packets),
+            ] {
+                let data: Vec<&Vec<u8>> = packets.iter().map(|packet| &packet.data).collect();
+                let repaginated_data: Vec<&Vec<u8>> = repaginated_packets
+                    .iter()
+                    .map(|packet| &packet.data)
+                    .collect();
+                assert_eq!(data, repaginated_data);
+            }
+
+            // Both links start at granule position zero and keep their own timing
+            let last_granule = |pages: &[OggPage]| pages.last().unwrap().absolute_granule_position;
+            assert_eq!(
+                last_granule(&repaginated_stream.pages),
+                last_granule(&stream.pages)
+            );
+            let sequence_numbers: Vec<u32> = repaginated_stream
+                .pages
+                .iter()
+                .map(|page| page.page_sequence_number)
+                .collect();
+            let expected: Vec<u32> = (0..sequence_numbers.len() as u32).collect();
+            assert_eq!(sequence_numbers, expected);
+        }
+    }
+
+    #[test]
+    fn granule_span_is_counted_from_the_start_of_the_stream() {
+        let options = OggRepaginateOptions {
+            max_page_size: None,
+            max_granule_span: Some(4096),
+        };
+        let repaginate = |start_granule| {
+            let pages = vorbis_stream(7, "title", 60, start_granule);
+            let stream = &demux_ogg_pages(&pages)[0];
+            repaginate_ogg_vorbis_stream(stream, &options).unwrap()
+        };
+
+        // A stream starting late is paginated as one starting at zero, shifted in time. The
+        // headers take up the first two pages
+        let shifted = |pages: Vec<OggPage>, offset: u64| -> Vec<u64> {
+            pages
+                .iter()
+                .skip(2)
+                .map(|page| page.absolute_granule_position - offset)
+                .collect()
+        };
+        let from_zero = shifted(repaginate(0), 0);
+        assert!(from_zero.len() > 1);
+        assert_eq!(shifted(repaginate(100000), 100000), from_zero);
+    }
+}
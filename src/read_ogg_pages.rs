@@ -0,0 +1,29 @@
+use std::io::{self};
+use tokio::io::AsyncReadExt;
+
+use crate::ogg_page::OggPage;
+use crate::ogg_page_reader::OggPageReader;
+
+/// Reads all Ogg pages of a physical stream asynchronously, without interpreting their packets.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to an asynchronous reader implementing `AsyncReadExt`.
+/// * `tolerate` - A boolean indicating whether to tolerate errors and continue parsing.
+///
+/// # Returns
+///
+/// A `Result` containing a vector of `OggPage` on success, or an `io::Error` on failure.
+pub async fn read_ogg_pages<R: AsyncReadExt + Unpin>(
+    reader: &mut R,
+    tolerate: bool,
+) -> io::Result<Vec<OggPage>> {
+    let mut reader = OggPageReader::new(reader, tolerate);
+    let mut pages = Vec::new();
+
+    while let Some(page) = reader.next_page().await? {
+        pages.push(page);
+    }
+
+    Ok(pages)
+}
@@ -1,8 +1,14 @@
+use std::collections::HashMap;
 use std::io::{self};
 use tokio::io::AsyncReadExt;
 
+use crate::ogg_packet::{OggPacket, OggPacketAssembler};
+use crate::ogg_page::OggPage;
+use crate::ogg_page_parser::OggSkipReport;
+use crate::ogg_page_reader::OggVorbisReader;
 use crate::ogg_vorbis_page::{
     IVorbisCommentHeader, IVorbisIdentificationHeader, IVorbisSetupHeader, OggVorbisPage,
+    VorbisFormatError, VorbisHeaderType,
 };
 
 /// Represents the result of parsing an Ogg page, containing the type of result,
@@ -49,6 +55,144 @@ pub struct OggVorbisPageResult {
     pub page: OggVorbisPage,
     /// The packets contained within the page.
     pub packets: Vec<OggVorbisPacket>,
+    /// The packets completed on this page, reassembled across page boundaries.
+    pub completed_packets: Vec<OggPacket>,
+}
+
+/// The state the parser keeps for one logical stream.
+#[derive(Debug, Default)]
+struct OggVorbisStreamState {
+    audio_channels: Option<u8>,
+    assembler: OggPacketAssembler,
+}
+
+/// Classifies the packets of successive pages of an Ogg Vorbis stream without performing any I/O.
+///
+/// The parser keeps the state shared between pages, such as the channel count needed to parse
+/// the setup header and the packets waiting for continuation. This state is kept per serial
+/// number, so pages of other logical streams multiplexed with the Vorbis stream, such as Theora
+/// or Skeleton, never interfere with it.
+#[derive(Debug)]
+pub struct OggVorbisPacketParser {
+    /// The range within which to search for headers.
+    pub header_search_range: usize,
+    streams: HashMap<u32, OggVorbisStreamState>,
+    page_index: usize,
+    offset: usize,
+}
+
+impl OggVorbisPacketParser {
+    /// Creates a new `OggVorbisPacketParser`.
+    ///
+    /// # Arguments
+    ///
+    /// * `header_search_range` - The range within which to search for headers.
+    pub fn new(header_search_range: usize) -> Self {
+        Self {
+            header_search_range,
+            streams: HashMap::new(),
+            page_index: 0,
+            offset: 0,
+        }
+    }
+
+    /// Parses the packets of the next page of the stream.
+    ///
+    /// Header packets are parsed once they are complete, from the data reassembled across page
+    /// boundaries, so they are reported on the page where they end. Lace groups that do not end
+    /// a header packet are reported as body packets.
+    ///
+    /// # Arguments
+    ///
+    /// * `ogg_page` - The next page of the stream.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `OggVorbisPageResult` of the page, or a `VorbisFormatError` if a
+    /// header packet is invalid.
+    pub fn parse_page(
+        &mut self,
+        ogg_page: OggPage,
+    ) -> Result<OggVorbisPageResult, VorbisFormatError> {
+        let header_search_range = self.header_search_range;
+        let page = OggVorbisPage { ogg_page };
+        let page_index = self.page_index;
+        let page_offset = self.offset;
+        self.page_index += 1;
+        self.offset += page.ogg_page.page_size;
+
+        let state = self
+            .streams
+            .entry(page.ogg_page.stream_serial_number)
+            .or_default();
+
+        // Every link of a chained file starts over with its own headers
+        if page.ogg_page.is_bos {
+            state.audio_channels = None;
+        }
+
+        let assembler = &mut state.assembler;
+        let completed_packets = assembler.push_page_at(&page.ogg_page, page_index, page_offset);
+        let raw_segments = page.ogg_page.map_segments(|segment, _| segment.to_vec());
+        let mut packets = Vec::with_capacity(raw_segments.len());
+
+        for (segment, get_raw_segment) in raw_segments.into_iter().enumerate() {
+            let header_packet = completed_packets
+                .iter()
+                .filter(|_| segment <= header_search_range)
+                .find(|packet| packet.is_complete && packet.last_segment_index == segment)
+                .and_then(|packet| {
+                    OggVorbisPage::header_packet_type(&packet.data)
+                        .map(|header_type| (header_type, packet))
+                });
+
+            let packet = match header_packet {
+                Some((VorbisHeaderType::Identification, packet)) => {
+                    let identification = OggVorbisPage::parse_identification_packet(&packet.data)?;
+                    state.audio_channels = Some(identification.audio_channels);
+
+                    OggVorbisPacket::Identification(OggVorbiseHeaderIdentificationParseResult {
+                        result_type: String::from("identification"),
+                        data: identification,
+                        index: segment,
+                        get_raw_segment,
+                    })
+                }
+                Some((VorbisHeaderType::Comment, packet)) => {
+                    OggVorbisPacket::Comment(OggVorbiseHeaderCommentParseResult {
+                        result_type: String::from("comment"),
+                        data: OggVorbisPage::parse_comment_packet(&packet.data)?,
+                        index: segment,
+                        get_raw_segment,
+                    })
+                }
+                // A setup header cannot be parsed without the channel count of its stream
+                Some((VorbisHeaderType::Setup, packet)) if state.audio_channels.is_some() => {
+                    let audio_channels = state.audio_channels.unwrap();
+
+                    OggVorbisPacket::Setup(OggVorbiseHeaderSetupParseResult {
+                        result_type: String::from("setup"),
+                        data: OggVorbisPage::parse_setup_packet(&packet.data, audio_channels)?,
+                        index: segment,
+                        get_raw_segment,
+                    })
+                }
+                _ => OggVorbisPacket::Body(OggParseResult {
+                    result_type: String::from("body"),
+                    data: (),
+                    index: segment,
+                    get_raw_segment,
+                }),
+            };
+            packets.push(packet);
+        }
+
+        Ok(OggVorbisPageResult {
+            page,
+            packets,
+            completed_packets,
+        })
+    }
 }
 
 /// Reads an Ogg Vorbis file asynchronously and parses its pages and packets.
@@ -67,111 +211,126 @@ pub async fn read_ogg_vorbis_file<R: AsyncReadExt + Unpin>(
     tolerate: bool,
     header_search_range: usize,
 ) -> io::Result<Vec<OggVorbisPageResult>> {
-    let mut done = false;
-    let mut buffer: Vec<u8> = Vec::new();
-    let mut audio_channels: Option<u8> = None;
+    let mut reader = OggVorbisReader::new(reader, tolerate, header_search_range);
     let mut results = Vec::new();
 
-    while !done || !buffer.is_empty() {
-        let mut chunk = vec![0; 4096];
-        let n = reader.read(&mut chunk).await?;
-        if n == 0 {
-            done = true;
-        } else {
-            buffer.extend_from_slice(&chunk[..n]);
-        }
+    while let Some(result) = reader.next_page().await? {
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+/// Reads an Ogg Vorbis file asynchronously and parses its pages and packets, reporting the
+/// byte ranges skipped while resynchronising on damaged data.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to an asynchronous reader implementing `AsyncReadExt`.
+/// * `tolerate` - A boolean indicating whether to tolerate errors and continue parsing.
+/// * `header_search_range` - The range within which to search for headers.
+///
+/// # Returns
+///
+/// A `Result` containing a vector of `OggVorbisPageResult` and the `OggSkipReport` on success,
+/// or an `io::Error` on failure.
+pub async fn read_ogg_vorbis_file_with_report<R: AsyncReadExt + Unpin>(
+    reader: &mut R,
+    tolerate: bool,
+    header_search_range: usize,
+) -> io::Result<(Vec<OggVorbisPageResult>, OggSkipReport)> {
+    let mut reader = OggVorbisReader::new(reader, tolerate, header_search_range);
+    let mut results = Vec::new();
 
-        if buffer.len() < 4 && done {
-            break;
+    while let Some(result) = reader.next_page().await? {
+        results.push(result);
+    }
+
+    Ok((results, reader.skip_report().clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ogg_stream_writer::OggStreamWriter;
+    use std::collections::HashMap;
+
+    fn identification_packet() -> Vec<u8> {
+        OggVorbisPage::build_identification(&IVorbisIdentificationHeader {
+            vorbis_version: 0,
+            audio_channels: 2,
+            audio_sample_rate: 44100,
+            bitrate_maximum: 0,
+            bitrate_nominal: 128000,
+            bitrate_minimum: 0,
+            blocksize0: 256,
+            blocksize1: 2048,
+            framing_flag: true,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_page_keeps_streams_apart() {
+        let mut comments = HashMap::new();
+        comments.insert("COVER".to_string(), vec!["x".repeat(10000)]);
+        let comment_packet = OggVorbisPage::build_comments(IVorbisCommentHeader {
+            vendor: "test".to_string(),
+            comments,
+        });
+
+        let mut vorbis = WrittenPages::new(1);
+        vorbis.write(&identification_packet());
+        vorbis.flush();
+        vorbis.write(&comment_packet);
+        vorbis.flush();
+        assert!(vorbis.pages.len() > 2);
+
+        // Every page of the other stream starts a fresh packet while the comment is pending
+        let mut other = WrittenPages::new(2);
+        for _ in 0..vorbis.pages.len() {
+            other.write(b"\x80theora");
+            other.flush();
         }
 
-        // Try to parse the Ogg Vorbis page
-        let page = match OggVorbisPage::new(buffer.clone()) {
-            Ok(page) => page,
-            Err(error) => {
-                if done {
-                    if tolerate {
-                        // Move forward one byte and continue
-                        buffer.drain(..1);
-                        continue;
-                    } else {
-                        // If done and still error, return the error
-                        return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+        let mut parser = OggVorbisPacketParser::new(3);
+        let mut found_comment = None;
+        for (vorbis_page, other_page) in vorbis.pages.into_iter().zip(other.pages) {
+            for page in [vorbis_page, other_page] {
+                let result = parser.parse_page(page).unwrap();
+                for packet in result.packets {
+                    if let OggVorbisPacket::Comment(comment) = packet {
+                        found_comment = Some(comment.data);
                     }
-                } else {
-                    // If not done, wait for more data
-                    continue;
                 }
             }
-        };
+        }
 
-        let mut result = OggVorbisPageResult {
-            page: page.clone(),
-            packets: Vec::new(),
-        };
+        let comment = found_comment.expect("the comment header was not parsed");
+        assert_eq!(comment.comments["COVER"][0].len(), 10000);
+    }
 
-        for (accumulated_segments, segment) in (0..page.ogg_page.page_segments).enumerate() {
-            if accumulated_segments > header_search_range {
-                result.packets.push(OggVorbisPacket::Body(OggParseResult {
-                    result_type: String::from("body"),
-                    data: (),
-                    index: segment,
-                    get_raw_segment: page.ogg_page.get_page_segment(segment).unwrap(),
-                }));
-            } else if page.is_identification_packet(segment) {
-                let identification = page.get_identification(segment).unwrap();
-                audio_channels = Some(identification.audio_channels);
-
-                result.packets.push(OggVorbisPacket::Identification(
-                    OggVorbiseHeaderIdentificationParseResult {
-                        result_type: String::from("identification"),
-                        data: identification,
-                        index: segment,
-                        get_raw_segment: page.ogg_page.get_page_segment(segment).unwrap(),
-                    },
-                ));
-            } else if page.is_comment_packet(segment) {
-                let comments = page.get_comments(segment).unwrap();
-
-                result.packets.push(OggVorbisPacket::Comment(
-                    OggVorbiseHeaderCommentParseResult {
-                        result_type: String::from("comment"),
-                        data: comments,
-                        index: segment,
-                        get_raw_segment: page.ogg_page.get_page_segment(segment).unwrap(),
-                    },
-                ));
-            } else if page.is_setup_packet(segment) {
-                if let Some(channels) = audio_channels {
-                    let setup = page.get_setup(channels, segment).unwrap();
-
-                    result
-                        .packets
-                        .push(OggVorbisPacket::Setup(OggVorbiseHeaderSetupParseResult {
-                            result_type: String::from("setup"),
-                            data: setup,
-                            index: segment,
-                            get_raw_segment: page.ogg_page.get_page_segment(segment).unwrap(),
-                        }));
-                } else {
-                    println!(
-                        "Found a setup packet but no identification packet detected, will skip"
-                    );
-                }
-            } else {
-                result.packets.push(OggVorbisPacket::Body(OggParseResult {
-                    result_type: String::from("body"),
-                    data: (),
-                    index: segment,
-                    get_raw_segment: page.ogg_page.get_page_segment(segment).unwrap(),
-                }));
+    /// Collects the pages written by an `OggStreamWriter`.
+    struct WrittenPages {
+        writer: OggStreamWriter,
+        pages: Vec<OggPage>,
+    }
+
+    impl WrittenPages {
+        fn new(stream_serial_number: u32) -> Self {
+            Self {
+                writer: OggStreamWriter::new(stream_serial_number),
+                pages: Vec::new(),
             }
         }
 
-        // Update buffer to remove the processed page
-        buffer.drain(..page.ogg_page.page_size);
-        results.push(result);
-    }
+        fn write(&mut self, packet: &[u8]) {
+            let pages = self.writer.write_packet(packet, 0, false).unwrap();
+            self.pages.extend(pages);
+        }
 
-    Ok(results)
+        fn flush(&mut self) {
+            self.pages.extend(self.writer.flush().unwrap());
+        }
+    }
 }
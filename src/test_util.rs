@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use crate::ogg_page::OggPage;
+use crate::ogg_stream_writer::OggStreamWriter;
+use crate::ogg_vorbis_granule::OggVorbisGranuleCalculator;
+use crate::ogg_vorbis_page::{IVorbisCommentHeader, OggVorbisPage};
+
+/// A stereo 44.1 kHz identification header with a nominal bitrate of 128 kbit/s and block
+/// sizes of 256 and 2048.
+pub(crate) const IDENTIFICATION_PACKET: [u8; 30] = [
+    0x01, 0x76, 0x6f, 0x72, 0x62, 0x69, 0x73, 0x00, 0x00, 0x00, 0x00, 0x02, 0x44, 0xac, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0xf4, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0xb8, 0x01,
+];
+
+/// A stereo setup header accepted by other Vorbis decoders, holding an ordered codebook with
+/// lookup type 1, a sparse codebook with lookup type 2 and a dense codebook without lookup,
+/// one floor 1, one residue 2 whose cascades have the bitflag set with and without high bits
+/// and cleared, a coupled mapping, a mapping with two submaps and two modes.
+pub(crate) const SETUP_PACKET: [u8; 130] = [
+    0x05, 0x76, 0x6f, 0x72, 0x62, 0x69, 0x73, 0x02, 0x42, 0x43, 0x56, 0x01, 0x00, 0x03, 0x00, 0x00,
+    0x41, 0x06, 0x00, 0x00, 0x04, 0x38, 0x00, 0x00, 0xfc, 0x97, 0xc0, 0x2e, 0x34, 0x64, 0x25, 0x00,
+    0x50, 0x00, 0x00, 0x60, 0x60, 0x30, 0x08, 0x00, 0x00, 0x04, 0x38, 0x00, 0x00, 0xfc, 0x17, 0x05,
+    0xc3, 0x24, 0xf6, 0x64, 0xc5, 0x5b, 0x68, 0xc8, 0x2a, 0x00, 0x80, 0x00, 0x00, 0x80, 0x10, 0x42,
+    0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x10, 0x10, 0x49, 0x00, 0x60, 0x00, 0x0c, 0x74, 0x0a, 0x8a,
+    0x07, 0x10, 0x00, 0x00, 0x00, 0x00, 0x20, 0x03, 0x00, 0xf8, 0x00, 0x00, 0x10, 0x04, 0x1a, 0x04,
+    0x86, 0x00, 0x00, 0x80, 0x80, 0x00, 0x00, 0x40, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x18, 0x80,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00,
+    0x08, 0x08,
+];
+
+/// The number of audio packets put on each page by `vorbis_stream`.
+pub(crate) const PACKETS_PER_PAGE: usize = 4;
+
+/// Builds a comment header holding a single title.
+pub(crate) fn comment_packet(title: &str) -> Vec<u8> {
+    let mut comments = HashMap::new();
+    comments.insert("TITLE".to_string(), vec![title.to_string()]);
+    OggVorbisPage::build_comments(IVorbisCommentHeader {
+        vendor: "test".to_string(),
+        comments,
+    })
+}
+
+/// Builds the audio packet at the given index of a stream, alternating between the two modes
+/// of `SETUP_PACKET` in pairs so both block sizes and both transitions between them occur.
+pub(crate) fn audio_packet(index: usize) -> Vec<u8> {
+    let mode = (index / 2 % 2) as u8;
+    let mut packet = vec![mode << 1];
+    packet.extend(std::iter::repeat(index as u8).take(20 + index % 7));
+    packet
+}
+
+/// Returns the granule position at the end of each of the first `count` audio packets of a
+/// stream starting at `start_granule`.
+pub(crate) fn audio_granules(count: usize, start_granule: u64) -> Vec<u64> {
+    let identification =
+        OggVorbisPage::parse_identification_packet(&IDENTIFICATION_PACKET).unwrap();
+    let setup = OggVorbisPage::parse_setup_packet(&SETUP_PACKET, 2).unwrap();
+    let mut calculator = OggVorbisGranuleCalculator::new(&identification, &setup);
+
+    let mut granule = start_granule;
+    (0..count)
+        .map(|index| {
+            granule += calculator.push_packet(&audio_packet(index));
+            granule
+        })
+        .collect()
+}
+
+/// Writes a Vorbis stream with the identification header on its own page, the comment and setup
+/// headers on the next page, and `PACKETS_PER_PAGE` audio packets on each following page.
+///
+/// # Arguments
+///
+/// * `stream_serial_number` - The serial number of the stream.
+/// * `title` - The title stored in the comment header.
+/// * `audio_packet_count` - The number of audio packets, at least one.
+/// * `start_granule` - The granule position the stream starts at.
+pub(crate) fn vorbis_stream(
+    stream_serial_number: u32,
+    title: &str,
+    audio_packet_count: usize,
+    start_granule: u64,
+) -> Vec<OggPage> {
+    let mut writer = OggStreamWriter::new(stream_serial_number);
+    let mut pages = Vec::new();
+
+    let headers = [
+        IDENTIFICATION_PACKET.to_vec(),
+        comment_packet(title),
+        SETUP_PACKET.to_vec(),
+    ];
+    for (index, header) in headers.iter().enumerate() {
+        pages.extend(writer.write_packet(header, 0, false).unwrap());
+        // The identification header is alone on the first page, the others end on the second
+        if index != 1 {
+            pages.extend(writer.flush().unwrap());
+        }
+    }
+
+    let granules = audio_granules(audio_packet_count, start_granule);
+    for (index, granule) in granules.into_iter().enumerate() {
+        let is_last = index + 1 == audio_packet_count;
+        let written = writer.write_packet(&audio_packet(index), granule, is_last);
+        pages.extend(written.unwrap());
+        if !is_last && (index + 1) % PACKETS_PER_PAGE == 0 {
+            pages.extend(writer.flush().unwrap());
+        }
+    }
+
+    pages
+}
+
+/// Concatenates the buffers of the given pages.
+pub(crate) fn page_bytes(pages: &[OggPage]) -> Vec<u8> {
+    pages.iter().flat_map(|page| page.buffer.clone()).collect()
+}
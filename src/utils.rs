@@ -1,8 +1,111 @@
-use crate::ogg_vorbis_page::{IVorbisCommentHeader, OggVorbisPage};
-use crate::read_ogg_vorbis_file::{read_ogg_vorbis_file, OggVorbisPacket, OggVorbisPageResult};
+use crate::ogg_demuxer::{demux_ogg_pages, OggCodec};
+use crate::ogg_page::{OggFormatError, OggPage};
+use crate::ogg_page_reader::OggVorbisReader;
+use crate::ogg_vorbis_link::OggVorbisLink;
+use crate::ogg_vorbis_page::{IVorbisCommentHeader, OggVorbisPage, VorbisFormatError};
+use crate::read_ogg_vorbis_file::{OggVorbisPacket, OggVorbisPacketParser, OggVorbisPageResult};
+use futures_util::stream::{self, Stream, StreamExt};
+use std::collections::HashMap;
 use std::io::{self, Error, ErrorKind};
+use std::pin::pin;
 use tokio::io::AsyncReadExt;
 
+/// Trims the segments before the first header from a page, once the header is found.
+///
+/// # Arguments
+///
+/// * `page_result` - The next page of the file.
+/// * `header_found` - Whether the first header was found on a previous page, updated by this function.
+///
+/// # Returns
+///
+/// A result containing the trimmed page, `None` if the page lies before the first header, or an
+/// `io::Error` if the segments cannot be removed.
+pub(crate) fn trim_ogg_vorbis_page(
+    mut page_result: OggVorbisPageResult,
+    header_found: &mut bool,
+) -> io::Result<Option<OggVorbisPageResult>> {
+    if *header_found {
+        return Ok(Some(page_result));
+    }
+
+    let Some(found_header_index) = page_result.packets.iter().position(|packet| {
+        matches!(
+            packet,
+            OggVorbisPacket::Identification(_)
+                | OggVorbisPacket::Comment(_)
+                | OggVorbisPacket::Setup(_)
+        )
+    }) else {
+        return Ok(None);
+    };
+
+    *header_found = true;
+    if found_header_index > 0 {
+        page_result.page = page_result
+            .page
+            .remove_page_segment(0, found_header_index)
+            .map_err(|e| Error::new(ErrorKind::Other, e))?;
+        page_result.packets = page_result.packets.split_off(found_header_index);
+        // The packets left all start on this page, after the removed lace groups
+        page_result
+            .completed_packets
+            .retain(|packet| packet.last_segment_index >= found_header_index);
+        for packet in page_result.completed_packets.iter_mut() {
+            packet.first_segment_index -= found_header_index;
+            packet.last_segment_index -= found_header_index;
+        }
+        // The first segment left starts the header, so the page no longer continues a packet
+        let header_type = page_result.page.header_type & !0x1;
+        page_result.page.set_header_type(header_type);
+    }
+
+    Ok(Some(page_result))
+}
+
+/// Trims an Ogg Vorbis stream by removing segments before the first header, as the pages arrive.
+///
+/// This is the streaming counterpart of `trim_ogg_vorbis_file`, only the page being parsed is
+/// held in memory.
+///
+/// # Arguments
+///
+/// * `reader` - An asynchronous reader implementing `AsyncReadExt` and `Unpin`.
+/// * `tolerate` - A boolean indicating whether to tolerate minor errors during reading.
+/// * `header_search_range` - The range in bytes to search for the header.
+///
+/// # Returns
+///
+/// A `Stream` of trimmed `OggVorbisPageResult`, or `io::Error` if an error occurs.
+pub fn trim_ogg_vorbis_stream<R: AsyncReadExt + Unpin>(
+    reader: R,
+    tolerate: bool,
+    header_search_range: usize,
+) -> impl Stream<Item = io::Result<OggVorbisPageResult>> {
+    let reader = OggVorbisReader::new(reader, tolerate, header_search_range);
+
+    stream::unfold(
+        (reader, false),
+        |(mut reader, mut header_found)| async move {
+            loop {
+                let page_result = match reader.next_page().await {
+                    Ok(Some(page_result)) => page_result,
+                    Ok(None) => return None,
+                    Err(e) => return Some((Err(e), (reader, header_found))),
+                };
+
+                match trim_ogg_vorbis_page(page_result, &mut header_found) {
+                    Ok(Some(page_result)) => {
+                        return Some((Ok(page_result), (reader, header_found)))
+                    }
+                    Ok(None) => continue,
+                    Err(e) => return Some((Err(e), (reader, header_found))),
+                }
+            }
+        },
+    )
+}
+
 /// Asynchronously trims an Ogg Vorbis file by removing segments before the first header and data after the last segment.
 ///
 /// This function reads the Ogg Vorbis file, searches for the first header (Identification, Comment, or Setup),
@@ -18,37 +121,19 @@ use tokio::io::AsyncReadExt;
 ///
 /// A result containing a vector of `OggVorbisPageResult` if successful, or an `io::Error` if an error occurs.
 pub async fn trim_ogg_vorbis_file<R: AsyncReadExt + Unpin>(
-    mut reader: R,
+    reader: R,
     tolerate: bool,
     header_search_range: usize,
 ) -> io::Result<Vec<OggVorbisPageResult>> {
     let mut result = Vec::new();
-    let mut header_found = false;
-
-    let page_results = read_ogg_vorbis_file(&mut reader, tolerate, header_search_range).await?;
-    for mut page_result in page_results {
-        if !header_found {
-            if let Some(found_header_index) = page_result.packets.iter().position(|packet| {
-                matches!(
-                    packet,
-                    OggVorbisPacket::Identification(_)
-                        | OggVorbisPacket::Comment(_)
-                        | OggVorbisPacket::Setup(_)
-                )
-            }) {
-                header_found = true;
-                if found_header_index > 0 {
-                    page_result.page = page_result.page
-                        .remove_page_segment(0, found_header_index)
-                        .map_err(|e| Error::new(ErrorKind::Other, e))?;
-                    page_result.packets = page_result.packets.split_off(found_header_index);
-                    page_result.page.update_page_checksum();
-                }
-            } else {
-                continue;
-            }
-        }
-        result.push(page_result);
+
+    let mut page_results = pin!(trim_ogg_vorbis_stream(
+        reader,
+        tolerate,
+        header_search_range
+    ));
+    while let Some(page_result) = page_results.next().await {
+        result.push(page_result?);
     }
 
     Ok(result)
@@ -69,14 +154,14 @@ pub async fn trim_ogg_vorbis_file<R: AsyncReadExt + Unpin>(
 ///
 /// A result containing a vector of `OggVorbisPageResult` if successful, or an `io::Error` if an error occurs.
 pub async fn collect_ogg_vorbis_file<R: AsyncReadExt + Unpin>(
-    mut reader: R,
+    reader: R,
     tolerate: bool,
     header_search_range: usize,
 ) -> io::Result<Vec<OggVorbisPageResult>> {
     let mut result = Vec::new();
 
-    let page_results = read_ogg_vorbis_file(&mut reader, tolerate, header_search_range).await?;
-    for page_result in page_results {
+    let mut reader = OggVorbisReader::new(reader, tolerate, header_search_range);
+    while let Some(page_result) = reader.next_page().await? {
         result.push(page_result);
     }
 
@@ -122,43 +207,674 @@ pub fn find_packet_by_type(
         })
 }
 
+/// Finds all packets of a specified type in an Ogg Vorbis file.
+///
+/// Unlike `find_packet_by_type`, this function returns every match, such as the comment header
+/// of each link in a chained file.
+///
+/// # Arguments
+///
+/// * `ogg_vorbis_file` - A slice of `OggVorbisPageResult` representing the Ogg Vorbis file.
+/// * `packet_type` - A string specifying the type of packet to find ("identification", "comment", or "setup").
+///
+/// # Returns
+///
+/// A vector of tuples of page and packet indices, in file order.
+pub fn find_packets_by_type(
+    ogg_vorbis_file: &[OggVorbisPageResult],
+    packet_type: &str,
+) -> Vec<(usize, usize)> {
+    ogg_vorbis_file
+        .iter()
+        .enumerate()
+        .flat_map(|(page_index, page)| {
+            page.packets
+                .iter()
+                .enumerate()
+                .filter(|(_, packet)| match packet {
+                    OggVorbisPacket::Identification(_) => packet_type == "identification",
+                    OggVorbisPacket::Comment(_) => packet_type == "comment",
+                    OggVorbisPacket::Setup(_) => packet_type == "setup",
+                    OggVorbisPacket::Body(_) => false,
+                })
+                .map(move |(packet_index, _)| (page_index, packet_index))
+        })
+        .collect()
+}
+
+/// Records a page whose sequence number was rewritten by `renumber_ogg_pages`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OggSequenceChange {
+    /// The index of the page in the page list.
+    pub page_index: usize,
+    /// The serial number of the logical stream of the page.
+    pub stream_serial_number: u32,
+    /// The sequence number before renumbering.
+    pub old_page_sequence_number: u32,
+    /// The sequence number after renumbering.
+    pub new_page_sequence_number: u32,
+}
+
+/// Renumbers the pages of every logical stream contiguously from zero.
+///
+/// Each serial number is counted separately, and restarts from zero on a BOS page so the links
+/// of a chained file reusing a serial number are numbered independently. The checksum of every
+/// renumbered page is recomputed.
+///
+/// # Arguments
+///
+/// * `pages` - The pages of the physical stream, in stream order.
+///
+/// # Returns
+///
+/// A vector of `OggSequenceChange`, one for each page whose sequence number changed.
+pub fn renumber_ogg_pages<'a>(
+    pages: impl IntoIterator<Item = &'a mut OggPage>,
+) -> Vec<OggSequenceChange> {
+    let mut next_sequence_numbers: HashMap<u32, u32> = HashMap::new();
+    let mut changes = Vec::new();
+
+    for (page_index, page) in pages.into_iter().enumerate() {
+        let stream_serial_number = page.stream_serial_number;
+        if page.is_bos {
+            next_sequence_numbers.remove(&stream_serial_number);
+        }
+
+        let next_sequence_number = next_sequence_numbers
+            .entry(stream_serial_number)
+            .or_insert(0);
+        let new_page_sequence_number = *next_sequence_number;
+        *next_sequence_number = next_sequence_number.wrapping_add(1);
+
+        if page.page_sequence_number != new_page_sequence_number {
+            changes.push(OggSequenceChange {
+                page_index,
+                stream_serial_number,
+                old_page_sequence_number: page.page_sequence_number,
+                new_page_sequence_number,
+            });
+            page.set_page_sequence_number(new_page_sequence_number);
+        }
+    }
+
+    changes
+}
+
+/// Renumbers the pages of an Ogg Vorbis file contiguously from zero, as `renumber_ogg_pages` does.
+///
+/// This is meant to run after pages were removed or inserted, such as by `trim_ogg_vorbis_file`.
+///
+/// # Arguments
+///
+/// * `ogg_vorbis_file` - A mutable slice of `OggVorbisPageResult` representing the Ogg Vorbis file.
+///
+/// # Returns
+///
+/// A vector of `OggSequenceChange`, one for each page whose sequence number changed.
+pub fn renumber_ogg_vorbis_file(
+    ogg_vorbis_file: &mut [OggVorbisPageResult],
+) -> Vec<OggSequenceChange> {
+    renumber_ogg_pages(
+        ogg_vorbis_file
+            .iter_mut()
+            .map(|page_result| &mut page_result.page.ogg_page),
+    )
+}
+
+/// Extracts one logical stream from a multiplexed Ogg file into a standalone stream.
+///
+/// The pages of the stream are copied from its first page up to its first EOS page, so only the
+/// first link is kept if a chained file reuses the serial number. The first page is flagged as
+/// BOS, the last page as EOS, and the pages are renumbered contiguously from zero.
+///
+/// # Arguments
+///
+/// * `pages` - The pages of the physical stream, in stream order.
+/// * `stream_serial_number` - The serial number of the logical stream to extract.
+///
+/// # Returns
+///
+/// A result containing the pages of the stream, or an `OggFormatError` if no page has the serial number.
+pub fn extract_ogg_stream(
+    pages: &[OggPage],
+    stream_serial_number: u32,
+) -> Result<Vec<OggPage>, OggFormatError> {
+    let mut stream_pages: Vec<OggPage> = Vec::new();
+    for page in pages
+        .iter()
+        .filter(|page| page.stream_serial_number == stream_serial_number)
+    {
+        if !stream_pages.is_empty() && page.is_bos {
+            break;
+        }
+        stream_pages.push(page.clone());
+        if page.is_boe {
+            break;
+        }
+    }
+
+    if stream_pages.is_empty() {
+        return Err(OggFormatError(format!(
+            "No page has the serial number {}",
+            stream_serial_number
+        )));
+    }
+
+    let last_page_index = stream_pages.len() - 1;
+    for (page_index, page) in stream_pages.iter_mut().enumerate() {
+        let is_bos = page_index == 0;
+        let is_eos = page_index == last_page_index;
+        if page.is_bos != is_bos {
+            page.set_is_bos(is_bos);
+        }
+        if page.is_boe != is_eos {
+            page.set_is_boe(is_eos);
+        }
+    }
+    renumber_ogg_pages(&mut stream_pages);
+
+    Ok(stream_pages)
+}
+
+/// Extracts the first Vorbis stream from a multiplexed Ogg file, as `extract_ogg_stream` does.
+///
+/// # Arguments
+///
+/// * `pages` - The pages of the physical stream, in stream order.
+///
+/// # Returns
+///
+/// A result containing the pages of the Vorbis stream, or an `OggFormatError` if the file holds no Vorbis stream.
+pub fn extract_ogg_vorbis_stream(pages: &[OggPage]) -> Result<Vec<OggPage>, OggFormatError> {
+    let stream_serial_number = demux_ogg_pages(pages)
+        .iter()
+        .find(|stream| stream.codec == OggCodec::Vorbis)
+        .map(|stream| stream.stream_serial_number)
+        .ok_or_else(|| OggFormatError("The file holds no Vorbis stream".to_string()))?;
+
+    extract_ogg_stream(pages, stream_serial_number)
+}
+
+/// Updates the comments of one link in a chained Ogg Vorbis file.
+///
+/// # Arguments
+///
+/// * `ogg_vorbis_file` - A vector of `OggVorbisPageResult` representing the Ogg Vorbis file.
+/// * `link` - The link whose comment header should be replaced, as found by `find_ogg_vorbis_links`.
+/// * `new_comments` - A `HashMap` containing the new comments to replace the existing ones.
+/// * `header_search_range` - The range within which to search for headers, as used to parse the
+///   file.
+///
+/// # Returns
+///
+/// A result containing a vector of `OggVorbisPageResult` representing the updated Ogg Vorbis file,
+/// unchanged if the link has no comment header, or a `VorbisFormatError` as
+/// `update_ogg_vorbis_comments` returns.
+pub fn update_ogg_vorbis_link_comments(
+    ogg_vorbis_file: Vec<OggVorbisPageResult>,
+    link: &OggVorbisLink,
+    new_comments: HashMap<String, Vec<String>>,
+    header_search_range: usize,
+) -> Result<Vec<OggVorbisPageResult>, VorbisFormatError> {
+    match link.comment {
+        Some((comments_page_index, comments_index)) => update_ogg_vorbis_comments(
+            ogg_vorbis_file,
+            comments_page_index,
+            comments_index,
+            new_comments,
+            header_search_range,
+        ),
+        None => Ok(ogg_vorbis_file),
+    }
+}
+
 /// Updates the comments in an Ogg Vorbis file.
 ///
 /// This function replaces the comment packet at the specified position with new comments provided
-/// as a `HashMap`. The updated Ogg Vorbis file is returned as a vector of `OggVorbisPageResult`.
+/// as a `HashMap`. The comment packet may span several pages, such as when it embeds cover art:
+/// every page from the one it starts on to the one it ends on is rebuilt, keeping the packets
+/// sharing these pages, and the following pages of the stream are renumbered. The updated Ogg
+/// Vorbis file is parsed again and returned as a vector of `OggVorbisPageResult`.
 ///
 /// # Arguments
 ///
 /// * `ogg_vorbis_file` - A vector of `OggVorbisPageResult` representing the Ogg Vorbis file.
-/// * `comments_page_index` - The index of the page containing the comment packet to update.
+/// * `comments_page_index` - The index of the page on which the comment packet ends.
 /// * `comments_index` - The index of the comment packet within the specified page.
 /// * `new_comments` - A `HashMap` containing the new comments to replace the existing ones.
+/// * `header_search_range` - The range within which to search for headers when the updated file
+///   is parsed again, as used to parse `ogg_vorbis_file`.
 ///
 /// # Returns
 ///
-/// A vector of `OggVorbisPageResult` representing the updated Ogg Vorbis file.
+/// A result containing a vector of `OggVorbisPageResult` representing the updated Ogg Vorbis file,
+/// or a `VorbisFormatError` if the position does not hold a comment packet or the pages cannot be
+/// rebuilt.
 pub fn update_ogg_vorbis_comments(
     mut ogg_vorbis_file: Vec<OggVorbisPageResult>,
     comments_page_index: usize,
     comments_index: usize,
-    new_comments: std::collections::HashMap<String, Vec<String>>,
-) -> Vec<OggVorbisPageResult> {
-    if comments_page_index != usize::MAX {
-        let comments_page = &mut ogg_vorbis_file[comments_page_index];
-        if let OggVorbisPacket::Comment(ref mut comment_packet) =
-            comments_page.packets[comments_index]
-        {
-            let new_comment_header = IVorbisCommentHeader {
-                vendor: comment_packet.data.vendor.clone(),
-                comments: new_comments,
-            };
-            let new_comment_segment = OggVorbisPage::build_comments(new_comment_header);
-            comments_page.page = comments_page
-                .page
-                .replace_page_segment(new_comment_segment, comments_index)
-                .expect("Failed to replace page segment");
+    new_comments: HashMap<String, Vec<String>>,
+    header_search_range: usize,
+) -> Result<Vec<OggVorbisPageResult>, VorbisFormatError> {
+    if comments_page_index == usize::MAX {
+        return Ok(ogg_vorbis_file);
+    }
+
+    let vendor = match ogg_vorbis_file
+        .get(comments_page_index)
+        .and_then(|page_result| page_result.packets.get(comments_index))
+    {
+        Some(OggVorbisPacket::Comment(comment_packet)) => comment_packet.data.vendor.clone(),
+        _ => {
+            return Err(VorbisFormatError(format!(
+                "No comment packet at page {} index {}",
+                comments_page_index, comments_index
+            )))
         }
+    };
+    let new_comment_packet = OggVorbisPage::build_comments(IVorbisCommentHeader {
+        vendor,
+        comments: new_comments,
+    });
+
+    let end_page = &ogg_vorbis_file[comments_page_index].page.ogg_page;
+    let stream_serial_number = end_page.stream_serial_number;
+    let stream_page_indices: Vec<usize> = (0..=comments_page_index)
+        .filter(|&index| ogg_vorbis_file[index].page.stream_serial_number == stream_serial_number)
+        .collect();
+
+    // Walk back to the page the comment packet starts on
+    let mut span_start = stream_page_indices.len() - 1;
+    let mut start_segment_index = comments_index;
+    while start_segment_index == 0
+        && !ogg_vorbis_file[stream_page_indices[span_start]]
+            .page
+            .is_fresh_packet
+    {
+        span_start = span_start.checked_sub(1).ok_or_else(|| {
+            VorbisFormatError("The start of the comment packet is missing".to_string())
+        })?;
+        start_segment_index = ogg_vorbis_file[stream_page_indices[span_start]]
+            .page
+            .parsed_segment_table
+            .len()
+            - 1;
     }
+    let span_page_indices = &stream_page_indices[span_start..];
+    let start_page = &ogg_vorbis_file[span_page_indices[0]].page.ogg_page;
 
-    ogg_vorbis_file
+    // Keep the lacing values and bytes of the packets sharing the first and the last page
+    let (prefix_segment_table, prefix_body) =
+        split_ogg_lace_groups(start_page, start_segment_index);
+    let (suffix_segment_table, suffix_body) = split_ogg_lace_groups(end_page, comments_index + 1);
+    let mut segment_table = start_page.segment_table[..prefix_segment_table].to_vec();
+    let mut body = start_page.buffer[27 + start_page.segment_table.len()..][..prefix_body].to_vec();
+    segment_table.extend(OggPage::create_laced_vec(&[new_comment_packet.len()]));
+    body.extend_from_slice(&new_comment_packet);
+    segment_table.extend_from_slice(&end_page.segment_table[suffix_segment_table..]);
+    body.extend_from_slice(&end_page.buffer[27 + end_page.segment_table.len() + suffix_body..]);
+
+    let new_pages = build_ogg_header_pages(start_page, end_page, &segment_table, &body)?;
+    let sequence_shift = new_pages.len().wrapping_sub(span_page_indices.len()) as u32;
+
+    // Shift the sequence numbers of the following pages of the same link
+    for page_result in ogg_vorbis_file[comments_page_index + 1..].iter_mut() {
+        let page = &mut page_result.page.ogg_page;
+        if page.stream_serial_number != stream_serial_number {
+            continue;
+        }
+        if page.is_bos {
+            break;
+        }
+        page.set_page_sequence_number(page.page_sequence_number.wrapping_add(sequence_shift));
+    }
+
+    let first_span_page_index = span_page_indices[0];
+    let mut pages = Vec::with_capacity(ogg_vorbis_file.len() + new_pages.len());
+    let mut new_pages = Some(new_pages);
+    for (index, page_result) in ogg_vorbis_file.into_iter().enumerate() {
+        if index == first_span_page_index {
+            pages.extend(new_pages.take().unwrap_or_default());
+        } else if !span_page_indices.contains(&index) {
+            pages.push(page_result.page.ogg_page);
+        }
+    }
+
+    // The page indices and byte offsets of every following packet moved, so parse the file again
+    let mut parser = OggVorbisPacketParser::new(header_search_range);
+    pages
+        .into_iter()
+        .map(|page| parser.parse_page(page))
+        .collect()
+}
+
+/// Counts the lacing values and body bytes of the lace groups of a page before a given group.
+fn split_ogg_lace_groups(page: &OggPage, segment_index: usize) -> (usize, usize) {
+    let body_length: usize = page.parsed_segment_table[..segment_index].iter().sum();
+    let lace_groups = page.lace_groups();
+    let segment_table_length = lace_groups[..segment_index]
+        .iter()
+        .map(|lace_group| lace_group.length / 255 + 1)
+        .sum();
+
+    (segment_table_length, body_length)
+}
+
+/// Splits the lacing values and body of rewritten header packets into pages.
+///
+/// The pages replace the pages from `start_page` to `end_page`: the first one takes over the
+/// flags, serial number and sequence number of `start_page`, and the last one the granule
+/// position and EOS flag of `end_page`. Pages before it on which a header packet ends carry a
+/// granule position of 0, as header pages do.
+fn build_ogg_header_pages(
+    start_page: &OggPage,
+    end_page: &OggPage,
+    segment_table: &[u8],
+    body: &[u8],
+) -> Result<Vec<OggPage>, VorbisFormatError> {
+    let map_error = |e: OggFormatError| VorbisFormatError(e.to_string());
+    let chunks: Vec<&[u8]> = segment_table.chunks(255).collect();
+    let mut pages = Vec::with_capacity(chunks.len());
+    let mut body_position = 0;
+    let mut is_continued = !start_page.is_fresh_packet;
+
+    for (chunk_index, chunk) in chunks.iter().enumerate() {
+        let is_last = chunk_index + 1 == chunks.len();
+        let chunk_body_length: usize = chunk.iter().map(|&value| value as usize).sum();
+
+        let mut header_type = if is_continued { 0x1 } else { 0 };
+        if chunk_index == 0 && start_page.is_bos {
+            header_type |= 0x2;
+        }
+        if is_last && end_page.is_boe {
+            header_type |= 0x4;
+        }
+
+        let absolute_granule_position = if is_last {
+            end_page.absolute_granule_position
+        } else if chunk.iter().any(|&value| value < 255) {
+            0
+        } else {
+            u64::MAX
+        };
+
+        pages.push(
+            OggPage::build(
+                header_type,
+                absolute_granule_position,
+                start_page.stream_serial_number,
+                start_page
+                    .page_sequence_number
+                    .wrapping_add(chunk_index as u32),
+                chunk,
+                &body[body_position..body_position + chunk_body_length],
+            )
+            .map_err(map_error)?,
+        );
+
+        body_position += chunk_body_length;
+        is_continued = chunk.last() == Some(&255);
+    }
+
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ogg_stream_writer::OggStreamWriter;
+    use crate::ogg_validator::validate_ogg_data;
+    use crate::test_util;
+
+    /// A mono 44.1 kHz identification header with block sizes of 256 and 2048.
+    fn identification_packet() -> Vec<u8> {
+        vec![
+            0x01, 0x76, 0x6f, 0x72, 0x62, 0x69, 0x73, 0x00, 0x00, 0x00, 0x00, 0x01, 0x44, 0xac,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf4, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xb8, 0x01,
+        ]
+    }
+
+    fn comment_packet(value_length: usize) -> Vec<u8> {
+        let mut comments = HashMap::new();
+        comments.insert("COVER".to_string(), vec!["x".repeat(value_length)]);
+        OggVorbisPage::build_comments(IVorbisCommentHeader {
+            vendor: "test".to_string(),
+            comments,
+        })
+    }
+
+    /// The smallest mono setup header: one codebook, one floor 1, one residue, one mapping and
+    /// two modes.
+    fn setup_packet() -> Vec<u8> {
+        vec![
+            0x05, 0x76, 0x6f, 0x72, 0x62, 0x69, 0x73, 0x00, 0x42, 0x43, 0x56, 0x01, 0x00, 0x02,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x10, 0x00, 0x00, 0x40, 0x05,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x90, 0x01, 0x00, 0x7c, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00,
+            0x00, 0x00, 0x40,
+        ]
+    }
+
+    /// Writes a Vorbis stream whose comment header shares its last page with the setup header.
+    fn vorbis_stream(comment_value_length: usize) -> Vec<OggPage> {
+        let mut writer = OggStreamWriter::new(1);
+        let mut pages = writer
+            .write_packet(&identification_packet(), 0, false)
+            .unwrap();
+        pages.extend(writer.flush().unwrap());
+        pages.extend(
+            writer
+                .write_packet(&comment_packet(comment_value_length), 0, false)
+                .unwrap(),
+        );
+        pages.extend(writer.write_packet(&setup_packet(), 0, false).unwrap());
+        pages.extend(writer.flush().unwrap());
+        pages.extend(writer.write_packet(&[0; 300], 128, true).unwrap());
+        pages
+    }
+
+    fn parse_pages(pages: Vec<OggPage>) -> Vec<OggVorbisPageResult> {
+        let mut parser = OggVorbisPacketParser::new(usize::MAX);
+        pages
+            .into_iter()
+            .map(|page| parser.parse_page(page).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn update_comments_rebuilds_every_page_of_the_packet() {
+        for (old_length, new_length) in [(10000, 10), (10, 10000), (10000, 70000)] {
+            let file = parse_pages(vorbis_stream(old_length));
+            let (comments_page_index, comments_index) =
+                find_packet_by_type(&file, "comment").unwrap();
+
+            let mut new_comments = HashMap::new();
+            new_comments.insert("COVER".to_string(), vec!["y".repeat(new_length)]);
+            let updated = update_ogg_vorbis_comments(
+                file,
+                comments_page_index,
+                comments_index,
+                new_comments,
+                usize::MAX,
+            )
+            .unwrap();
+
+            let data: Vec<u8> = updated
+                .iter()
+                .flat_map(|page_result| page_result.page.buffer.clone())
+                .collect();
+            assert!(validate_ogg_data(&data).is_valid());
+
+            let expected = parse_pages(updated.iter().map(|r| r.page.ogg_page.clone()).collect());
+            assert_eq!(updated.len(), expected.len());
+            for (page_result, expected_result) in updated.iter().zip(&expected) {
+                assert_eq!(page_result.page.buffer, expected_result.page.buffer);
+                assert_eq!(page_result.packets.len(), expected_result.packets.len());
+                let byte_ranges = |result: &OggVorbisPageResult| {
+                    result
+                        .completed_packets
+                        .iter()
+                        .map(|packet| (packet.first_page_index, packet.byte_range.clone()))
+                        .collect::<Vec<_>>()
+                };
+                assert_eq!(byte_ranges(page_result), byte_ranges(expected_result));
+            }
+
+            let (comments_page_index, comments_index) =
+                find_packet_by_type(&updated, "comment").unwrap();
+            match &updated[comments_page_index].packets[comments_index] {
+                OggVorbisPacket::Comment(comment) => {
+                    assert_eq!(comment.data.comments["COVER"][0], "y".repeat(new_length))
+                }
+                _ => panic!("the comment header was not rewritten"),
+            }
+            assert!(find_packet_by_type(&updated, "setup").is_some());
+        }
+    }
+
+    #[test]
+    fn trim_page_renumbers_the_packets_left() {
+        let mut writer = OggStreamWriter::new(1);
+        let mut pages = writer.write_packet(&[0; 10], 0, false).unwrap();
+        pages.extend(writer.write_packet(&[0; 300], 0, false).unwrap());
+        pages.extend(
+            writer
+                .write_packet(&identification_packet(), 0, false)
+                .unwrap(),
+        );
+        pages.extend(writer.flush().unwrap());
+        assert_eq!(pages.len(), 1);
+
+        let page_result = parse_pages(pages).remove(0);
+        let mut header_found = false;
+        let trimmed = trim_ogg_vorbis_page(page_result, &mut header_found)
+            .unwrap()
+            .unwrap();
+        assert!(header_found);
+        assert_eq!(trimmed.page.parsed_segment_table, [30]);
+
+        let packet = &trimmed.completed_packets[0];
+        assert_eq!(trimmed.completed_packets.len(), 1);
+        assert_eq!(packet.data, identification_packet());
+        assert_eq!(
+            (packet.first_segment_index, packet.last_segment_index),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn trim_page_clears_the_continued_flag() {
+        let mut writer = OggStreamWriter::new(1);
+        writer.max_page_body_size = 100;
+        let mut pages = writer.write_packet(&[0; 300], 0, false).unwrap();
+        pages.extend(
+            writer
+                .write_packet(&identification_packet(), 0, false)
+                .unwrap(),
+        );
+        pages.extend(writer.flush().unwrap());
+        assert_eq!(pages.len(), 2);
+        assert!(!pages[1].is_fresh_packet);
+
+        let mut header_found = false;
+        let mut trimmed = Vec::new();
+        for page_result in parse_pages(pages) {
+            trimmed.extend(trim_ogg_vorbis_page(page_result, &mut header_found).unwrap());
+        }
+        assert_eq!(trimmed.len(), 1);
+
+        let page = OggPage::new(trimmed[0].page.buffer.clone()).unwrap();
+        assert!(page.is_fresh_packet);
+        assert_eq!(page.header_type, 0);
+        assert!(page.if_page_checksum_correct());
+        assert_eq!(page.get_page_segment(0).unwrap(), identification_packet());
+    }
+
+    #[test]
+    fn update_comments_rejects_a_missing_comment_packet() {
+        let file = parse_pages(vorbis_stream(10));
+        assert!(update_ogg_vorbis_comments(file, 0, 0, HashMap::new(), usize::MAX).is_err());
+    }
+
+    #[test]
+    fn extract_keeps_only_the_first_link_of_the_chosen_serial() {
+        let chained: Vec<OggPage> = test_util::vorbis_stream(7, "first", 10, 0)
+            .into_iter()
+            .chain(test_util::vorbis_stream(7, "second", 6, 0))
+            .collect();
+        let mut other_writer = OggStreamWriter::new(3);
+        let mut pages = Vec::new();
+        for (index, page) in chained.iter().enumerate() {
+            let is_last = index + 1 == chained.len();
+            let written = other_writer.write_packet(b"\x80theora", 0, is_last);
+            pages.extend(written.unwrap());
+            pages.extend(other_writer.flush().unwrap());
+            pages.push(page.clone());
+        }
+
+        let first_link_length = test_util::vorbis_stream(7, "first", 10, 0).len();
+        for extracted in [
+            extract_ogg_stream(&pages, 7).unwrap(),
+            extract_ogg_vorbis_stream(&pages).unwrap(),
+        ] {
+            assert_eq!(extracted.len(), first_link_length);
+            assert!(extracted.iter().all(|page| page.stream_serial_number == 7));
+            for (page, expected) in extracted.iter().zip(&chained) {
+                assert_eq!(page.buffer, expected.buffer);
+            }
+        }
+
+        assert!(extract_ogg_stream(&pages, 8).is_err());
+    }
+
+    #[test]
+    fn renumber_restarts_each_serial_on_bos() {
+        let first_link = test_util::vorbis_stream(7, "first", 10, 0);
+        let second_link = test_util::vorbis_stream(7, "second", 6, 0);
+        let mut other_writer = OggStreamWriter::new(3);
+        let mut pages = Vec::new();
+        for (index, mut page) in first_link.into_iter().chain(second_link).enumerate() {
+            // Pages of the other stream are already numbered, the Vorbis pages are not
+            pages.extend(other_writer.write_packet(b"\x80theora", 0, false).unwrap());
+            pages.extend(other_writer.flush().unwrap());
+            page.set_page_sequence_number(100 + index as u32);
+            pages.push(page);
+        }
+        let original = pages.clone();
+
+        let changes = renumber_ogg_pages(&mut pages);
+
+        let mut expected_changes = Vec::new();
+        let mut next_sequence_number = 0;
+        for (page_index, page) in original.iter().enumerate() {
+            if page.stream_serial_number != 7 {
+                continue;
+            }
+            if page.is_bos {
+                next_sequence_number = 0;
+            }
+            expected_changes.push(OggSequenceChange {
+                page_index,
+                stream_serial_number: 7,
+                old_page_sequence_number: page.page_sequence_number,
+                new_page_sequence_number: next_sequence_number,
+            });
+            next_sequence_number += 1;
+        }
+        assert_eq!(changes, expected_changes);
+
+        for (page, original) in pages.iter().zip(&original) {
+            assert!(page.if_page_checksum_correct());
+            if page.stream_serial_number == 3 {
+                assert_eq!(page.buffer, original.buffer);
+            }
+        }
+        let parsed = OggPage::new(pages[changes[2].page_index].buffer.clone()).unwrap();
+        assert_eq!(parsed.page_sequence_number, 2);
+
+        // Renumbering again changes nothing
+        assert!(renumber_ogg_pages(&mut pages).is_empty());
+    }
 }